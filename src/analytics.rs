@@ -0,0 +1,367 @@
+use uuid::Uuid;
+
+use crate::events::BookEvent;
+use crate::order_book::{OrderType, Trade};
+
+// How close together (in seconds) a buy and a sell between the same two accounts have to be
+// to count as a round trip
+pub const WASH_ROUND_TRIP_WINDOW_SECS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WashAlert {
+    // Both sides of the trade belong to the same account
+    SelfTrade {
+        trade_idx: usize,
+        owner: Uuid,
+    },
+    // `owner` traded with `counterparty` and then traded back the other way shortly after
+    RoundTrip {
+        first_trade_idx: usize,
+        second_trade_idx: usize,
+        owner: Uuid,
+        counterparty: Uuid,
+    },
+}
+
+// Buys are positive, sells are negative
+fn side_sign(side: OrderType) -> f64 {
+    match side {
+        OrderType::Bid => 1.0,
+        OrderType::Ask => -1.0,
+    }
+}
+
+// Transaction cost metric: twice the signed distance between the execution price and the mid
+// at the time of the trade. Positive means the aggressor paid to cross the spread.
+pub fn effective_spread(trade_price: f64, mid_at_trade: f64, side: OrderType) -> f64 {
+    2.0 * side_sign(side) * (trade_price - mid_at_trade)
+}
+
+// Realized spread the makers of the trades captured against a later mark, in quote currency
+// summed over the trades. Positive when the market moved in the maker's favour after the
+// fill (the mark rose after a maker buy or fell after a maker sell), negative when the
+// maker got picked off. Filter the tape by `maker_owner` to score a single account.
+pub fn maker_spread_capture(trades: &[Trade], mark_after: impl Fn(&Trade) -> f64) -> f64 {
+    trades
+        .iter()
+        .map(|trade| {
+            let maker_side = trade.aggressor.opposite();
+            side_sign(maker_side) * (mark_after(trade) - trade.price) * trade.size
+        })
+        .sum()
+}
+
+// Share of the volume taken by trades that came back within `window_secs`, from 0 (nothing
+// refilled) to 1. A trade counts as refilled by the orders added on its maker side at the
+// trade price or better until the window closes or the next trade happens, capped at the
+// traded size. 0 when nothing traded.
+pub fn book_resilience(events: &[BookEvent], window_secs: i64) -> f64 {
+    let (mut taken, mut refilled) = (0.0, 0.0);
+    for (trade_idx, event) in events.iter().enumerate() {
+        let BookEvent::Traded(trade) = event else {
+            continue;
+        };
+        let maker_side = trade.aggressor.opposite();
+        let mut trade_refilled = 0.0;
+        for later in &events[trade_idx + 1..] {
+            match later {
+                BookEvent::Traded(_) => break,
+                BookEvent::OrderAdded {
+                    side,
+                    price,
+                    size,
+                    timestamp,
+                    ..
+                } if *side == maker_side => {
+                    if timestamp - trade.timestamp > window_secs {
+                        break;
+                    }
+                    let at_or_better = match maker_side {
+                        OrderType::Bid => *price >= trade.price,
+                        OrderType::Ask => *price <= trade.price,
+                    };
+                    if at_or_better {
+                        trade_refilled += size;
+                    }
+                }
+                _ => {}
+            }
+        }
+        taken += trade.size;
+        refilled += trade_refilled.min(trade.size);
+    }
+    if taken > 0.0 { refilled / taken } else { 0.0 }
+}
+
+// (buyer, seller) owners of a trade
+fn trade_parties(trade: &Trade) -> (Uuid, Uuid) {
+    match trade.aggressor {
+        OrderType::Bid => (trade.taker_owner, trade.maker_owner),
+        OrderType::Ask => (trade.maker_owner, trade.taker_owner),
+    }
+}
+
+// Realized and unrealized PnL of an account over the tape using average cost accounting. A
+// fill against the position realizes the difference to the average cost on the part it
+// closes, a fill that flips the position opens the remainder at the fill price.
+pub fn average_cost_pnl(trades: &[Trade], owner: Uuid, mark_price: f64) -> (f64, f64) {
+    let (mut position, mut average_cost, mut realized) = (0.0_f64, 0.0, 0.0);
+    for trade in trades {
+        let (buyer, seller) = trade_parties(trade);
+        for (party, sign) in [(buyer, 1.0), (seller, -1.0)] {
+            if party != owner {
+                continue;
+            }
+            let fill = sign * trade.size;
+            if position == 0.0 || position.signum() == fill.signum() {
+                average_cost = (average_cost * position.abs() + trade.price * fill.abs())
+                    / (position.abs() + fill.abs());
+            } else {
+                let closed = fill.abs().min(position.abs());
+                realized += closed * (trade.price - average_cost) * position.signum();
+                if fill.abs() > position.abs() {
+                    average_cost = trade.price;
+                }
+            }
+            position += fill;
+            if position.abs() < 1e-12 {
+                position = 0.0;
+                average_cost = 0.0;
+            }
+        }
+    }
+    (realized, position * (mark_price - average_cost))
+}
+
+// Collapses runs of consecutive fills at the same price and aggressor side into one print
+// per price for tape consumers. An aggregate keeps the fields of the run's first fill,
+// maker included, with the sizes summed.
+pub fn coalesce_trades(trades: &[Trade]) -> Vec<Trade> {
+    let mut coalesced: Vec<Trade> = vec![];
+    for trade in trades {
+        match coalesced.last_mut() {
+            Some(last) if last.price == trade.price && last.aggressor == trade.aggressor => {
+                last.size += trade.size;
+            }
+            _ => coalesced.push(trade.clone()),
+        }
+    }
+    coalesced
+}
+
+// Post-trade compliance audit of the tape. Trades of the anonymous (nil) owner are skipped
+// as there is no account to attribute them to.
+pub fn detect_wash_trades(trades: &[Trade]) -> Vec<WashAlert> {
+    let mut alerts = vec![];
+    for (trade_idx, trade) in trades.iter().enumerate() {
+        let (buyer, seller) = trade_parties(trade);
+        if buyer.is_nil() || seller.is_nil() {
+            continue;
+        }
+        if buyer == seller {
+            alerts.push(WashAlert::SelfTrade {
+                trade_idx,
+                owner: buyer,
+            });
+            continue;
+        }
+
+        let round_trip = trades[trade_idx + 1..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, later)| {
+                later.timestamp - trade.timestamp <= WASH_ROUND_TRIP_WINDOW_SECS
+            })
+            .find(|(_, later)| trade_parties(later) == (seller, buyer));
+        if let Some((offset, _)) = round_trip {
+            alerts.push(WashAlert::RoundTrip {
+                first_trade_idx: trade_idx,
+                second_trade_idx: trade_idx + 1 + offset,
+                owner: buyer,
+                counterparty: seller,
+            });
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::analytics::{
+        WashAlert, book_resilience, coalesce_trades, detect_wash_trades, effective_spread,
+        maker_spread_capture,
+    };
+    use crate::clock::ManualClock;
+    use crate::order_book::{Order, OrderBook, OrderType};
+
+    #[test]
+    fn successfully_computes_the_effective_spread_of_a_buy_above_the_mid() {
+        // Given
+        let (trade_price, mid_at_trade) = (101.0, 100.5);
+
+        // When
+        let buy_spread = effective_spread(trade_price, mid_at_trade, OrderType::Bid);
+        let sell_spread = effective_spread(trade_price, mid_at_trade, OrderType::Ask);
+
+        // Then
+        assert_eq!(buy_spread, 1.0);
+        assert_eq!(sell_spread, -1.0);
+    }
+
+    #[test]
+    fn successfully_flags_a_trade_between_orders_of_the_same_account() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let owner = Uuid::new_v4();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0).with_owner(owner))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0).with_owner(owner))
+            .unwrap();
+
+        // When
+        let alerts = detect_wash_trades(order_book.trades());
+
+        // Then
+        assert_eq!(
+            alerts,
+            vec![WashAlert::SelfTrade {
+                trade_idx: 0,
+                owner
+            }]
+        );
+    }
+
+    #[test]
+    fn successfully_flags_a_quick_round_trip_between_two_accounts() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let (owner, counterparty) = (Uuid::new_v4(), Uuid::new_v4());
+        order_book
+            .add_order(
+                100.0,
+                Order::new(OrderType::Ask, 1.0).with_owner(counterparty),
+            )
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0).with_owner(owner))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .add_order(
+                100.0,
+                Order::new(OrderType::Bid, 1.0).with_owner(counterparty),
+            )
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0).with_owner(owner))
+            .unwrap();
+
+        // When
+        let alerts = detect_wash_trades(order_book.trades());
+
+        // Then
+        assert_eq!(
+            alerts,
+            vec![WashAlert::RoundTrip {
+                first_trade_idx: 0,
+                second_trade_idx: 1,
+                owner,
+                counterparty
+            }]
+        );
+    }
+
+    #[test]
+    fn successfully_coalesces_same_price_fills_into_one_print() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for size in [1.0, 2.0, 3.0] {
+            order_book
+                .add_order(100.0, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 7.0))
+            .unwrap();
+
+        // When
+        let coalesced = coalesce_trades(order_book.trades());
+
+        // Then
+        assert_eq!(order_book.trades().len(), 4);
+        assert_eq!(coalesced.len(), 2);
+        assert_eq!((coalesced[0].price, coalesced[0].size), (100.0, 6.0));
+        assert_eq!(
+            coalesced[0].maker_order_id,
+            order_book.trades()[0].maker_order_id
+        );
+        assert_eq!((coalesced[1].price, coalesced[1].size), (101.0, 1.0));
+    }
+
+    #[test]
+    fn successfully_measures_positive_capture_of_a_maker_buy_marked_higher() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let maker = Uuid::new_v4();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 2.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // When
+        let capture = maker_spread_capture(order_book.trades(), |_| 99.5);
+        let picked_off = maker_spread_capture(order_book.trades(), |_| 98.0);
+
+        // Then
+        assert_eq!(capture, 1.0);
+        assert_eq!(picked_off, -2.0);
+    }
+
+    #[test]
+    fn successfully_measures_how_much_of_a_trade_got_refilled_in_the_window() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 4.0))
+            .unwrap();
+        let receiver = order_book.subscribe();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 4.0))
+            .unwrap();
+
+        // When
+        clock.advance(1);
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(100.5, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        clock.advance(1);
+        order_book
+            .add_order(99.5, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let events: Vec<_> = receiver.try_iter().collect();
+
+        // Then
+        assert_eq!(book_resilience(&events, 5), 0.75);
+        assert_eq!(book_resilience(&events[..1], 5), 0.0);
+    }
+}
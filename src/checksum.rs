@@ -0,0 +1,87 @@
+use crate::order_book::{OrderBook, OrderType};
+
+// Decimals every price and size is rounded to before it is checksummed, so float noise
+// such as 100.99000000000001 doesn't change the result
+const CHECKSUM_DECIMALS: usize = 8;
+
+// CRC-32 (IEEE 802.3, reflected), the same one zlib and most exchange feeds use
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// The whole-book checksum out of the two side checksums, for clients that keep the sides
+// apart and still want to verify against the combined one
+pub fn combine_checksums(bid_checksum: u32, ask_checksum: u32) -> u32 {
+    let mut bytes = bid_checksum.to_be_bytes().to_vec();
+    bytes.extend(ask_checksum.to_be_bytes());
+    crc32(&bytes)
+}
+
+impl OrderBook {
+    // CRC-32 of the top `levels` levels of one side as depth reports them, best price first.
+    // Each level is written as `price:size;` with both rounded to a fixed number of decimals.
+    pub fn side_checksum(&self, side: OrderType, levels: usize) -> u32 {
+        let (bids, asks) = self.depth(levels);
+        let side_levels = match side {
+            OrderType::Bid => bids,
+            OrderType::Ask => asks,
+        };
+        let canonical: String = side_levels
+            .iter()
+            .map(|(price, size)| format!("{price:.CHECKSUM_DECIMALS$}:{size:.CHECKSUM_DECIMALS$};"))
+            .collect();
+        crc32(canonical.as_bytes())
+    }
+
+    // Checksum of the top `levels` levels of both sides, see combine_checksums
+    pub fn checksum(&self, levels: usize) -> u32 {
+        combine_checksums(
+            self.side_checksum(OrderType::Bid, levels),
+            self.side_checksum(OrderType::Ask, levels),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::checksum::{combine_checksums, crc32};
+    use crate::order_book::{Order, OrderBook, OrderType};
+
+    #[test]
+    fn successfully_combines_side_checksums_into_the_book_checksum() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.5))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        let mut bids_only = OrderBook::new();
+        bids_only
+            .add_order(99.0, Order::new(OrderType::Bid, 1.5))
+            .unwrap();
+
+        // When
+        let bid_checksum = order_book.side_checksum(OrderType::Bid, 10);
+        let ask_checksum = order_book.side_checksum(OrderType::Ask, 10);
+
+        // Then
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(bid_checksum, crc32(b"99.00000000:1.50000000;"));
+        assert_eq!(bid_checksum, bids_only.side_checksum(OrderType::Bid, 10));
+        assert_eq!(
+            order_book.checksum(10),
+            combine_checksums(bid_checksum, ask_checksum)
+        );
+        assert_ne!(order_book.checksum(10), bids_only.checksum(10));
+    }
+}
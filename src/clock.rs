@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use time::OffsetDateTime;
+
+// Where the order book gets its timestamps (unix seconds) from, injected so that tests and
+// simulations can control time instead of depending on the wall clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+
+    // The same instant in unix milliseconds, for rules finer than a second
+    fn now_millis(&self) -> i64 {
+        self.now() * 1000
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp()
+    }
+
+    fn now_millis(&self) -> i64 {
+        (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+    }
+}
+
+// A clock that only moves when told to. It keeps milliseconds so sub-second rules can be
+// tested, while `now` keeps reporting whole seconds.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_millis: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        Self {
+            now_millis: AtomicI64::new(now * 1000),
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now_millis.store(now * 1000, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: i64) {
+        self.advance_millis(by * 1000);
+    }
+
+    pub fn advance_millis(&self, by: i64) {
+        self.now_millis.fetch_add(by, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now_millis().div_euclid(1000)
+    }
+
+    fn now_millis(&self) -> i64 {
+        self.now_millis.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,316 @@
+use uuid::Uuid;
+
+use crate::order_book::{Match, Order, OrderBook, OrderBookError, OrderType};
+
+// Every operation the order book supports as a plain value, so that any transport (HTTP,
+// RPC, a replay log) can be wired to the engine through a single entry point
+#[derive(Debug, Clone)]
+pub enum Command {
+    PlaceLimit {
+        price: f64,
+        order: Order,
+    },
+    PlaceMarket {
+        order: Order,
+    },
+    Cancel {
+        order_id: Uuid,
+    },
+    Amend {
+        order_id: Uuid,
+        price: f64,
+        size: f64,
+    },
+    QueryTouch,
+    QueryLevel {
+        side: OrderType,
+        price: f64,
+    },
+    QueryOpenOrders {
+        owner: Uuid,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandResult {
+    Placed(Uuid),
+    Matched(Vec<Match>),
+    Cancelled(Uuid),
+    Amended(Uuid),
+    Touch(Option<(f64, f64, f64, f64)>),
+    Level(Option<Vec<(Uuid, f64)>>),
+    OpenOrders(Vec<Uuid>),
+    Rejected(OrderBookError),
+}
+
+impl OrderBook {
+    pub fn apply(&mut self, cmd: Command) -> CommandResult {
+        let result = match cmd {
+            // An order that traded on arrival reports its matches, one that only rests its id
+            Command::PlaceLimit { price, order } => {
+                let order_id = order.id();
+                self.place_limit_order(price, order).map(|matches| {
+                    if matches.is_empty() {
+                        CommandResult::Placed(order_id)
+                    } else {
+                        CommandResult::Matched(matches)
+                    }
+                })
+            }
+            Command::PlaceMarket { order } => {
+                self.place_market_order(order).map(CommandResult::Matched)
+            }
+            Command::Cancel { order_id } => self
+                .cancel_order(order_id)
+                .map(|_| CommandResult::Cancelled(order_id)),
+            Command::Amend {
+                order_id,
+                price,
+                size,
+            } => self
+                .amend_order(order_id, price, size)
+                .map(|_| CommandResult::Amended(order_id)),
+            Command::QueryTouch => Ok(CommandResult::Touch(self.touch())),
+            Command::QueryLevel { side, price } => {
+                Ok(CommandResult::Level(self.level_queue(side, price)))
+            }
+            Command::QueryOpenOrders { owner } => {
+                Ok(CommandResult::OpenOrders(self.open_orders(owner)))
+            }
+        };
+        result.unwrap_or_else(CommandResult::Rejected)
+    }
+
+    // Applies a recorded command as if it happened at `timestamp`, so resting orders and
+    // trades get the original timestamps instead of the clock's
+    pub fn apply_at(&mut self, timestamp: i64, cmd: Command) -> CommandResult {
+        self.at_timestamp(timestamp, |order_book| order_book.apply(cmd))
+    }
+
+    // What-if run of the commands against a detached copy of the book, see detached_copy.
+    // The commands go through apply just like live ones and this book is left untouched.
+    pub fn simulate_commands(&self, cmds: &[Command]) -> OrderBook {
+        let mut simulated = self.detached_copy();
+        for cmd in cmds {
+            simulated.apply(cmd.clone());
+        }
+        simulated
+    }
+
+    // Rebuilds a session from its recorded `(timestamp, command)` log
+    pub fn replay(&mut self, log: Vec<(i64, Command)>) -> Vec<CommandResult> {
+        log.into_iter()
+            .map(|(timestamp, cmd)| self.apply_at(timestamp, cmd))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::clock::ManualClock;
+    use crate::command::{Command, CommandResult};
+    use crate::order_book::{Order, OrderBook, OrderBookError, OrderType};
+
+    #[test]
+    fn successfully_dispatches_place_and_query_commands() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let owner = Uuid::new_v4();
+        let bid = Order::new(OrderType::Bid, 2.0).with_owner(owner);
+        let ask = Order::new(OrderType::Ask, 3.0).with_owner(owner);
+        let (bid_id, ask_id) = (bid.id(), ask.id());
+
+        // When
+        let placed_bid = order_book.apply(Command::PlaceLimit {
+            price: 99.0,
+            order: bid,
+        });
+        let placed_ask = order_book.apply(Command::PlaceLimit {
+            price: 101.0,
+            order: ask,
+        });
+        let touch = order_book.apply(Command::QueryTouch);
+        let level = order_book.apply(Command::QueryLevel {
+            side: OrderType::Ask,
+            price: 101.0,
+        });
+        let open_orders = order_book.apply(Command::QueryOpenOrders { owner });
+
+        // Then
+        assert_eq!(placed_bid, CommandResult::Placed(bid_id));
+        assert_eq!(placed_ask, CommandResult::Placed(ask_id));
+        assert_eq!(touch, CommandResult::Touch(Some((99.0, 2.0, 101.0, 3.0))));
+        assert_eq!(level, CommandResult::Level(Some(vec![(ask_id, 3.0)])));
+        assert_eq!(open_orders, CommandResult::OpenOrders(vec![bid_id, ask_id]));
+    }
+
+    #[test]
+    fn successfully_dispatches_market_amend_and_cancel_commands() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ask = Order::new(OrderType::Ask, 3.0);
+        let bid = Order::new(OrderType::Bid, 2.0);
+        let (ask_id, bid_id) = (ask.id(), bid.id());
+        order_book.add_order(101.0, ask).unwrap();
+        order_book.add_order(99.0, bid).unwrap();
+
+        // When
+        let amended = order_book.apply(Command::Amend {
+            order_id: ask_id,
+            price: 102.0,
+            size: 4.0,
+        });
+        let matched = order_book.apply(Command::PlaceMarket {
+            order: Order::new(OrderType::Bid, 1.0),
+        });
+        let cancelled = order_book.apply(Command::Cancel { order_id: bid_id });
+        let rejected = order_book.apply(Command::Cancel { order_id: bid_id });
+
+        // Then
+        assert_eq!(amended, CommandResult::Amended(ask_id));
+        match matched {
+            CommandResult::Matched(matches) => {
+                assert_eq!(matches.len(), 1);
+                assert_eq!(matches[0].ask_id, ask_id);
+                assert_eq!(matches[0].price, 102.0);
+            }
+            other => panic!("Expected matches but got {other:?}"),
+        }
+        assert_eq!(cancelled, CommandResult::Cancelled(bid_id));
+        assert_eq!(
+            rejected,
+            CommandResult::Rejected(OrderBookError::OrderNotFound(bid_id))
+        );
+        assert_eq!(
+            order_book.level_queue(OrderType::Ask, 102.0),
+            Some(vec![(ask_id, 3.0)])
+        );
+    }
+
+    #[test]
+    fn successfully_replays_commands_with_their_recorded_timestamps() {
+        // Given
+        let clock = Arc::new(ManualClock::new(5_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let ask = Order::new(OrderType::Ask, 3.0);
+        let bid = Order::new(OrderType::Bid, 2.0);
+        let (ask_id, bid_id) = (ask.id(), bid.id());
+        let log = vec![
+            (
+                1_700_000_000,
+                Command::PlaceLimit {
+                    price: 101.0,
+                    order: ask,
+                },
+            ),
+            (
+                1_700_000_007,
+                Command::PlaceLimit {
+                    price: 99.0,
+                    order: bid,
+                },
+            ),
+            (
+                1_700_000_012,
+                Command::PlaceMarket {
+                    order: Order::new(OrderType::Bid, 1.0),
+                },
+            ),
+        ];
+
+        // When
+        order_book.replay(log);
+        let live = Order::new(OrderType::Bid, 1.0);
+        let live_id = live.id();
+        order_book.apply(Command::PlaceLimit {
+            price: 98.0,
+            order: live,
+        });
+
+        // Then
+        let timestamp_of = |side, order_id| {
+            order_book
+                .orders_by_owner(side, Uuid::nil())
+                .into_iter()
+                .find(|view| view.id == order_id)
+                .map(|view| view.timestamp)
+        };
+        assert_eq!(timestamp_of(OrderType::Ask, ask_id), Some(1_700_000_000));
+        assert_eq!(timestamp_of(OrderType::Bid, bid_id), Some(1_700_000_007));
+        assert_eq!(timestamp_of(OrderType::Bid, live_id), Some(5_000));
+        assert_eq!(order_book.trades()[0].timestamp, 1_700_000_012);
+    }
+
+    #[test]
+    fn successfully_simulates_commands_without_touching_the_live_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let bid = Order::new(OrderType::Bid, 1.0);
+        let bid_id = bid.id();
+        order_book.add_order(99.0, bid).unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        let events = order_book.subscribe();
+
+        // When
+        let simulated = order_book.simulate_commands(&[
+            Command::Cancel { order_id: bid_id },
+            Command::PlaceMarket {
+                order: Order::new(OrderType::Bid, 0.5),
+            },
+        ]);
+
+        // Then
+        assert_eq!(simulated.depth(10), (vec![], vec![(101.0, 1.5)]));
+        assert_eq!(simulated.trades().len(), 1);
+        assert_eq!(
+            order_book.depth(10),
+            (vec![(99.0, 1.0)], vec![(101.0, 2.0)])
+        );
+        assert!(order_book.trades().is_empty());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn successfully_matches_a_crossing_limit_command() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ask = Order::new(OrderType::Ask, 1.0);
+        let ask_id = ask.id();
+        let log = vec![
+            (
+                1_700_000_000,
+                Command::PlaceLimit {
+                    price: 101.0,
+                    order: ask,
+                },
+            ),
+            (
+                1_700_000_001,
+                Command::PlaceLimit {
+                    price: 102.0,
+                    order: Order::new(OrderType::Bid, 3.0),
+                },
+            ),
+        ];
+
+        // When
+        let results = order_book.replay(log);
+
+        // Then
+        match &results[1] {
+            CommandResult::Matched(matches) => {
+                assert_eq!(matches.len(), 1);
+                assert_eq!(matches[0].ask_id, ask_id);
+            }
+            other => panic!("Expected matches but got {other:?}"),
+        }
+        assert_eq!(order_book.depth(10), (vec![(102.0, 2.0)], vec![]));
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+}
@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Add;
+use std::str::FromStr;
+
+// Number of decimal places a Decimal keeps, enough for satoshi sized lots
+pub const DECIMAL_PLACES: u32 = 8;
+const SCALE: i64 = 10_i64.pow(DECIMAL_PLACES);
+
+// Exact fixed-point decimal with DECIMAL_PLACES places, used to take prices and sizes from
+// text (e.g. JSON) without going through a lossy f64 parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Decimal(i64);
+
+#[derive(Debug, PartialEq)]
+pub enum DecimalParseError {
+    Empty,
+    InvalidDigit(String),
+    TooManyDecimalPlaces(String),
+    OutOfRange(String),
+}
+
+impl fmt::Display for DecimalParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecimalParseError::Empty => write!(f, "Cannot parse a decimal from an empty string"),
+            DecimalParseError::InvalidDigit(value) => write!(f, "{value} is not a decimal"),
+            DecimalParseError::TooManyDecimalPlaces(value) => {
+                write!(f, "{value} has more than {DECIMAL_PLACES} decimal places")
+            }
+            DecimalParseError::OutOfRange(value) => write!(f, "{value} is out of range"),
+        }
+    }
+}
+
+impl Error for DecimalParseError {}
+
+impl Decimal {
+    // The value as a count of the smallest unit, 10^-DECIMAL_PLACES
+    pub fn units(&self) -> i64 {
+        self.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = DecimalParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(DecimalParseError::Empty);
+        }
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err(DecimalParseError::InvalidDigit(s.to_string()));
+        }
+        if fraction.len() > DECIMAL_PLACES as usize {
+            return Err(DecimalParseError::TooManyDecimalPlaces(s.to_string()));
+        }
+
+        let out_of_range = || DecimalParseError::OutOfRange(s.to_string());
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| out_of_range())?
+        };
+        let fraction: i64 = format!("{fraction:0<width$}", width = DECIMAL_PLACES as usize)
+            .parse()
+            .map_err(|_| out_of_range())?;
+        let units = whole
+            .checked_mul(SCALE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or_else(out_of_range)?;
+        Ok(Decimal(if negative { -units } else { units }))
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        Decimal(self.0 + other.0)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = SCALE as u64;
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            units / scale,
+            units % scale,
+            width = DECIMAL_PLACES as usize
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::decimal::{Decimal, DecimalParseError};
+
+    #[test]
+    fn successfully_sums_decimal_strings_exactly() {
+        // Given
+        let tenth: Decimal = "0.1".parse().unwrap();
+        let one: Decimal = "1.0".parse().unwrap();
+
+        // When
+        let sum = (0..10).fold(Decimal::default(), |sum, _| sum + tenth);
+
+        // Then
+        assert_eq!(sum, one);
+        assert_eq!(sum.to_string(), "1.00000000");
+        assert_eq!(
+            "0.1x".parse::<Decimal>(),
+            Err(DecimalParseError::InvalidDigit("0.1x".to_string()))
+        );
+        assert_eq!(
+            "0.000000001".parse::<Decimal>(),
+            Err(DecimalParseError::TooManyDecimalPlaces(
+                "0.000000001".to_string()
+            ))
+        );
+        assert_eq!("-2.5".parse::<Decimal>().unwrap().to_f64(), -2.5);
+    }
+}
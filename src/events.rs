@@ -0,0 +1,401 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::order_book::{CancelReason, Order, OrderBook, OrderType, Trade};
+
+// Every mutation of the book as it happens, in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookEvent {
+    // Also sent when an amend moves a resting order, the order's previous price and size are
+    // then replaced
+    OrderAdded {
+        order_id: Uuid,
+        side: OrderType,
+        price: f64,
+        size: f64,
+        timestamp: i64,
+    },
+    OrderReduced {
+        order_id: Uuid,
+        remaining: f64,
+    },
+    OrderCancelled {
+        order_id: Uuid,
+        reason: CancelReason,
+    },
+    // A resting order traded, carrying its running fill totals
+    OrderFilled {
+        order_id: Uuid,
+        size_filled: f64,
+        price: f64,
+        remaining: f64,
+        cumulative_filled: f64,
+        avg_fill_price: f64,
+    },
+    Traded(Trade),
+}
+
+impl BookEvent {
+    // Built from the order after the fill got applied to it
+    pub(crate) fn from_fill(order: &Order, size_filled: f64, price: f64) -> Self {
+        BookEvent::OrderFilled {
+            order_id: order.id(),
+            size_filled,
+            price,
+            remaining: order.size(),
+            cumulative_filled: order.cumulative_filled(),
+            avg_fill_price: order.avg_fill_price(),
+        }
+    }
+}
+
+// What happens to an event published while a bounded subscriber's buffer is full. Block
+// stalls the publishing thread until the subscriber catches up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    Block,
+}
+
+pub(crate) struct EventBuffer {
+    events: Mutex<VecDeque<BookEvent>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    // Signalled whenever an event is pushed or taken out
+    changed: Condvar,
+}
+
+// Receiving end of a bounded subscription
+pub struct BoundedReceiver {
+    buffer: Arc<EventBuffer>,
+}
+
+impl BoundedReceiver {
+    pub fn try_recv(&self) -> Option<BookEvent> {
+        let event = self.buffer.events.lock().unwrap().pop_front();
+        self.buffer.changed.notify_all();
+        event
+    }
+
+    // Waits for the next event, None once the book is gone and everything was received
+    pub fn recv(&self) -> Option<BookEvent> {
+        let mut events = self.buffer.events.lock().unwrap();
+        loop {
+            if let Some(event) = events.pop_front() {
+                self.buffer.changed.notify_all();
+                return Some(event);
+            }
+            if Arc::strong_count(&self.buffer) == 1 {
+                return None;
+            }
+            // Woken up regularly so a book dropped while waiting is noticed
+            events = self
+                .buffer
+                .changed
+                .wait_timeout(events, Duration::from_millis(10))
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+impl OrderBook {
+    // Each subscriber gets its own copy of every event published from now on
+    pub fn subscribe(&mut self) -> mpsc::Receiver<BookEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    // Like subscribe with at most `capacity` events held for the subscriber, so a slow one
+    // can't make the book buffer without bound. Events lost to the overflow policy are
+    // counted in dropped_events.
+    pub fn subscribe_bounded(
+        &mut self,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> BoundedReceiver {
+        let buffer = Arc::new(EventBuffer {
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            policy,
+            changed: Condvar::new(),
+        });
+        self.bounded_subscribers.push(buffer.clone());
+        BoundedReceiver { buffer }
+    }
+
+    // Events bounded subscribers never got because their buffer was full
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events
+    }
+
+    // Subscribers whose receiver was dropped are forgotten on the next event
+    pub(crate) fn publish(&mut self, event: BookEvent) {
+        if self.subscribers.is_empty() && self.bounded_subscribers.is_empty() {
+            return;
+        }
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+
+        let mut dropped = 0;
+        self.bounded_subscribers.retain(|buffer| {
+            let mut events = buffer.events.lock().unwrap();
+            while events.len() >= buffer.capacity {
+                if Arc::strong_count(buffer) == 1 {
+                    return false;
+                }
+                match buffer.policy {
+                    OverflowPolicy::DropOldest => {
+                        events.pop_front();
+                        dropped += 1;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        dropped += 1;
+                        return true;
+                    }
+                    OverflowPolicy::Block => {
+                        events = buffer
+                            .changed
+                            .wait_timeout(events, Duration::from_millis(10))
+                            .unwrap()
+                            .0;
+                    }
+                }
+            }
+            events.push_back(event.clone());
+            buffer.changed.notify_all();
+            Arc::strong_count(buffer) > 1
+        });
+        self.dropped_events += dropped;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::clock::ManualClock;
+    use crate::events::{BookEvent, BoundedReceiver, OverflowPolicy};
+    use crate::order_book::{
+        CancelReason, ExecInstructions, ExecutionPrice, Order, OrderBook, OrderType,
+    };
+
+    #[test]
+    fn successfully_streams_book_events_to_every_subscriber() {
+        // Given
+        let mut order_book = OrderBook::new().with_clock(Arc::new(ManualClock::new(1_000)));
+        let receiver = order_book.subscribe();
+        let dropped_receiver = order_book.subscribe();
+        drop(dropped_receiver);
+        let other_receiver = order_book.subscribe();
+        let ask = Order::new(OrderType::Ask, 3.0);
+        let bid = Order::new(OrderType::Bid, 1.0);
+        let (ask_id, bid_id) = (ask.id(), bid.id());
+
+        // When
+        order_book.add_order(101.0, ask).unwrap();
+        order_book.reduce_order(ask_id, 1.0).unwrap();
+        order_book.place_market_order(bid).unwrap();
+        order_book.cancel_order(ask_id).unwrap();
+
+        // Then
+        let events: Vec<BookEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[0],
+            BookEvent::OrderAdded {
+                order_id: ask_id,
+                side: OrderType::Ask,
+                price: 101.0,
+                size: 3.0,
+                timestamp: 1_000
+            }
+        );
+        assert_eq!(
+            events[1],
+            BookEvent::OrderReduced {
+                order_id: ask_id,
+                remaining: 2.0
+            }
+        );
+        assert_eq!(
+            events[2],
+            BookEvent::OrderFilled {
+                order_id: ask_id,
+                size_filled: 1.0,
+                price: 101.0,
+                remaining: 1.0,
+                cumulative_filled: 1.0,
+                avg_fill_price: 101.0
+            }
+        );
+        match &events[3] {
+            BookEvent::Traded(trade) => {
+                assert_eq!(trade.maker_order_id, ask_id);
+                assert_eq!(trade.taker_order_id, bid_id);
+                assert_eq!(trade.size, 1.0);
+            }
+            other => panic!("Expected a trade but got {other:?}"),
+        }
+        assert_eq!(
+            events[4],
+            BookEvent::OrderCancelled {
+                order_id: ask_id,
+                reason: CancelReason::User
+            }
+        );
+        assert_eq!(other_receiver.try_iter().collect::<Vec<_>>(), events);
+    }
+
+    #[test]
+    fn successfully_reports_cumulative_fills_of_a_partially_filled_order() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ask = Order::new(OrderType::Ask, 10.0);
+        let ask_id = ask.id();
+        order_book.add_order(100.0, ask).unwrap();
+        let receiver = order_book.subscribe();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book.amend_order(ask_id, 103.0, 8.0).unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 6.0))
+            .unwrap();
+        let order = order_book.get_order(ask_id).unwrap();
+        let (cumulative_filled, avg_fill_price) =
+            (order.cumulative_filled(), order.avg_fill_price());
+        let taker_buy = Order::new(OrderType::Bid, 2.0).with_execution_price(ExecutionPrice::Taker);
+        order_book.place_limit_order(104.0, taker_buy).unwrap();
+
+        // Then
+        assert_eq!(cumulative_filled, 8.0);
+        assert_eq!(avg_fill_price, (2.0 * 100.0 + 6.0 * 103.0) / 8.0);
+        let fills: Vec<(f64, f64)> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                BookEvent::OrderFilled {
+                    cumulative_filled,
+                    avg_fill_price,
+                    ..
+                } => Some((cumulative_filled, avg_fill_price)),
+                _ => None,
+            })
+            .collect();
+        // The taker priced trade counts at its own price, not the resting level's
+        let taker_avg = (8.0 * 102.25 + 2.0 * 104.0) / 10.0;
+        assert_eq!(fills, vec![(2.0, 100.0), (8.0, 102.25), (10.0, taker_avg)]);
+    }
+
+    #[test]
+    fn successfully_applies_the_overflow_policy_of_a_full_subscriber_buffer() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let drop_oldest = order_book.subscribe_bounded(2, OverflowPolicy::DropOldest);
+        let drop_newest = order_book.subscribe_bounded(2, OverflowPolicy::DropNewest);
+        let prices = [99.0, 98.0, 97.0];
+
+        // When
+        for price in prices {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, 1.0))
+                .unwrap();
+        }
+
+        // Then
+        let received_prices = |receiver: &BoundedReceiver| {
+            std::iter::from_fn(|| receiver.try_recv())
+                .map(|event| match event {
+                    BookEvent::OrderAdded { price, .. } => price,
+                    event => panic!("Unexpected event {event:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(received_prices(&drop_oldest), vec![98.0, 97.0]);
+        assert_eq!(received_prices(&drop_newest), vec![99.0, 98.0]);
+        assert_eq!(order_book.dropped_events(), 2);
+    }
+
+    #[test]
+    fn successfully_blocks_the_publisher_until_the_subscriber_catches_up() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let receiver = order_book.subscribe_bounded(1, OverflowPolicy::Block);
+
+        // When
+        let publisher = thread::spawn(move || {
+            for price in [99.0, 98.0, 97.0] {
+                order_book
+                    .add_order(price, Order::new(OrderType::Bid, 1.0))
+                    .unwrap();
+            }
+            order_book
+        });
+        let received: Vec<Option<BookEvent>> = (0..3).map(|_| receiver.recv()).collect();
+        let order_book = publisher.join().unwrap();
+
+        // Then
+        assert!(received.iter().all(Option::is_some));
+        assert_eq!(receiver.try_recv(), None);
+        assert_eq!(order_book.dropped_events(), 0);
+    }
+
+    #[test]
+    fn successfully_reports_the_discarded_remainder_of_an_ioc_order() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let events = order_book.subscribe();
+        let ioc = Order::new(OrderType::Bid, 3.0)
+            .with_exec_instructions(ExecInstructions::IMMEDIATE_OR_CANCEL);
+        let ioc_id = ioc.id();
+        let protected = Order::new(OrderType::Bid, 2.0);
+        let protected_id = protected.id();
+
+        // When
+        order_book.place_limit_order(101.0, ioc).unwrap();
+        let received: Vec<BookEvent> = events.try_iter().collect();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(110.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .place_protected_market_order(protected, 1.0)
+            .unwrap();
+
+        // Then
+        assert!(matches!(received[0], BookEvent::OrderFilled { .. }));
+        assert!(matches!(received[1], BookEvent::Traded(_)));
+        assert_eq!(
+            received.last(),
+            Some(&BookEvent::OrderCancelled {
+                order_id: ioc_id,
+                reason: CancelReason::IocRemainder
+            })
+        );
+        assert!(order_book.depth(10).0.is_empty());
+        assert_eq!(
+            order_book.cancel_reason(ioc_id),
+            Some(CancelReason::IocRemainder)
+        );
+        assert_eq!(
+            events.try_iter().last(),
+            Some(BookEvent::OrderCancelled {
+                order_id: protected_id,
+                reason: CancelReason::IocRemainder
+            })
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::analytics::average_cost_pnl;
+use crate::fees::FeeSchedule;
+use crate::order_book::{
+    Match, Order, OrderBook, OrderBookConfig, OrderBookError, OrderType, has_volume,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum ExchangeError {
+    MarketNotFound(String),
+    MarketAlreadyExists(String),
+    OrderBook(OrderBookError),
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeError::MarketNotFound(symbol) => write!(f, "Could not find market {symbol}"),
+            ExchangeError::MarketAlreadyExists(symbol) => {
+                write!(f, "Market {symbol} already exists")
+            }
+            ExchangeError::OrderBook(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ExchangeError {}
+
+impl From<OrderBookError> for ExchangeError {
+    fn from(err: OrderBookError) -> Self {
+        ExchangeError::OrderBook(err)
+    }
+}
+
+// A routed order that failed part way, along with the fills that already happened on other
+// venues before it did
+#[derive(Debug, PartialEq)]
+pub struct RouteError {
+    pub routed: Vec<(String, Match)>,
+    pub error: ExchangeError,
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Routing failed after {} fills: {}",
+            self.routed.len(),
+            self.error
+        )
+    }
+}
+
+impl Error for RouteError {}
+
+// Each market has its own precision and fees, a low priced altcoin needs a much finer
+// tick than BTC-USD
+#[derive(Debug, Clone)]
+pub struct MarketConfig {
+    pub tick_size: f64,
+    pub lot_size: f64,
+    pub fee_schedule: FeeSchedule,
+}
+
+impl From<MarketConfig> for OrderBookConfig {
+    fn from(config: MarketConfig) -> Self {
+        OrderBookConfig {
+            tick_size: config.tick_size,
+            min_price: config.tick_size,
+            lot_size: config.lot_size,
+            fee_schedule: config.fee_schedule,
+            ..OrderBookConfig::default()
+        }
+    }
+}
+
+// Best price of one side across every venue trading an instrument
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueQuote {
+    pub venue: String,
+    pub price: f64,
+    pub size: f64,
+}
+
+// Consolidated best bid and offer, either side None when no venue has liquidity on it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Nbbo {
+    pub best_bid: Option<VenueQuote>,
+    pub best_ask: Option<VenueQuote>,
+}
+
+#[derive(Default)]
+pub struct Exchange {
+    markets: HashMap<String, OrderBook>,
+    // Books of the same instrument on different venues, keyed by symbol and then by venue
+    venues: HashMap<String, HashMap<String, OrderBook>>,
+}
+
+impl Exchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_market(
+        &mut self,
+        symbol: &str,
+        config: MarketConfig,
+    ) -> Result<(), ExchangeError> {
+        if self.markets.contains_key(symbol) {
+            return Err(ExchangeError::MarketAlreadyExists(symbol.to_string()));
+        }
+        self.markets
+            .insert(symbol.to_string(), OrderBook::with_config(config.into()));
+        Ok(())
+    }
+
+    pub fn market(&self, symbol: &str) -> Result<&OrderBook, ExchangeError> {
+        self.markets
+            .get(symbol)
+            .ok_or_else(|| ExchangeError::MarketNotFound(symbol.to_string()))
+    }
+
+    fn market_mut(&mut self, symbol: &str) -> Result<&mut OrderBook, ExchangeError> {
+        self.markets
+            .get_mut(symbol)
+            .ok_or_else(|| ExchangeError::MarketNotFound(symbol.to_string()))
+    }
+
+    pub fn fee_schedule(&self, symbol: &str) -> Result<FeeSchedule, ExchangeError> {
+        Ok(self.market(symbol)?.config().fee_schedule)
+    }
+
+    pub fn place_limit_order(
+        &mut self,
+        symbol: &str,
+        price: f64,
+        order: Order,
+    ) -> Result<Vec<Match>, ExchangeError> {
+        Ok(self.market_mut(symbol)?.place_limit_order(price, order)?)
+    }
+
+    pub fn place_market_order(
+        &mut self,
+        symbol: &str,
+        order: Order,
+    ) -> Result<Vec<Match>, ExchangeError> {
+        Ok(self.market_mut(symbol)?.place_market_order(order)?)
+    }
+
+    // (realized, unrealized) PnL of the account in one market, see average_cost_pnl
+    pub fn account_pnl(
+        &self,
+        symbol: &str,
+        owner: Uuid,
+        mark_price: f64,
+    ) -> Result<(f64, f64), ExchangeError> {
+        Ok(average_cost_pnl(
+            self.market(symbol)?.trades(),
+            owner,
+            mark_price,
+        ))
+    }
+
+    pub fn create_venue(
+        &mut self,
+        symbol: &str,
+        venue: &str,
+        config: MarketConfig,
+    ) -> Result<(), ExchangeError> {
+        let venues = self.venues.entry(symbol.to_string()).or_default();
+        if venues.contains_key(venue) {
+            return Err(ExchangeError::MarketAlreadyExists(format!(
+                "{symbol}@{venue}"
+            )));
+        }
+        venues.insert(venue.to_string(), OrderBook::with_config(config.into()));
+        Ok(())
+    }
+
+    pub fn venue_mut(
+        &mut self,
+        symbol: &str,
+        venue: &str,
+    ) -> Result<&mut OrderBook, ExchangeError> {
+        self.venues
+            .get_mut(symbol)
+            .and_then(|venues| venues.get_mut(venue))
+            .ok_or_else(|| ExchangeError::MarketNotFound(format!("{symbol}@{venue}")))
+    }
+
+    // Best displayed price of one side over every venue of the symbol. Ties go to the venue
+    // with the larger size, then to the venue whose name sorts first.
+    fn best_venue_quote(&self, symbol: &str, side: OrderType) -> Option<VenueQuote> {
+        let better = |a: &VenueQuote, b: &VenueQuote| match side {
+            OrderType::Bid => a.price > b.price,
+            OrderType::Ask => a.price < b.price,
+        };
+        let mut best: Option<VenueQuote> = None;
+        for (venue, order_book) in self.venues.get(symbol)? {
+            let (bids, asks) = order_book.depth(1);
+            let levels = match side {
+                OrderType::Bid => bids,
+                OrderType::Ask => asks,
+            };
+            let Some(&(price, size)) = levels.first() else {
+                continue;
+            };
+            let quote = VenueQuote {
+                venue: venue.clone(),
+                price,
+                size,
+            };
+            let replaces = match &best {
+                None => true,
+                Some(current) => {
+                    better(&quote, current)
+                        || (quote.price == current.price && quote.size > current.size)
+                        || (quote.price == current.price
+                            && quote.size == current.size
+                            && quote.venue < current.venue)
+                }
+            };
+            if replaces {
+                best = Some(quote);
+            }
+        }
+        best
+    }
+
+    pub fn nbbo(&self, symbol: &str) -> Result<Nbbo, ExchangeError> {
+        if !self.venues.contains_key(symbol) {
+            return Err(ExchangeError::MarketNotFound(symbol.to_string()));
+        }
+        Ok(Nbbo {
+            best_bid: self.best_venue_quote(symbol, OrderType::Bid),
+            best_ask: self.best_venue_quote(symbol, OrderType::Ask),
+        })
+    }
+
+    // Routes a market order across the venues of the symbol without ever trading through a
+    // better price shown elsewhere: each child order only takes the consolidated best level
+    // before the best price is looked up again. Child orders carry every attribute of the
+    // parent but their own id and size. Returns the matches with the venue they happened on.
+    pub fn route_market_order(
+        &mut self,
+        symbol: &str,
+        order: Order,
+    ) -> Result<Vec<(String, Match)>, RouteError> {
+        if !self.venues.contains_key(symbol) {
+            return Err(RouteError {
+                routed: vec![],
+                error: ExchangeError::MarketNotFound(symbol.to_string()),
+            });
+        }
+
+        let side = order.order_type();
+        let mut remaining = order.size();
+        let mut routed = vec![];
+        while has_volume(remaining) {
+            let Some(quote) = self.best_venue_quote(symbol, side.opposite()) else {
+                break;
+            };
+            let mut child = order.clone().with_id(Uuid::new_v4());
+            child.size = remaining.min(quote.size);
+            let placed = self
+                .venue_mut(symbol, &quote.venue)
+                .and_then(|order_book| Ok(order_book.place_market_order(child)?));
+            let matches = match placed {
+                Ok(matches) => matches,
+                Err(error) => return Err(RouteError { routed, error }),
+            };
+            if matches.is_empty() {
+                break;
+            }
+            remaining -= matches.iter().map(|m| m.size_filled).sum::<f64>();
+            routed.extend(matches.into_iter().map(|m| (quote.venue.clone(), m)));
+        }
+        Ok(routed)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use uuid::Uuid;
+
+    use crate::exchange::{Exchange, ExchangeError, MarketConfig, VenueQuote};
+    use crate::fees::FeeSchedule;
+    use crate::order_book::{Order, OrderBookError, OrderType};
+
+    fn exchange_with_two_markets() -> Exchange {
+        let mut exchange = Exchange::new();
+        exchange
+            .create_market(
+                "BTC-USD",
+                MarketConfig {
+                    tick_size: 1.0,
+                    lot_size: 0.0001,
+                    fee_schedule: FeeSchedule::new(1.0, 5.0),
+                },
+            )
+            .unwrap();
+        exchange
+            .create_market(
+                "DOGE-USD",
+                MarketConfig {
+                    tick_size: 0.0001,
+                    lot_size: 1.0,
+                    fee_schedule: FeeSchedule::new(2.0, 10.0),
+                },
+            )
+            .unwrap();
+        exchange
+    }
+
+    #[test]
+    fn successfully_validates_orders_against_their_own_market_config() {
+        // Given
+        let mut exchange = exchange_with_two_markets();
+
+        // When
+        let btc_result =
+            exchange.place_limit_order("BTC-USD", 0.1234, Order::new(OrderType::Bid, 1.0));
+        let doge_result =
+            exchange.place_limit_order("DOGE-USD", 0.1234, Order::new(OrderType::Bid, 1.0));
+        let doge_fractional_size =
+            exchange.place_limit_order("DOGE-USD", 0.1234, Order::new(OrderType::Bid, 0.5));
+
+        // Then
+        assert!(matches!(
+            btc_result,
+            Err(ExchangeError::OrderBook(
+                OrderBookError::PriceOffTick { .. }
+            ))
+        ));
+        assert_eq!(doge_result, Ok(vec![]));
+        assert!(matches!(
+            doge_fractional_size,
+            Err(ExchangeError::OrderBook(OrderBookError::SizeOffLot { .. }))
+        ));
+        assert_eq!(
+            exchange.fee_schedule("DOGE-USD"),
+            Ok(FeeSchedule::new(2.0, 10.0))
+        );
+    }
+
+    #[test]
+    fn successfully_rejects_orders_for_unknown_and_duplicate_markets() {
+        // Given
+        let mut exchange = exchange_with_two_markets();
+
+        // When
+        let unknown =
+            exchange.place_limit_order("ETH-USD", 2_000.0, Order::new(OrderType::Bid, 1.0));
+        let duplicate = exchange.create_market(
+            "BTC-USD",
+            MarketConfig {
+                tick_size: 0.5,
+                lot_size: 0.0001,
+                fee_schedule: FeeSchedule::default(),
+            },
+        );
+
+        // Then
+        assert_eq!(
+            unknown,
+            Err(ExchangeError::MarketNotFound("ETH-USD".to_string()))
+        );
+        assert_eq!(
+            duplicate,
+            Err(ExchangeError::MarketAlreadyExists("BTC-USD".to_string()))
+        );
+    }
+
+    #[test]
+    fn successfully_routes_a_market_order_to_the_venue_with_the_better_price_first() {
+        // Given
+        let mut exchange = Exchange::new();
+        for venue in ["alpha", "beta"] {
+            exchange
+                .create_venue(
+                    "BTC-USD",
+                    venue,
+                    MarketConfig {
+                        tick_size: 1.0,
+                        lot_size: 0.0001,
+                        fee_schedule: FeeSchedule::default(),
+                    },
+                )
+                .unwrap();
+        }
+        for (venue, price, size) in [
+            ("alpha", 30_002.0, 1.0),
+            ("alpha", 30_003.0, 5.0),
+            ("beta", 30_001.0, 0.5),
+            ("beta", 30_004.0, 5.0),
+        ] {
+            exchange
+                .venue_mut("BTC-USD", venue)
+                .unwrap()
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+
+        // When
+        let nbbo = exchange.nbbo("BTC-USD").unwrap();
+        let routed = exchange
+            .route_market_order("BTC-USD", Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            nbbo.best_ask,
+            Some(VenueQuote {
+                venue: "beta".to_string(),
+                price: 30_001.0,
+                size: 0.5
+            })
+        );
+        assert_eq!(nbbo.best_bid, None);
+        let fills: Vec<(&str, f64, f64)> = routed
+            .iter()
+            .map(|(venue, m)| (venue.as_str(), m.price, m.size_filled))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![
+                ("beta", 30_001.0, 0.5),
+                ("alpha", 30_002.0, 1.0),
+                ("alpha", 30_003.0, 0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn successfully_returns_the_fills_routed_before_a_venue_failed() {
+        // Given
+        let mut exchange = Exchange::new();
+        for venue in ["gamma", "beta", "alpha"] {
+            exchange
+                .create_venue(
+                    "BTC-USD",
+                    venue,
+                    MarketConfig {
+                        tick_size: 1.0,
+                        lot_size: 0.0001,
+                        fee_schedule: FeeSchedule::default(),
+                    },
+                )
+                .unwrap();
+            exchange
+                .venue_mut("BTC-USD", venue)
+                .unwrap()
+                .add_order(30_001.0, Order::new(OrderType::Ask, 1.0))
+                .unwrap();
+        }
+        exchange
+            .venue_mut("BTC-USD", "beta")
+            .unwrap()
+            .start_auction();
+        let trader = Uuid::new_v4();
+
+        // When
+        let failed = exchange
+            .route_market_order(
+                "BTC-USD",
+                Order::new(OrderType::Bid, 3.0).with_owner(trader),
+            )
+            .unwrap_err();
+
+        // Then
+        assert_eq!(
+            failed.error,
+            ExchangeError::OrderBook(OrderBookError::AuctionInProgress)
+        );
+        assert_eq!(failed.routed.len(), 1);
+        assert_eq!(failed.routed[0].0, "alpha");
+        assert_eq!(failed.routed[0].1.size_filled, 1.0);
+        let alpha_trades = exchange.venue_mut("BTC-USD", "alpha").unwrap().trades();
+        assert_eq!(alpha_trades[0].taker_owner, trader);
+    }
+
+    #[test]
+    fn successfully_computes_pnl_across_a_position_flip() {
+        // Given
+        let mut exchange = exchange_with_two_markets();
+        let (trader, market_maker) = (Uuid::new_v4(), Uuid::new_v4());
+        exchange
+            .place_limit_order(
+                "BTC-USD",
+                100.0,
+                Order::new(OrderType::Ask, 2.0).with_owner(market_maker),
+            )
+            .unwrap();
+        exchange
+            .place_market_order(
+                "BTC-USD",
+                Order::new(OrderType::Bid, 2.0).with_owner(trader),
+            )
+            .unwrap();
+        exchange
+            .place_limit_order(
+                "BTC-USD",
+                110.0,
+                Order::new(OrderType::Bid, 5.0).with_owner(market_maker),
+            )
+            .unwrap();
+
+        // When
+        exchange
+            .place_market_order(
+                "BTC-USD",
+                Order::new(OrderType::Ask, 5.0).with_owner(trader),
+            )
+            .unwrap();
+        let trader_pnl = exchange.account_pnl("BTC-USD", trader, 105.0).unwrap();
+        let market_maker_pnl = exchange
+            .account_pnl("BTC-USD", market_maker, 105.0)
+            .unwrap();
+
+        // Then
+        // Bought 2 at 100, sold 5 at 110: 20 realized and short 3 from 110
+        assert_eq!(trader_pnl, (20.0, 15.0));
+        assert_eq!(market_maker_pnl, (-20.0, -15.0));
+    }
+}
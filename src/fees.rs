@@ -0,0 +1,127 @@
+use crate::order_book::Trade;
+
+// How an amount that falls between two minor units is settled. HalfUp takes ties away from
+// zero, Floor and Ceil go towards negative and positive infinity respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RoundingMode {
+    #[default]
+    HalfUp,
+    HalfEven,
+    Floor,
+    Ceil,
+}
+
+// Rounds amounts to whole minor units of the settlement currency, e.g. 2 decimals for
+// cents, so fee figures match an external ledger to the unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeRounding {
+    pub mode: RoundingMode,
+    pub decimals: u32,
+}
+
+impl FeeRounding {
+    // The amount as a count of minor units. Float noise is cleared before rounding so that
+    // e.g. 0.145 is treated as the tie it was meant to be and not as 0.14499999999999999.
+    pub fn to_minor_units(&self, amount: f64) -> i64 {
+        let scaled = amount * 10f64.powi(self.decimals as i32);
+        let scaled = (scaled * 1e6).round() / 1e6;
+        let units = match self.mode {
+            RoundingMode::HalfUp => scaled.round(),
+            RoundingMode::HalfEven => scaled.round_ties_even(),
+            RoundingMode::Floor => scaled.floor(),
+            RoundingMode::Ceil => scaled.ceil(),
+        };
+        units as i64
+    }
+
+    pub fn round(&self, amount: f64) -> f64 {
+        self.to_minor_units(amount) as f64 / 10f64.powi(self.decimals as i32)
+    }
+}
+
+// Fees are expressed in basis points of the traded notional. A negative maker_bps is a
+// rebate paid out to the maker. Without a rounding fees are left as computed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FeeSchedule {
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+    pub rounding: Option<FeeRounding>,
+}
+
+impl FeeSchedule {
+    pub fn new(maker_bps: f64, taker_bps: f64) -> Self {
+        Self {
+            maker_bps,
+            taker_bps,
+            rounding: None,
+        }
+    }
+
+    pub fn with_rounding(mut self, mode: RoundingMode, decimals: u32) -> Self {
+        self.rounding = Some(FeeRounding { mode, decimals });
+        self
+    }
+
+    pub fn maker_fee(&self, notional: f64) -> f64 {
+        self.rounded(notional * self.maker_bps / 10_000.0)
+    }
+
+    pub fn taker_fee(&self, notional: f64) -> f64 {
+        self.rounded(notional * self.taker_bps / 10_000.0)
+    }
+
+    fn rounded(&self, fee: f64) -> f64 {
+        self.rounding.map_or(fee, |rounding| rounding.round(fee))
+    }
+
+    // Maker and taker fee of the trade in that order, negative when credited
+    pub fn trade_fees(&self, trade: &Trade) -> (f64, f64) {
+        self.notional_fees(trade.price * trade.size)
+    }
+
+    // Like trade_fees for a notional the caller worked out, e.g. of an inverse contract
+    pub fn notional_fees(&self, notional: f64) -> (f64, f64) {
+        (self.maker_fee(notional), self.taker_fee(notional))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::fees::{FeeSchedule, RoundingMode};
+
+    #[test]
+    fn successfully_computes_maker_and_taker_fees() {
+        // Given
+        let fee_schedule = FeeSchedule::new(2.0, 5.0);
+
+        // When
+        let maker_fee = fee_schedule.maker_fee(10_000.0);
+        let taker_fee = fee_schedule.taker_fee(10_000.0);
+
+        // Then
+        assert_eq!(maker_fee, 2.0);
+        assert_eq!(taker_fee, 5.0);
+    }
+
+    #[test]
+    fn successfully_rounds_a_half_unit_fee_by_the_rounding_mode() {
+        // Given
+        let half_up = FeeSchedule::new(2.5, 2.9).with_rounding(RoundingMode::HalfUp, 2);
+        let half_even = FeeSchedule::new(2.5, 2.9).with_rounding(RoundingMode::HalfEven, 2);
+
+        // When
+        let half_up_fees = half_up.notional_fees(500.0);
+        let half_even_fees = half_even.notional_fees(500.0);
+
+        // Then
+        assert_eq!(half_up_fees, (0.13, 0.15));
+        assert_eq!(half_even_fees, (0.12, 0.14));
+        assert_eq!(half_even.rounding.unwrap().to_minor_units(0.125), 12);
+        assert_eq!(
+            FeeSchedule::new(2.5, 2.9)
+                .with_rounding(RoundingMode::Floor, 2)
+                .maker_fee(500.0),
+            0.12
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::command::Command;
+use crate::order_book::{Match, Order, OrderType};
+
+// A minimal tag=value codec for order entry, enough to talk to FIX speaking clients without
+// pulling in a full FIX engine. Fields are separated by SOH, although `|` is accepted as well
+// since that is how FIX messages are usually written down.
+const SOH: char = '\x01';
+
+const TAG_MSG_TYPE: u32 = 35;
+const TAG_SIDE: u32 = 54;
+const TAG_ORDER_QTY: u32 = 38;
+const TAG_PRICE: u32 = 44;
+const TAG_ORD_TYPE: u32 = 40;
+const TAG_ORDER_ID: u32 = 37;
+const TAG_EXEC_TYPE: u32 = 150;
+const TAG_LAST_PX: u32 = 31;
+const TAG_LAST_QTY: u32 = 32;
+
+const MSG_TYPE_NEW_ORDER: &str = "D";
+const MSG_TYPE_EXECUTION_REPORT: &str = "8";
+const EXEC_TYPE_TRADE: &str = "F";
+
+#[derive(Debug, PartialEq)]
+pub enum FixParseError {
+    MalformedField(String),
+    MissingTag(u32),
+    InvalidValue { tag: u32, value: String },
+    UnsupportedMsgType(String),
+}
+
+impl fmt::Display for FixParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixParseError::MalformedField(field) => write!(f, "Malformed field {field}"),
+            FixParseError::MissingTag(tag) => write!(f, "Missing required tag {tag}"),
+            FixParseError::InvalidValue { tag, value } => {
+                write!(f, "Invalid value {value} for tag {tag}")
+            }
+            FixParseError::UnsupportedMsgType(msg_type) => {
+                write!(f, "Unsupported message type {msg_type}")
+            }
+        }
+    }
+}
+
+impl Error for FixParseError {}
+
+fn parse_fields(message: &str) -> Result<HashMap<u32, &str>, FixParseError> {
+    let mut fields = HashMap::new();
+    for field in message.split([SOH, '|']).filter(|field| !field.is_empty()) {
+        let (tag, value) = field
+            .split_once('=')
+            .ok_or_else(|| FixParseError::MalformedField(field.to_string()))?;
+        let tag = tag
+            .parse::<u32>()
+            .map_err(|_| FixParseError::MalformedField(field.to_string()))?;
+        fields.insert(tag, value);
+    }
+    Ok(fields)
+}
+
+fn required<'a>(fields: &HashMap<u32, &'a str>, tag: u32) -> Result<&'a str, FixParseError> {
+    fields
+        .get(&tag)
+        .copied()
+        .ok_or(FixParseError::MissingTag(tag))
+}
+
+fn parse_number(fields: &HashMap<u32, &str>, tag: u32) -> Result<f64, FixParseError> {
+    let value = required(fields, tag)?;
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or_else(|| FixParseError::InvalidValue {
+            tag,
+            value: value.to_string(),
+        })
+}
+
+// Parses a New Order Single (35=D) into the command that places it. Side (54) is 1 for buy
+// and 2 for sell, order type (40) is 1 for market and 2 for limit, which also requires a
+// price (44).
+pub fn parse_new_order(message: &str) -> Result<Command, FixParseError> {
+    let fields = parse_fields(message)?;
+
+    let msg_type = required(&fields, TAG_MSG_TYPE)?;
+    if msg_type != MSG_TYPE_NEW_ORDER {
+        return Err(FixParseError::UnsupportedMsgType(msg_type.to_string()));
+    }
+
+    let side = required(&fields, TAG_SIDE)?;
+    let order_type = side
+        .parse()
+        .ok()
+        .and_then(OrderType::from_code)
+        .ok_or_else(|| FixParseError::InvalidValue {
+            tag: TAG_SIDE,
+            value: side.to_string(),
+        })?;
+    let size = parse_number(&fields, TAG_ORDER_QTY)?;
+    let order = Order::new(order_type, size);
+
+    match required(&fields, TAG_ORD_TYPE)? {
+        "1" => Ok(Command::PlaceMarket { order }),
+        "2" => Ok(Command::PlaceLimit {
+            price: parse_number(&fields, TAG_PRICE)?,
+            order,
+        }),
+        value => Err(FixParseError::InvalidValue {
+            tag: TAG_ORD_TYPE,
+            value: value.to_string(),
+        }),
+    }
+}
+
+// Serializes a fill of the given order into an Execution Report (35=8)
+pub fn execution_report(order_id: Uuid, m: &Match) -> String {
+    [
+        (TAG_MSG_TYPE, MSG_TYPE_EXECUTION_REPORT.to_string()),
+        (TAG_ORDER_ID, order_id.to_string()),
+        (TAG_EXEC_TYPE, EXEC_TYPE_TRADE.to_string()),
+        (TAG_LAST_PX, m.price.to_string()),
+        (TAG_LAST_QTY, m.size_filled.to_string()),
+    ]
+    .iter()
+    .map(|(tag, value)| format!("{tag}={value}{SOH}"))
+    .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use uuid::Uuid;
+
+    use crate::command::Command;
+    use crate::fix::{FixParseError, execution_report, parse_new_order};
+    use crate::order_book::{Match, OrderBook, OrderType};
+
+    #[test]
+    fn successfully_parses_a_new_limit_order() {
+        // Given
+        let message = "35=D|54=2|38=1.5|44=101.25|40=2|";
+
+        // When
+        let command = parse_new_order(message).unwrap();
+
+        // Then
+        let Command::PlaceLimit { price, order } = command else {
+            panic!("Expected a limit order but got {command:?}");
+        };
+        assert_eq!(price, 101.25);
+        let order_id = order.id();
+        let mut order_book = OrderBook::new();
+        order_book.add_order(price, order).unwrap();
+        assert_eq!(
+            order_book.level_queue(OrderType::Ask, 101.25),
+            Some(vec![(order_id, 1.5)])
+        );
+    }
+
+    #[test]
+    fn successfully_rejects_a_new_order_without_a_side() {
+        // Given
+        let message = "35=D\x0138=1\x0140=1\x01";
+
+        // When
+        let result = parse_new_order(message);
+
+        // Then
+        assert!(matches!(result, Err(FixParseError::MissingTag(54))));
+    }
+
+    #[test]
+    fn successfully_serializes_an_execution_report() {
+        // Given
+        let order_id = Uuid::nil();
+        let m = Match {
+            ask_id: order_id,
+            bid_id: Uuid::new_v4(),
+            size_filled: 2.0,
+            price: 100.5,
+        };
+
+        // When
+        let report = execution_report(order_id, &m);
+
+        // Then
+        assert_eq!(
+            report,
+            format!("35=8\x0137={order_id}\x01150=F\x0131=100.5\x0132=2\x01")
+        );
+    }
+}
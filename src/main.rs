@@ -1,4 +1,22 @@
+pub mod analytics;
+pub mod checksum;
+pub mod clock;
+pub mod command;
+pub mod decimal;
+pub mod events;
+pub mod exchange;
+pub mod fees;
+pub mod fix;
 pub mod order_book;
+#[cfg(feature = "record-batch")]
+pub mod record_batch;
+pub mod risk;
+#[cfg(feature = "binary-snapshot")]
+pub mod snapshot;
+pub mod stress;
+pub mod trade_log;
+pub mod twap;
+pub mod view;
 
 fn main() {
     println!("Hello, world!");
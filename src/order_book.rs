@@ -1,48 +1,914 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
 use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::{BitOr, Deref};
+use std::str::FromStr;
+use std::sync::{Arc, mpsc};
 
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-// struct Match {
-//     ask_id: Uuid,
-//     bid_id: Uuid,
-//     size_filled: f64,
-//     price: f64,
-// }
+use crate::clock::{Clock, SystemClock};
+use crate::decimal::{Decimal, DecimalParseError};
+use crate::events::{BookEvent, EventBuffer};
+use crate::fees::FeeSchedule;
+use crate::risk::{NoRiskCheck, RejectReason, RiskCheck};
 
-#[derive(Hash, Eq, PartialEq, Clone, Copy)]
-enum OrderType {
+// A price level as (price, volume)
+pub type PriceLevel = (f64, f64);
+
+// Sizes and volumes are floats, so after enough partial fills a quantity that should be
+// exactly zero can be left with residue. Anything at or below this counts as nothing.
+pub const VOLUME_EPSILON: f64 = 1e-9;
+
+pub fn has_volume(volume: f64) -> bool {
+    volume > VOLUME_EPSILON
+}
+
+// Depth laid out as parallel columns rather than tuples, which is what columnar analytics tools
+// such as Arrow or Polars ingest most easily
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DepthColumns {
+    pub bid_prices: Vec<f64>,
+    pub bid_sizes: Vec<f64>,
+    pub ask_prices: Vec<f64>,
+    pub ask_sizes: Vec<f64>,
+}
+
+// A depth level with the number of displayed orders making it up, which tells one large
+// order apart from many small ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelDetail {
+    pub price: f64,
+    pub volume: f64,
+    pub order_count: usize,
+}
+
+impl LevelDetail {
+    pub fn average_order_size(&self) -> f64 {
+        self.volume / self.order_count as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Activity {
+    Add,
+    Cancel,
+    Trade,
+}
+
+// What an account did over the activity window. A compliance layer looks for accounts that
+// place and pull many orders without ever trading.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ActivityStats {
+    pub adds: usize,
+    pub cancels: usize,
+    pub trades: usize,
+}
+
+impl ActivityStats {
+    pub fn cancel_to_add_ratio(&self) -> f64 {
+        if self.adds == 0 {
+            return 0.0;
+        }
+        self.cancels as f64 / self.adds as f64
+    }
+
+    // Infinite for an account that placed orders but never traded
+    pub fn order_to_trade_ratio(&self) -> f64 {
+        if self.adds == 0 {
+            return 0.0;
+        }
+        self.adds as f64 / self.trades as f64
+    }
+}
+
+// How much volume joined a price level and how it left, either traded or cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LevelFillStats {
+    pub added: f64,
+    pub filled: f64,
+    pub cancelled: f64,
+}
+
+// Copy of the resting orders of one side with their prices, in arrival order
+#[derive(Debug, Clone)]
+pub struct SideSnapshot {
+    side: OrderType,
+    orders: Vec<(f64, Order)>,
+}
+
+impl SideSnapshot {
+    pub fn side(&self) -> OrderType {
+        self.side
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter().map(|(_, order)| order)
+    }
+}
+
+// Read-only view of the book handed to `OrderBook::with_frozen`. Every read through it sees
+// the state as of `sequence`, nothing can change the book while the view is alive.
+pub struct FrozenBook<'a> {
+    order_book: &'a OrderBook,
+    sequence: u64,
+    timestamp: i64,
+}
+
+impl FrozenBook<'_> {
+    // Sequence the next order would have got when the view was taken
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+impl Deref for FrozenBook<'_> {
+    type Target = OrderBook;
+
+    fn deref(&self) -> &OrderBook {
+        self.order_book
+    }
+}
+
+// Bookkeeping figures of one side of the book, see IntegrityReport
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SideIntegrity {
+    pub orders: usize,
+    // Limits holding at least one order
+    pub levels: usize,
+    // Limits kept around without any orders so limit indices stay stable
+    pub empty_limits: usize,
+    pub cached_volume: f64,
+    pub recomputed_volume: f64,
+}
+
+// Snapshot of the internal bookkeeping for reconciling the book against external records
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IntegrityReport {
+    pub bids: SideIntegrity,
+    pub asks: SideIntegrity,
+    pub index_entries: usize,
+    // Best bid above the best ask
+    pub crossed: bool,
+    // Best bid equal to the best ask
+    pub locked: bool,
+}
+
+// How the shorter side of DepthColumns is filled up so both sides have the same length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthPadding {
+    None,
+    Nan,
+    Zero,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub ask_id: Uuid,
+    pub bid_id: Uuid,
+    pub size_filled: f64,
+    pub price: f64,
+}
+
+impl Match {
+    // Size weighted average price of the fills an order got, None when it got none
+    pub fn weighted_price(matches: &[Match]) -> Option<f64> {
+        let size: f64 = matches.iter().map(|m| m.size_filled).sum();
+        let notional: f64 = matches.iter().map(|m| m.price * m.size_filled).sum();
+        has_volume(size).then(|| notional / size)
+    }
+}
+
+// An execution as recorded on the book's tape
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub maker_owner: Uuid,
+    pub taker_owner: Uuid,
+    // Side of the incoming order that caused the trade
+    pub aggressor: OrderType,
+    pub price: f64,
+    pub size: f64,
+    pub timestamp: i64,
+    // Mid price of the book right before the trade, None for a one-sided book
+    pub mid_at_trade: Option<f64>,
+    pub taker_class: OrderClass,
+}
+
+// An account's netted fills over a window for handoff to clearing. Deltas are signed from
+// the account's point of view: a buy adds base and spends quote. The quote leg is the
+// trade notional as the book computes it, and fees are what the account owes (negative
+// for a net rebate), not yet taken out of quote_delta.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettlementRecord {
+    pub owner: Uuid,
+    pub base_delta: f64,
+    pub quote_delta: f64,
+    pub fees: f64,
+    pub trade_count: usize,
+}
+
+// Whether a side of a trade provided the liquidity (rested on the book) or took it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiquidityFlag {
+    MakerAdd,
+    TakerRemove,
+}
+
+impl Trade {
+    pub fn liquidity_flag(&self, side: OrderType) -> LiquidityFlag {
+        if side == self.aggressor {
+            LiquidityFlag::TakerRemove
+        } else {
+            LiquidityFlag::MakerAdd
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrderBookError {
+    OrderNotFound(Uuid),
+    InvalidSize(f64),
+    InvalidLimitIndex {
+        limit_idx: usize,
+        price: f64,
+    },
+    InvalidPrice(f64),
+    PriceBelowMinimum {
+        price: f64,
+        min_price: f64,
+    },
+    PriceOffTick {
+        price: f64,
+        tick_size: f64,
+    },
+    SizeOffLot {
+        size: f64,
+        lot_size: f64,
+    },
+    OrderAlreadyLinked(Uuid),
+    InvalidOcoLink(Uuid),
+    DuplicateOrderId(Uuid),
+    AuctionInProgress,
+    NoLiquidity(OrderType),
+    LevelVolumeExceeded {
+        price: f64,
+        max_volume: f64,
+    },
+    PriceBandExceeded {
+        price: f64,
+        reference_price: f64,
+    },
+    InvalidDecimal(DecimalParseError),
+    DuplicateClientOrderId(String),
+    ClientOrderIdNotFound(String),
+    CooldownInProgress {
+        until: i64,
+    },
+    CrossedSnapshot {
+        best_bid: f64,
+        best_ask: f64,
+    },
+    NotionalBelowMinimum {
+        notional: f64,
+        min_notional: f64,
+    },
+    RateLimited(Uuid),
+    SelfTradeRejected(Uuid),
+    ConflictingInstructions(ExecInstructions),
+    PostOnlyWouldCross(Uuid),
+    ReduceOnlyWouldIncrease(Uuid),
+    QuoteNotFound(Uuid),
+    QuoteExpired(Uuid),
+    QuoteMoved {
+        quoted: f64,
+        current: f64,
+    },
+    AmendWouldCross(Uuid),
+    MmpTripped(Uuid),
+    SessionNotFound(Uuid),
+    MatchNotApplicable {
+        ask_id: Uuid,
+        bid_id: Uuid,
+    },
+    LevelCountExceeded {
+        price: f64,
+        max_levels: usize,
+    },
+    RiskRejected(RejectReason),
+    CancelTooSoon {
+        order_id: Uuid,
+        min_resting_time_ms: i64,
+    },
+    WouldLockBook(Uuid),
+    NoYieldConvention,
+    PriceAboveMaximum {
+        price: f64,
+        max_price: f64,
+    },
+    SizeAboveMaximum {
+        size: f64,
+        max_size: f64,
+    },
+}
+
+impl fmt::Display for OrderBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderBookError::OrderNotFound(order_id) => {
+                write!(f, "Could not find order by id {order_id}")
+            }
+            OrderBookError::InvalidSize(size) => write!(f, "Invalid size {size}"),
+            OrderBookError::InvalidLimitIndex { limit_idx, price } => {
+                write!(f, "Limit index {limit_idx} is invalid for price {price}")
+            }
+            OrderBookError::InvalidPrice(price) => write!(f, "Invalid price {price}"),
+            OrderBookError::PriceBelowMinimum { price, min_price } => {
+                write!(f, "Price {price} is below the minimum price {min_price}")
+            }
+            OrderBookError::PriceOffTick { price, tick_size } => {
+                write!(
+                    f,
+                    "Price {price} is not a multiple of the tick size {tick_size}"
+                )
+            }
+            OrderBookError::SizeOffLot { size, lot_size } => {
+                write!(
+                    f,
+                    "Size {size} is not a multiple of the lot size {lot_size}"
+                )
+            }
+            OrderBookError::OrderAlreadyLinked(order_id) => {
+                write!(f, "Order {order_id} is already linked to another order")
+            }
+            OrderBookError::InvalidOcoLink(order_id) => {
+                write!(f, "Order {order_id} cannot be linked to itself")
+            }
+            OrderBookError::DuplicateOrderId(order_id) => {
+                write!(f, "Order {order_id} is already in the book")
+            }
+            OrderBookError::AuctionInProgress => {
+                write!(
+                    f,
+                    "Orders cannot be matched while an auction is in progress"
+                )
+            }
+            OrderBookError::NoLiquidity(side) => {
+                write!(f, "There is no liquidity on the {side} side")
+            }
+            OrderBookError::LevelVolumeExceeded { price, max_volume } => {
+                write!(
+                    f,
+                    "Level {price} cannot hold more than {max_volume} of resting size"
+                )
+            }
+            OrderBookError::PriceBandExceeded {
+                price,
+                reference_price,
+            } => {
+                write!(
+                    f,
+                    "Price {price} is outside of the band around the reference price {reference_price}"
+                )
+            }
+            OrderBookError::InvalidDecimal(err) => write!(f, "{err}"),
+            OrderBookError::DuplicateClientOrderId(client_order_id) => {
+                write!(f, "Client order id {client_order_id} is already in use")
+            }
+            OrderBookError::ClientOrderIdNotFound(client_order_id) => {
+                write!(
+                    f,
+                    "Could not find order by client order id {client_order_id}"
+                )
+            }
+            OrderBookError::CooldownInProgress { until } => {
+                write!(
+                    f,
+                    "Aggressive orders are rejected until {until} after a large price move"
+                )
+            }
+            OrderBookError::CrossedSnapshot { best_bid, best_ask } => {
+                write!(
+                    f,
+                    "Snapshot is crossed, best bid {best_bid} >= best ask {best_ask}"
+                )
+            }
+            OrderBookError::NotionalBelowMinimum {
+                notional,
+                min_notional,
+            } => {
+                write!(
+                    f,
+                    "Notional {notional} is below the minimum notional {min_notional}"
+                )
+            }
+            OrderBookError::RateLimited(owner) => {
+                write!(f, "Account {owner} is sending orders too fast")
+            }
+            OrderBookError::SelfTradeRejected(order_id) => {
+                write!(
+                    f,
+                    "Order {order_id} would trade against its own resting orders"
+                )
+            }
+            OrderBookError::ConflictingInstructions(instructions) => {
+                write!(
+                    f,
+                    "Execution instructions {:#08b} can't be combined",
+                    instructions.bits()
+                )
+            }
+            OrderBookError::PostOnlyWouldCross(order_id) => {
+                write!(f, "Post-only order {order_id} would take liquidity")
+            }
+            OrderBookError::ReduceOnlyWouldIncrease(order_id) => {
+                write!(
+                    f,
+                    "Reduce-only order {order_id} would increase the position"
+                )
+            }
+            OrderBookError::QuoteNotFound(quote_id) => write!(f, "Quote {quote_id} not found"),
+            OrderBookError::QuoteExpired(quote_id) => write!(f, "Quote {quote_id} expired"),
+            OrderBookError::QuoteMoved { quoted, current } => {
+                write!(
+                    f,
+                    "The book moved from the quoted price {quoted} to {current}"
+                )
+            }
+            OrderBookError::AmendWouldCross(order_id) => {
+                write!(f, "Amending order {order_id} would cross the book")
+            }
+            OrderBookError::MmpTripped(owner) => {
+                write!(f, "Market maker protection of account {owner} tripped")
+            }
+            OrderBookError::SessionNotFound(session) => {
+                write!(f, "Could not find session {session}")
+            }
+            OrderBookError::MatchNotApplicable { ask_id, bid_id } => {
+                write!(
+                    f,
+                    "Match of ask {ask_id} and bid {bid_id} does not fit the resting orders"
+                )
+            }
+            OrderBookError::LevelCountExceeded { price, max_levels } => {
+                write!(
+                    f,
+                    "Price {price} would open a level beyond the {max_levels} levels allowed"
+                )
+            }
+            OrderBookError::RiskRejected(reason) => {
+                write!(f, "Order rejected by the risk check: {reason}")
+            }
+            OrderBookError::CancelTooSoon {
+                order_id,
+                min_resting_time_ms,
+            } => {
+                write!(
+                    f,
+                    "Order {order_id} has to rest for {min_resting_time_ms}ms before it can be cancelled"
+                )
+            }
+            OrderBookError::WouldLockBook(order_id) => {
+                write!(
+                    f,
+                    "Order {order_id} would lock the book at the opposite touch"
+                )
+            }
+            OrderBookError::NoYieldConvention => {
+                write!(f, "The book has no yield convention to convert yields with")
+            }
+            OrderBookError::PriceAboveMaximum { price, max_price } => {
+                write!(f, "Price {price} is above the maximum price {max_price}")
+            }
+            OrderBookError::SizeAboveMaximum { size, max_size } => {
+                write!(f, "Size {size} is above the maximum order size {max_size}")
+            }
+        }
+    }
+}
+
+impl Error for OrderBookError {}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum OrderType {
     Bid,
     Ask,
 }
 
+impl OrderType {
+    pub fn opposite(self) -> OrderType {
+        match self {
+            OrderType::Bid => OrderType::Ask,
+            OrderType::Ask => OrderType::Bid,
+        }
+    }
+}
+
 impl fmt::Display for OrderType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self,)
+        match self {
+            OrderType::Bid => write!(f, "Bid"),
+            OrderType::Ask => write!(f, "Ask"),
+        }
     }
 }
 
-struct Order {
-    id: Uuid,
-    order_type: OrderType,
-    size: f64,
-    timestamp: i64,
+impl OrderType {
+    // Lowercase name, which parses back into the same side
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderType::Bid => "bid",
+            OrderType::Ask => "ask",
+        }
+    }
+
+    // FIX side code (tag 54), 1 for buy and 2 for sell
+    pub fn code(self) -> u8 {
+        match self {
+            OrderType::Bid => 1,
+            OrderType::Ask => 2,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<OrderType> {
+        match code {
+            1 => Some(OrderType::Bid),
+            2 => Some(OrderType::Ask),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OrderTypeParseError(pub String);
+
+impl fmt::Display for OrderTypeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not an order side", self.0)
+    }
+}
+
+impl Error for OrderTypeParseError {}
+
+// Accepts the usual spellings of either side regardless of case: buy, bid or b for bids and
+// sell, ask or s for asks
+impl FromStr for OrderType {
+    type Err = OrderTypeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "buy" | "bid" | "b" => Ok(OrderType::Bid),
+            "sell" | "ask" | "s" => Ok(OrderType::Ask),
+            _ => Err(OrderTypeParseError(s.to_string())),
+        }
+    }
+}
+
+// Session orders only live until the end of the trading session, good-till-cancel orders
+// keep resting until they fill or get cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeInForce {
+    #[default]
+    GoodTillCancel,
+    Session,
+}
+
+// Hidden orders take part in matching but never show up in the displayed volume or depth,
+// and they yield priority to displayed orders at the same price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    #[default]
+    Displayed,
+    Hidden,
+}
+
+// Liquidation orders come from the risk engine closing out a position and are let through
+// the protections that apply to normal orders, such as the price band
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderClass {
+    #[default]
+    Normal,
+    Liquidation,
+}
+
+// The price a crossing limit order trades at. Normally the resting maker's, RFQ style flows
+// where the taker accepts a quoted price execute at the taker's limit price instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionPrice {
+    #[default]
+    Maker,
+    Taker,
+}
+
+// Behaviors an order combines, one bit each. Conflicting combinations such as post-only
+// together with immediate-or-cancel are rejected when the order arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExecInstructions(u8);
+
+impl ExecInstructions {
+    pub const NONE: ExecInstructions = ExecInstructions(0);
+    // Rejected instead of trading on arrival, the order may only add liquidity
+    pub const POST_ONLY: ExecInstructions = ExecInstructions(1);
+    // May only shrink the account's position from its trades on this book, never grow or
+    // flip it
+    pub const REDUCE_ONLY: ExecInstructions = ExecInstructions(1 << 1);
+    // Rests hidden, the same as Visibility::Hidden
+    pub const HIDDEN: ExecInstructions = ExecInstructions(1 << 2);
+    // Whatever does not fill on arrival is cancelled instead of resting
+    pub const IMMEDIATE_OR_CANCEL: ExecInstructions = ExecInstructions(1 << 3);
+    // Fills completely on arrival or not at all
+    pub const FILL_OR_KILL: ExecInstructions = ExecInstructions(1 << 4);
+    // Only trades on arrival when at least the order's minimum fill size is available, set
+    // by `Order::with_min_fill`
+    pub const MIN_FILL: ExecInstructions = ExecInstructions(1 << 5);
+
+    const ALL: u8 = (1 << 6) - 1;
+
+    pub fn contains(self, instructions: ExecInstructions) -> bool {
+        self.0 & instructions.0 == instructions.0
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    // None when a bit is set that is not an instruction
+    pub fn from_bits(bits: u8) -> Option<ExecInstructions> {
+        (bits & !ExecInstructions::ALL == 0).then_some(ExecInstructions(bits))
+    }
+
+    // An order that may not take liquidity can't also be required to trade on arrival
+    fn conflicts(self) -> bool {
+        let takes_on_arrival = ExecInstructions::IMMEDIATE_OR_CANCEL.0
+            | ExecInstructions::FILL_OR_KILL.0
+            | ExecInstructions::MIN_FILL.0;
+        self.contains(ExecInstructions::POST_ONLY) && self.0 & takes_on_arrival != 0
+    }
+}
+
+impl BitOr for ExecInstructions {
+    type Output = ExecInstructions;
+
+    fn bitor(self, instructions: ExecInstructions) -> ExecInstructions {
+        ExecInstructions(self.0 | instructions.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub(crate) id: Uuid,
+    pub(crate) order_type: OrderType,
+    pub(crate) size: f64,
+    pub(crate) timestamp: i64,
+    // When the order last started resting, in unix milliseconds
+    pub(crate) rested_at_millis: i64,
+    // Assigned by the order book on arrival and used to break time priority ties, since
+    // timestamps only have second resolution
+    pub(crate) sequence: u64,
+    // The account the order belongs to
+    pub(crate) owner: Uuid,
+    pub(crate) time_in_force: TimeInForce,
+    pub(crate) visibility: Visibility,
+    pub(crate) execution_price: ExecutionPrice,
+    pub(crate) class: OrderClass,
+    // Unix timestamp after which the order is purged, None to never expire
+    pub(crate) expires_at: Option<i64>,
+    // Unix timestamp before which the order is held back instead of resting or matching
+    pub(crate) not_before: Option<i64>,
+    // Opaque id the client tracks the order by, unique among the owner's resting orders
+    pub(crate) client_order_id: Option<String>,
+    // Largest size an iceberg order shows at once, None for a plain order
+    pub(crate) peak: Option<f64>,
+    // Size of an iceberg order held back to replenish the shown slice, not part of any volume
+    pub(crate) reserve: f64,
+    // How many more times an iceberg slice is replenished at the peak size before the whole
+    // reserve is shown, None for no limit
+    pub(crate) refreshes_remaining: Option<u32>,
+    // Running totals over every fill of the order so far
+    pub(crate) cumulative_filled: f64,
+    pub(crate) avg_fill_price: f64,
+    pub(crate) exec_instructions: ExecInstructions,
+    // Connection the order came in on, its orders are cancelled when it drops. Sessions
+    // don't outlive the process so snapshots leave this out.
+    pub(crate) session: Option<Uuid>,
+    // Smallest size the order trades on arrival, only looked at with ExecInstructions::MIN_FILL
+    pub(crate) min_fill_size: Option<f64>,
     limit_id: Option<Uuid>,
 }
 
 impl Order {
-    fn new(order_type: OrderType, size: f64) -> Self {
-        return Order {
+    pub fn new(order_type: OrderType, size: f64) -> Self {
+        Order {
             id: Uuid::new_v4(),
-            order_type: order_type,
+            order_type,
             size,
             timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            rested_at_millis: 0,
+            sequence: 0,
+            owner: Uuid::nil(),
+            time_in_force: TimeInForce::default(),
+            visibility: Visibility::default(),
+            execution_price: ExecutionPrice::default(),
+            class: OrderClass::default(),
+            expires_at: None,
+            not_before: None,
+            client_order_id: None,
+            peak: None,
+            reserve: 0.0,
+            refreshes_remaining: None,
+            cumulative_filled: 0.0,
+            avg_fill_price: 0.0,
+            exec_instructions: ExecInstructions::NONE,
+            min_fill_size: None,
+            session: None,
             limit_id: None,
+        }
+    }
+
+    // Takes the size as text so API callers never have to go through a lossy f64
+    pub fn from_decimal_str(order_type: OrderType, size: &str) -> Result<Self, DecimalParseError> {
+        let size: Decimal = size.parse()?;
+        Ok(Order::new(order_type, size.to_f64()))
+    }
+
+    // Orders normally get a random id, this is for ids supplied from the outside such as
+    // when replaying or importing orders
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_owner(mut self, owner: Uuid) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn with_execution_price(mut self, execution_price: ExecutionPrice) -> Self {
+        self.execution_price = execution_price;
+        self
+    }
+
+    pub fn with_class(mut self, class: OrderClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn with_expiry(mut self, expires_at: i64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn with_not_before(mut self, not_before: i64) -> Self {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    pub fn with_client_order_id(mut self, client_order_id: impl Into<String>) -> Self {
+        self.client_order_id = Some(client_order_id.into());
+        self
+    }
+
+    // Once resting only `peak` of the size is shown, each time the shown slice is used up a
+    // new one comes out of the reserve and joins the back of the queue
+    pub fn with_iceberg(mut self, peak: f64) -> Self {
+        self.peak = Some(peak);
+        self
+    }
+
+    pub fn with_max_refreshes(mut self, max_refreshes: u32) -> Self {
+        self.refreshes_remaining = Some(max_refreshes);
+        self
+    }
+
+    pub fn with_exec_instructions(mut self, exec_instructions: ExecInstructions) -> Self {
+        self.exec_instructions = exec_instructions;
+        self
+    }
+
+    pub fn with_min_fill(mut self, min_fill_size: f64) -> Self {
+        self.exec_instructions = self.exec_instructions | ExecInstructions::MIN_FILL;
+        self.min_fill_size = Some(min_fill_size);
+        self
+    }
+
+    // Ties the order to a session from `OrderBook::register_session` for cancel-on-disconnect
+    pub fn with_session(mut self, session: Uuid) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn exec_instructions(&self) -> ExecInstructions {
+        self.exec_instructions
+    }
+
+    pub fn reserve(&self) -> f64 {
+        self.reserve
+    }
+
+    pub fn refreshes_remaining(&self) -> Option<u32> {
+        self.refreshes_remaining
+    }
+
+    // The next slice of a used up iceberg order, None when there is no reserve left. Once
+    // the refreshes ran out the whole reserve comes out as a plain displayed order.
+    fn replenished(mut self) -> Option<Order> {
+        if !has_volume(self.reserve) {
+            return None;
+        }
+        let slice = match (self.peak, self.refreshes_remaining) {
+            (Some(peak), Some(refreshes_remaining)) if refreshes_remaining > 0 => {
+                self.refreshes_remaining = Some(refreshes_remaining - 1);
+                peak.min(self.reserve)
+            }
+            (Some(peak), None) => peak.min(self.reserve),
+            _ => {
+                self.peak = None;
+                self.reserve
+            }
         };
+        self.size = slice;
+        self.reserve -= slice;
+        if !has_volume(self.reserve) {
+            self.reserve = 0.0;
+        }
+        Some(self)
+    }
+
+    pub fn client_order_id(&self) -> Option<&str> {
+        self.client_order_id.as_deref()
+    }
+
+    pub fn cumulative_filled(&self) -> f64 {
+        self.cumulative_filled
+    }
+
+    // Volume weighted over every fill so far, 0 while nothing filled
+    pub fn avg_fill_price(&self) -> f64 {
+        self.avg_fill_price
+    }
+
+    fn record_fill(&mut self, size: f64, price: f64) {
+        let cumulative_filled = self.cumulative_filled + size;
+        self.avg_fill_price =
+            (self.avg_fill_price * self.cumulative_filled + price * size) / cumulative_filled;
+        self.cumulative_filled = cumulative_filled;
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn is_due(&self, now: i64) -> bool {
+        self.not_before.is_none_or(|not_before| not_before <= now)
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    pub fn owner(&self) -> Uuid {
+        self.owner
     }
 }
 
+// A read-only copy of a resting order handed out to clients
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderView {
+    pub id: Uuid,
+    pub owner: Uuid,
+    pub order_type: OrderType,
+    pub price: f64,
+    pub size: f64,
+    pub timestamp: i64,
+    pub visibility: Visibility,
+}
+
 impl fmt::Display for Order {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -55,40 +921,163 @@ impl fmt::Display for Order {
 
 // A group of orders at a certain price level
 // a bucket of orders that are of different sizes sitting on the same price level
-struct Limit {
+#[derive(Clone)]
+pub struct Limit {
     id: Uuid,
-    price: f64,
-    orders: Vec<Order>,
+    pub(crate) price: f64,
+    pub(crate) orders: Vec<Order>,
+    // Only displayed orders count towards the total volume, hidden size is tracked apart
     total_volume: f64,
+    hidden_volume: f64,
+    // When an order was last added to, taken from or filled at this level
+    last_updated: i64,
 }
 
 impl Limit {
-    fn new(price: f64) -> Self {
-        return Self {
+    pub fn new(price: f64) -> Self {
+        Self {
             id: Uuid::new_v4(),
             price,
             orders: vec![],
             total_volume: 0.0,
+            hidden_volume: 0.0,
+            last_updated: 0,
+        }
+    }
+
+    fn adjust_volume(&mut self, visibility: Visibility, delta: f64) {
+        let volume = match visibility {
+            Visibility::Displayed => &mut self.total_volume,
+            Visibility::Hidden => &mut self.hidden_volume,
         };
+        *volume += delta;
+        if !has_volume(*volume) {
+            *volume = 0.0;
+        }
+    }
+
+    // Whether there is anything an incoming order could match against, hidden or not
+    fn has_liquidity(&self) -> bool {
+        has_volume(self.total_volume) || has_volume(self.hidden_volume)
     }
 
-    fn add_order(&mut self, mut o: Order) {
+    // Orders are kept sorted by sequence so the queue is always in time priority, a freshly
+    // sequenced order simply goes to the back
+    pub fn add_order(&mut self, mut o: Order) {
         o.limit_id = Some(self.id);
-        self.total_volume += o.size;
-        self.orders.push(o);
+        self.adjust_volume(o.visibility, o.size);
+        let position = self.orders.partition_point(|x| x.sequence <= o.sequence);
+        self.orders.insert(position, o);
     }
 
-    fn remove_order(&mut self, order_id: Uuid) -> Result<(), String> {
+    // Removal keeps the remaining orders in arrival order so time priority is preserved
+    pub fn remove_order(&mut self, order_id: Uuid) -> Result<Order, OrderBookError> {
         let index = self.orders.iter().position(|x: &Order| x.id == order_id);
         match index {
             Some(i) => {
-                let mut removed_order = self.orders.swap_remove(i);
+                let mut removed_order = self.orders.remove(i);
                 removed_order.limit_id = None;
-                self.total_volume -= removed_order.size;
-                Ok(())
+                self.adjust_volume(removed_order.visibility, -removed_order.size);
+                Ok(removed_order)
+            }
+            None => Err(OrderBookError::OrderNotFound(order_id)),
+        }
+    }
+
+    // Shrinks an order in place so it keeps its position in the queue
+    fn reduce_order(&mut self, order_id: Uuid, reduce_by: f64) -> Result<f64, OrderBookError> {
+        let order = self
+            .orders
+            .iter_mut()
+            .find(|x| x.id == order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        let reduce_by = reduce_by.min(order.size);
+        order.size -= reduce_by;
+        let (visibility, remaining) = (order.visibility, order.size);
+        self.adjust_volume(visibility, -reduce_by);
+        Ok(remaining)
+    }
+
+    fn next_order_to_fill(&self) -> Option<&Order> {
+        self.next_to_fill(&[]).map(|i| &self.orders[i])
+    }
+
+    // Displayed orders are filled before hidden ones regardless of their arrival. Orders in
+    // `skipped` are passed over.
+    fn next_to_fill(&self, skipped: &[Uuid]) -> Option<usize> {
+        self.orders
+            .iter()
+            .position(|o| o.visibility == Visibility::Displayed && !skipped.contains(&o.id))
+            .or_else(|| self.orders.iter().position(|o| !skipped.contains(&o.id)))
+    }
+
+    // Fills the incoming order against the resting orders in arrival order, returning the
+    // matches and the resting orders that got completely filled. With a minimum increment a
+    // partial fill never leaves a resting order smaller than it, the dust policy decides
+    // what happens instead. Fills happen at `trade_price`, the level's price unless given.
+    fn fill(
+        &mut self,
+        order: &mut Order,
+        min_increment: Option<(f64, DustPolicy)>,
+        trade_price: Option<f64>,
+    ) -> (Vec<Match>, Vec<Order>) {
+        let price = trade_price.unwrap_or(self.price);
+        let mut matches = vec![];
+        let mut filled_orders = vec![];
+        let mut skipped = vec![];
+
+        while has_volume(order.size) {
+            let Some(i) = self.next_to_fill(&skipped) else {
+                break;
+            };
+            let resting_order = &mut self.orders[i];
+            let mut size_filled = order.size.min(resting_order.size);
+            let remainder = resting_order.size - size_filled;
+            if let Some((min_increment, policy)) = min_increment
+                && has_volume(remainder)
+                && remainder < min_increment - VOLUME_EPSILON
+            {
+                match policy {
+                    DustPolicy::FillResting => size_filled = resting_order.size,
+                    DustPolicy::SkipResting => {
+                        skipped.push(resting_order.id);
+                        continue;
+                    }
+                }
+            }
+            order.size = (order.size - size_filled).max(0.0);
+            resting_order.size -= size_filled;
+            resting_order.record_fill(size_filled, price);
+            order.record_fill(size_filled, price);
+            let (resting_order_id, visibility, remaining) = (
+                resting_order.id,
+                resting_order.visibility,
+                resting_order.size,
+            );
+            self.adjust_volume(visibility, -size_filled);
+
+            let (ask_id, bid_id) = match order.order_type {
+                OrderType::Bid => (resting_order_id, order.id),
+                OrderType::Ask => (order.id, resting_order_id),
+            };
+            matches.push(Match {
+                ask_id,
+                bid_id,
+                size_filled,
+                price,
+            });
+
+            if !has_volume(remaining) {
+                // Drop whatever residue the order was left with along with it
+                self.adjust_volume(visibility, -remaining);
+                let mut filled_order = self.orders.remove(i);
+                filled_order.size = 0.0;
+                filled_order.limit_id = None;
+                filled_orders.push(filled_order);
             }
-            None => Err(format!("Could not find order by id {order_id}")),
         }
+
+        (matches, filled_orders)
     }
 }
 
@@ -102,115 +1091,8026 @@ impl fmt::Display for Limit {
     }
 }
 
-struct OrderBook {
-    limits: HashMap<OrderType, Vec<Limit>>,
-    limits_by_price: HashMap<OrderType, HashMap<String, usize>>,
+// How much a market order is for, either an amount of the base asset ("buy 2 BTC") or an
+// amount of the quote asset to spend or receive ("buy $50k worth")
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeSpec {
+    Base(f64),
+    Quote(f64),
 }
 
-impl OrderBook {
-    fn new() -> Self {
-        let mut limits: HashMap<OrderType, Vec<Limit>> = HashMap::new();
-        let mut limits_by_price: HashMap<OrderType, HashMap<String, usize>> = HashMap::new();
+// What happens when a partial fill would leave a resting order with less than the minimum
+// fill increment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DustPolicy {
+    // The resting order is filled completely, giving the incoming order the remainder on top
+    // of what it asked for
+    #[default]
+    FillResting,
+    // The resting order is left untouched and matching moves on to the next one
+    SkipResting,
+}
 
-        for e in vec![OrderType::Bid, OrderType::Ask] {
-            limits.insert(e, vec![]);
-            limits_by_price.insert(e, HashMap::new());
-        }
+// What happens to a market order that arrives while the opposite side of the book is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarketOrderFallback {
+    // The order is dropped and an empty set of matches is returned
+    #[default]
+    ReturnUnfilled,
+    Reject,
+    // The order rests as a limit order at the last traded price, and is rejected if nothing
+    // traded yet
+    RestAtReferencePrice,
+}
 
-        OrderBook {
-            limits,
-            limits_by_price,
-        }
+// What the mid falls back to while one side of the book is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MidFallback {
+    // No mid without both sides
+    #[default]
+    Strict,
+    LastTrade,
+    // The price set with `OrderBook::set_mark_price`
+    Mark,
+}
+
+// How a rate instrument's yield (in percent) maps to the price the book stores. Both are
+// decreasing in the yield, so a higher price is always a lower yield and matching on
+// prices stays correct.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YieldConvention {
+    // Price is par minus the yield, e.g. 100 - 4.25 = 95.75 for interest rate futures
+    Index { par: f64 },
+    // Bank discount basis of a bill: face * (1 - yield / 100 * days / 360)
+    Discount { face: f64, days_to_maturity: u32 },
+}
+
+impl YieldConvention {
+    pub fn yield_to_price(&self, yield_pct: f64) -> f64 {
+        match *self {
+            YieldConvention::Index { par } => par - yield_pct,
+            YieldConvention::Discount {
+                face,
+                days_to_maturity,
+            } => face * (1.0 - yield_pct / 100.0 * days_to_maturity as f64 / 360.0),
+        }
+    }
+
+    pub fn price_to_yield(&self, price: f64) -> f64 {
+        match *self {
+            YieldConvention::Index { par } => par - price,
+            YieldConvention::Discount {
+                face,
+                days_to_maturity,
+            } => (1.0 - price / face) * 360.0 / days_to_maturity as f64 * 100.0,
+        }
+    }
+}
+
+// What happens to a limit order priced exactly at the opposite touch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TouchPolicy {
+    // Trades against the touch like any other crossing order
+    #[default]
+    Match,
+    // Rests without trading, which leaves the book locked
+    Rest,
+    // Refused, for venues that forbid locked markets
+    Reject,
+}
+
+// What happens to an amend that moves a resting order through the opposite touch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmendCrossPolicy {
+    // The amended order matches as an incoming limit order and rests whatever is left
+    #[default]
+    Execute,
+    Reject,
+    // The new price is pulled back to one tick short of the opposite touch so the order
+    // rests without trading
+    Clamp,
+}
+
+// Price bands with their own tick size, as (lowest price of the band, tick size). Band tick
+// sizes have to be whole multiples of the book's base tick size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TickSchedule {
+    bands: Vec<(f64, f64)>,
+}
+
+impl TickSchedule {
+    pub fn new(mut bands: Vec<(f64, f64)>) -> Self {
+        bands.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { bands }
+    }
+
+    // Tick size of the highest band starting at or below the price, None below every band
+    pub fn tick_size_at(&self, price: f64) -> Option<f64> {
+        self.bands
+            .iter()
+            .rev()
+            .find(|(from_price, _)| price >= *from_price)
+            .map(|&(_, tick_size)| tick_size)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderBookConfig {
+    // Every price has to be a whole multiple of the tick size
+    pub tick_size: f64,
+    // Coarser tick sizes for higher prices, None to use `tick_size` everywhere
+    pub tick_schedule: Option<TickSchedule>,
+    // Lowest accepted price (inclusive). Instruments such as spreads can trade at or below
+    // zero so this is allowed to be negative.
+    pub min_price: f64,
+    // Every order size has to be a whole multiple of the lot size
+    pub lot_size: f64,
+    // Hard fat finger limits on any order's price (inclusive) and size, whatever the
+    // reference price. None for no limit.
+    pub max_price: Option<f64>,
+    pub max_size: Option<f64>,
+    pub fee_schedule: FeeSchedule,
+    pub market_order_fallback: MarketOrderFallback,
+    // Most resting size (displayed and hidden) a single price level may hold, None for no cap
+    pub max_level_volume: Option<f64>,
+    // Most distinct prices a side may hold. Past it new orders are only accepted at prices
+    // that already have orders, None for no cap. Crossing limit orders are let through and
+    // whatever is left of them may still open a level.
+    pub max_levels_per_side: Option<usize>,
+    // Anti-flicker rule: how long (milliseconds) an order has to rest before its owner may
+    // cancel or move it, None for no minimum. Cancels by the engine itself (expiry, OCO,
+    // sessions, market maker protection) are exempt.
+    pub min_resting_time_ms: Option<i64>,
+    // Largest fraction a market order may move the price away from the reference price
+    // (the last trade, or the touch before anything traded), None for no band
+    pub price_band: Option<f64>,
+    // Runs the book as a frequent batch auction: orders only accumulate and `tick` uncrosses
+    // them all at a single price once this many seconds passed, None for continuous trading
+    pub batch_interval: Option<i64>,
+    // Rolling window (seconds) the per-account activity stats cover
+    pub activity_window_secs: i64,
+    // Smallest remainder a partial fill may leave behind on a resting order, None for any
+    pub min_fill_increment: Option<f64>,
+    pub dust_policy: DustPolicy,
+    // Largest fraction a single incoming order may move the last trade price before the
+    // book cools down and rejects aggressive orders for `cooldown_secs`, None for no cooldown
+    pub cooldown_move: Option<f64>,
+    pub cooldown_secs: i64,
+    // How many top of book samples are kept, the oldest are dropped first. 0 records none.
+    pub top_history_capacity: usize,
+    // How many completely filled orders, resting or incoming, are kept for post-trade
+    // queries, the oldest are dropped first. 0 keeps none.
+    pub filled_order_capacity: usize,
+    // How many idempotency keys are remembered per account, the oldest are forgotten first
+    pub idempotency_keys_per_owner: usize,
+    // Inverse contracts (e.g. BTC-USD perpetuals sized in USD) are worth size / price of the
+    // settlement currency instead of size * price. This applies to every notional the book
+    // computes: VWAPs, the minimum notional, value at the touch and fees. Price and size
+    // precision stay independent through `tick_size` and `lot_size`.
+    pub inverse: bool,
+    // Smallest notional (see `inverse`) a new limit order may have, None for no minimum.
+    // Remainders of partially filled orders may rest below it.
+    pub min_notional: Option<f64>,
+    // Order entry actions (placing and amending orders, cancels are free) each account may
+    // take, None for no limit
+    pub order_rate_limit: Option<RateLimit>,
+    // How an aggressive order that would trade against resting orders of the same account
+    // is handled, None to let it self-trade
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    // How long (seconds) an RFQ quote can be accepted for, and the largest fraction the
+    // book's price for the size may have moved away from the quote by then
+    pub rfq_validity_secs: i64,
+    pub rfq_tolerance: f64,
+    pub amend_cross_policy: AmendCrossPolicy,
+    pub touch_policy: TouchPolicy,
+    // Lets orders be entered and prices be read as yields, None for a price quoted book
+    pub yield_convention: Option<YieldConvention>,
+    // Reference price the mid and the metrics built on it use for a one-sided book
+    pub mid_fallback: MidFallback,
+    // Pulls every quote of an account whose resting orders fill too fast and blocks new
+    // ones until `reset_mmp`, None for no protection
+    pub market_maker_protection: Option<MarketMakerProtection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    // The whole aggressive order is rejected before anything trades, however many levels
+    // of other accounts' liquidity it would have taken first
+    RejectAggressor,
+}
+
+// Who or what took an order off the book, everything but User is the engine's doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    User,
+    Expired,
+    // The linked OCO order filled completely
+    OcoSibling,
+    MmpTrip,
+    SessionEnd,
+    // The session the order came in on disconnected
+    Disconnect,
+    // What an IOC, FOK or market order couldn't fill on arrival, it never rested
+    IocRemainder,
+    // An L2 feed update replaced or removed the order's level
+    Feed,
+}
+
+// Trips when an account's resting orders get filled more than `max_fills` times or for more
+// than `max_volume` within `window_secs`, e.g. when a spike runs through a market maker's
+// stale quotes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarketMakerProtection {
+    pub max_fills: u32,
+    pub max_volume: f64,
+    pub window_secs: i64,
+}
+
+// Token bucket of `max_actions` tokens that refills at `max_actions` per `window_secs`,
+// so bursts up to the bucket size are fine as long as the average rate stays below it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub max_actions: u32,
+    pub window_secs: i64,
+}
+
+impl Default for OrderBookConfig {
+    fn default() -> Self {
+        Self {
+            tick_size: 0.01,
+            tick_schedule: None,
+            min_price: 0.01,
+            lot_size: 0.00000001,
+            max_price: None,
+            max_size: None,
+            fee_schedule: FeeSchedule::default(),
+            market_order_fallback: MarketOrderFallback::default(),
+            max_level_volume: None,
+            max_levels_per_side: None,
+            min_resting_time_ms: None,
+            price_band: None,
+            batch_interval: None,
+            activity_window_secs: 60,
+            min_fill_increment: None,
+            dust_policy: DustPolicy::default(),
+            cooldown_move: None,
+            cooldown_secs: 5,
+            top_history_capacity: 1024,
+            filled_order_capacity: 0,
+            idempotency_keys_per_owner: 1024,
+            inverse: false,
+            min_notional: None,
+            order_rate_limit: None,
+            self_trade_prevention: None,
+            rfq_validity_secs: 5,
+            rfq_tolerance: 0.001,
+            amend_cross_policy: AmendCrossPolicy::default(),
+            touch_policy: TouchPolicy::default(),
+            yield_convention: None,
+            mid_fallback: MidFallback::default(),
+            market_maker_protection: None,
+        }
+    }
+}
+
+// The best prices right after one of them changed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopSample {
+    pub timestamp: i64,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+}
+
+// Indicative price for taking `size` on `side` off the book, good until `expires_at`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RfqQuote {
+    pub quote_id: Uuid,
+    pub owner: Uuid,
+    pub side: OrderType,
+    pub size: f64,
+    pub price: f64,
+    pub expires_at: i64,
+}
+
+// What a conditional order waits for before it is sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trigger {
+    // The last trade reaching the price against the order, at or above it for a buy and at
+    // or below it for a sell. Sends a market order.
+    Stop(f64),
+    // The opposite touch reaching the price in the order's favor, the ask at or below it for
+    // a buy and the bid at or above it for a sell. Posts a limit order at the limit price, or
+    // sends a market order without one.
+    IfTouched {
+        trigger_price: f64,
+        limit_price: Option<f64>,
+    },
+}
+
+// How far back the tape is looked at to estimate the rate at which volume trades
+pub const FILL_RATE_LOOKBACK_SECS: i64 = 300;
+
+pub struct OrderBook {
+    config: OrderBookConfig,
+    pub(crate) limits: HashMap<OrderType, Vec<Limit>>,
+    // Limits are keyed by their price expressed in ticks so that prices which are equal on
+    // the tick grid always end up in the same limit regardless of float representation
+    limits_by_price: HashMap<OrderType, HashMap<i64, usize>>,
+    // Where each resting order lives so it can be found without scanning every limit
+    order_index: HashMap<Uuid, (OrderType, usize)>,
+    // One-cancels-other pairs, stored in both directions
+    pub(crate) oco_links: HashMap<Uuid, Uuid>,
+    // Resting order ids of each account
+    orders_by_owner: HashMap<Uuid, HashSet<Uuid>>,
+    // Server order id of every resting order that came with a client order id, by owner
+    // and client order id
+    client_order_ids: HashMap<(Uuid, String), Uuid>,
+    // Tick of the best non-empty limit of each side, kept up to date on every mutation so
+    // the top of the book never has to be searched for
+    best_ticks: HashMap<OrderType, Option<i64>>,
+    pub(crate) next_sequence: u64,
+    // Every trade in the order it happened
+    trades: Vec<Trade>,
+    // While an auction runs orders only accumulate, nothing matches until the book is uncrossed
+    auction_in_progress: bool,
+    // When the order that opened the current batch arrived, only used in batch auction mode
+    batch_started_at: Option<i64>,
+    // Midpoint pegged orders in arrival order. They have no price of their own, they always
+    // trade at whatever the mid is at the time, so they live outside of the limits and never
+    // show up in depth.
+    midpoint_orders: HashMap<OrderType, Vec<Order>>,
+    clock: Arc<dyn Clock>,
+    risk_check: Arc<dyn RiskCheck>,
+    // Set while a recorded command is replayed so everything it does is stamped with the
+    // original time rather than the clock's
+    timestamp_override: Option<i64>,
+    pub(crate) subscribers: Vec<mpsc::Sender<BookEvent>>,
+    pub(crate) bounded_subscribers: Vec<Arc<EventBuffer>>,
+    pub(crate) dropped_events: u64,
+    // Timestamped adds, cancels and trades of each account within the activity window
+    activity: HashMap<Uuid, Vec<(i64, Activity)>>,
+    // Stop and if-touched orders waiting for their trigger, in arrival order
+    pending_stops: Vec<(Trigger, Order)>,
+    // Limit orders submitted ahead of their not_before time, with their limit price
+    delayed_orders: Vec<(f64, Order)>,
+    // Until when aggressive orders are rejected after a large price move
+    cooldown_until: Option<i64>,
+    // Best prices each time either of them changed, oldest first
+    top_history: VecDeque<TopSample>,
+    filled_orders: VecDeque<Order>,
+    mark_price: Option<f64>,
+    // File every trade is appended to, and why it stopped if writing to it failed
+    pub(crate) trade_log: Option<File>,
+    pub(crate) trade_log_error: Option<io::Error>,
+    // Tokens left in each account's order entry bucket and when it was last refilled
+    rate_buckets: HashMap<Uuid, (f64, i64)>,
+    // Volume flow through each price level this session, keyed by side and tick
+    level_stats: HashMap<(OrderType, i64), LevelFillStats>,
+    // Quotes handed out that were neither accepted nor found expired yet
+    rfq_quotes: HashMap<Uuid, RfqQuote>,
+    cancel_reasons: HashMap<Uuid, CancelReason>,
+    // Owner of each connected session
+    sessions: HashMap<Uuid, Uuid>,
+    // Maker fills (timestamp, size) of each account inside the protection window
+    mmp_fills: HashMap<Uuid, Vec<(i64, f64)>>,
+    // Accounts whose protection tripped, their quotes get pulled once matching is done
+    mmp_tripped: HashSet<Uuid>,
+    // Order id and matches of each keyed submission by owner and key, and every owner's
+    // keys oldest first so the retention can be enforced
+    idempotent_results: HashMap<(Uuid, String), (Uuid, Vec<Match>)>,
+    idempotency_keys: HashMap<Uuid, VecDeque<String>>,
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::with_config(OrderBookConfig::default())
+    }
+
+    pub fn with_config(config: OrderBookConfig) -> Self {
+        // In batch auction mode the book is always collecting orders for the next batch
+        let auction_in_progress = config.batch_interval.is_some();
+        let mut limits: HashMap<OrderType, Vec<Limit>> = HashMap::new();
+        let mut limits_by_price: HashMap<OrderType, HashMap<i64, usize>> = HashMap::new();
+
+        let mut best_ticks: HashMap<OrderType, Option<i64>> = HashMap::new();
+        let mut midpoint_orders: HashMap<OrderType, Vec<Order>> = HashMap::new();
+
+        for e in [OrderType::Bid, OrderType::Ask] {
+            limits.insert(e, vec![]);
+            limits_by_price.insert(e, HashMap::new());
+            best_ticks.insert(e, None);
+            midpoint_orders.insert(e, vec![]);
+        }
+
+        OrderBook {
+            config,
+            limits,
+            limits_by_price,
+            order_index: HashMap::new(),
+            oco_links: HashMap::new(),
+            orders_by_owner: HashMap::new(),
+            client_order_ids: HashMap::new(),
+            best_ticks,
+            next_sequence: 1,
+            trades: vec![],
+            auction_in_progress,
+            batch_started_at: None,
+            midpoint_orders,
+            clock: Arc::new(SystemClock),
+            risk_check: Arc::new(NoRiskCheck),
+            timestamp_override: None,
+            subscribers: vec![],
+            bounded_subscribers: vec![],
+            dropped_events: 0,
+            activity: HashMap::new(),
+            pending_stops: vec![],
+            delayed_orders: vec![],
+            cooldown_until: None,
+            top_history: VecDeque::new(),
+            filled_orders: VecDeque::new(),
+            mark_price: None,
+            trade_log: None,
+            trade_log_error: None,
+            rate_buckets: HashMap::new(),
+            level_stats: HashMap::new(),
+            rfq_quotes: HashMap::new(),
+            cancel_reasons: HashMap::new(),
+            sessions: HashMap::new(),
+            mmp_fills: HashMap::new(),
+            mmp_tripped: HashSet::new(),
+            idempotent_results: HashMap::new(),
+            idempotency_keys: HashMap::new(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_risk_check(mut self, risk_check: Arc<dyn RiskCheck>) -> Self {
+        self.risk_check = risk_check;
+        self
+    }
+
+    fn check_risk(&self, order: &Order) -> Result<(), OrderBookError> {
+        self.risk_check
+            .check(order, self)
+            .map_err(OrderBookError::RiskRejected)
+    }
+
+    pub fn now(&self) -> i64 {
+        self.timestamp_override.unwrap_or_else(|| self.clock.now())
+    }
+
+    pub fn now_millis(&self) -> i64 {
+        self.timestamp_override
+            .map(|timestamp| timestamp * 1000)
+            .unwrap_or_else(|| self.clock.now_millis())
+    }
+
+    // Runs `f` with every timestamp the book hands out pinned to `timestamp`
+    pub(crate) fn at_timestamp<T>(&mut self, timestamp: i64, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.timestamp_override = Some(timestamp);
+        let result = f(self);
+        self.timestamp_override = None;
+        result
+    }
+
+    // Validates the price and converts it to its position on the tick grid. Ticks are signed
+    // so prices below zero map to negative ticks.
+    fn price_to_tick(&self, price: f64) -> Result<i64, OrderBookError> {
+        if !price.is_finite() {
+            return Err(OrderBookError::InvalidPrice(price));
+        }
+
+        // The band's tick decides whether the price is valid, the grid itself stays on the
+        // base tick size so all bands share one key space
+        let band_tick_size = self.tick_size_at(price);
+        if ((price / band_tick_size) - (price / band_tick_size).round()).abs() > 1e-9 {
+            return Err(OrderBookError::PriceOffTick {
+                price,
+                tick_size: band_tick_size,
+            });
+        }
+
+        let tick_size = self.config.tick_size;
+        let tick = (price / tick_size).round() as i64;
+        if self.tick_to_price(tick) < self.config.min_price - tick_size * 1e-9 {
+            return Err(OrderBookError::PriceBelowMinimum {
+                price,
+                min_price: self.config.min_price,
+            });
+        }
+        if let Some(max_price) = self.config.max_price
+            && self.tick_to_price(tick) > max_price + tick_size * 1e-9
+        {
+            return Err(OrderBookError::PriceAboveMaximum { price, max_price });
+        }
+        Ok(tick)
+    }
+
+    // Tick size that applies at the given price, the schedule's band if there is one
+    pub fn tick_size_at(&self, price: f64) -> f64 {
+        self.config
+            .tick_schedule
+            .as_ref()
+            .and_then(|schedule| schedule.tick_size_at(price))
+            .unwrap_or(self.config.tick_size)
+    }
+
+    // Rounds the price to the nearest valid price of its band
+    pub fn round_to_tick(&self, price: f64) -> f64 {
+        let tick_size = self.tick_size_at(price);
+        (price / tick_size).round() * tick_size
+    }
+
+    // The price a yield stands for, rounded to the nearest valid price
+    pub fn yield_to_price(&self, yield_pct: f64) -> Result<f64, OrderBookError> {
+        let convention = self
+            .config
+            .yield_convention
+            .ok_or(OrderBookError::NoYieldConvention)?;
+        Ok(self.round_to_tick(convention.yield_to_price(yield_pct)))
+    }
+
+    pub fn price_to_yield(&self, price: f64) -> Result<f64, OrderBookError> {
+        let convention = self
+            .config
+            .yield_convention
+            .ok_or(OrderBookError::NoYieldConvention)?;
+        Ok(convention.price_to_yield(price))
+    }
+
+    fn tick_to_price(&self, tick: i64) -> f64 {
+        tick as f64 * self.config.tick_size
+    }
+
+    fn validate_size(&self, size: f64) -> Result<(), OrderBookError> {
+        if !size.is_finite() || size <= 0.0 {
+            return Err(OrderBookError::InvalidSize(size));
+        }
+
+        let lot_size = self.config.lot_size;
+        if ((size / lot_size) - (size / lot_size).round()).abs() > 1e-6 {
+            return Err(OrderBookError::SizeOffLot { size, lot_size });
+        }
+        if let Some(max_size) = self.config.max_size
+            && size > max_size + VOLUME_EPSILON
+        {
+            return Err(OrderBookError::SizeAboveMaximum { size, max_size });
+        }
+        Ok(())
+    }
+
+    // What `size` at `price` is worth, size * price or size / price for an inverse contract
+    pub fn notional(&self, price: f64, size: f64) -> f64 {
+        if self.config.inverse {
+            size / price
+        } else {
+            size * price
+        }
+    }
+
+    fn validate_notional(&self, price: f64, order: &Order) -> Result<(), OrderBookError> {
+        let Some(min_notional) = self.config.min_notional else {
+            return Ok(());
+        };
+        let notional = self.notional(price, order.size).abs();
+        if order.cumulative_filled == 0.0 && notional < min_notional - 1e-9 {
+            return Err(OrderBookError::NotionalBelowMinimum {
+                notional,
+                min_notional,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &OrderBookConfig {
+        &self.config
+    }
+
+    // add_order with the price given as text, see Order::from_decimal_str
+    pub fn add_order_decimal_str(
+        &mut self,
+        price: &str,
+        order: Order,
+    ) -> Result<(), OrderBookError> {
+        let price: Decimal = price.parse().map_err(OrderBookError::InvalidDecimal)?;
+        self.add_order(price.to_f64(), order)
+    }
+
+    pub fn add_order(&mut self, price: f64, order: Order) -> Result<(), OrderBookError> {
+        self.check_mmp(order.owner)?;
+        self.check_session(&order)?;
+        self.check_risk(&order)?;
+        self.check_touch_policy(&order, price)?;
+        self.check_level_count(order.order_type, price)?;
+        self.rest_order(price, order)?;
+        self.trigger_stops();
+        Ok(())
+    }
+
+    // Puts the order on the book without matching it or activating conditional orders the
+    // new touch may trigger, which is up to the caller
+    fn rest_order(&mut self, price: f64, mut order: Order) -> Result<(), OrderBookError> {
+        if self.order_index.contains_key(&order.id) {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        if let Some(client_order_id) = &order.client_order_id
+            && self
+                .client_order_ids
+                .contains_key(&(order.owner, client_order_id.clone()))
+        {
+            return Err(OrderBookError::DuplicateClientOrderId(
+                client_order_id.clone(),
+            ));
+        }
+        self.validate_size(order.size)?;
+        let price_key = self.price_to_tick(price)?;
+        let price = self.tick_to_price(price_key);
+        self.validate_notional(price, &order)?;
+        if let Some(max_volume) = self.config.max_level_volume {
+            let resting_volume = self
+                .limit_at(order.order_type, price)
+                .map_or(0.0, |limit| limit.total_volume + limit.hidden_volume);
+            if resting_volume + order.size > max_volume + VOLUME_EPSILON {
+                return Err(OrderBookError::LevelVolumeExceeded { price, max_volume });
+            }
+        }
+        if let Some(peak) = order.peak
+            && order.size > peak
+        {
+            order.reserve += order.size - peak;
+            order.size = peak;
+        }
+        order.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        order.timestamp = self.now();
+        order.rested_at_millis = self.now_millis();
+        if self.config.batch_interval.is_some() && self.batch_started_at.is_none() {
+            self.batch_started_at = Some(order.timestamp);
+        }
+
+        let event = BookEvent::OrderAdded {
+            order_id: order.id,
+            side: order.order_type,
+            price,
+            size: order.size,
+            timestamp: order.timestamp,
+        };
+        let (order_type, owner, size) = (order.order_type, order.owner, order.size);
+        self.file_order(price_key, price, order)?;
+        self.level_stats_entry(order_type, price).added += size;
+        self.publish(event);
+        self.record_activity(owner, Activity::Add);
+        Ok(())
+    }
+
+    // Puts an order that already passed every check into its level and the lookup indices,
+    // keeping its sequence
+    fn file_order(
+        &mut self,
+        price_key: i64,
+        price: f64,
+        order: Order,
+    ) -> Result<(), OrderBookError> {
+        let order_type = order.order_type;
+        let limits = self
+            .limits
+            .get_mut(&order_type)
+            .expect("Did not find limits for order type");
+
+        let price_to_limit_idx_map = self
+            .limits_by_price
+            .get_mut(&order_type)
+            .expect("Did not find limits by price for order type");
+
+        let limit_idx = match price_to_limit_idx_map.get(&price_key) {
+            // We already have a limit for this price so we add the order to it
+            Some(&limit_idx) => limit_idx,
+            None => {
+                // These is no limit for this price yet so we need to create one
+                let new_limit_idx = limits.len();
+                limits.push(Limit::new(price));
+                price_to_limit_idx_map.insert(price_key, new_limit_idx);
+                new_limit_idx
+            }
+        };
+
+        let limit = limits
+            .get_mut(limit_idx)
+            .ok_or(OrderBookError::InvalidLimitIndex { limit_idx, price })?;
+        self.order_index.insert(order.id, (order_type, limit_idx));
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_order_ids
+                .insert((order.owner, client_order_id.clone()), order.id);
+        }
+        self.orders_by_owner
+            .entry(order.owner)
+            .or_default()
+            .insert(order.id);
+        limit.add_order(order);
+        self.refresh_best_price(order_type, limit_idx);
+        Ok(())
+    }
+
+    // Indices of the non-empty limits of one side ordered best price first, i.e. the order
+    // in which an incoming order on the other side would consume them. Limits holding only
+    // hidden orders are left out unless asked for.
+    fn limit_indices_by_priority(&self, order_type: OrderType, include_hidden: bool) -> Vec<usize> {
+        let limits = &self.limits[&order_type];
+        let mut indices: Vec<usize> = (0..limits.len())
+            .filter(|&i| {
+                has_volume(limits[i].total_volume) || (include_hidden && limits[i].has_liquidity())
+            })
+            .collect();
+        match order_type {
+            OrderType::Bid => indices.sort_by(|&a, &b| limits[b].price.total_cmp(&limits[a].price)),
+            OrderType::Ask => indices.sort_by(|&a, &b| limits[a].price.total_cmp(&limits[b].price)),
+        }
+        indices
+    }
+
+    fn limits_by_priority(&self, order_type: OrderType) -> Vec<&Limit> {
+        let limits = &self.limits[&order_type];
+        self.limit_indices_by_priority(order_type, false)
+            .into_iter()
+            .map(|i| &limits[i])
+            .collect()
+    }
+
+    // The limit sitting at the given price on one side, if there is one
+    fn limit_at(&self, order_type: OrderType, price: f64) -> Option<&Limit> {
+        let tick = self.price_to_tick(price).ok()?;
+        let &limit_idx = self.limits_by_price[&order_type].get(&tick)?;
+        self.limits[&order_type].get(limit_idx)
+    }
+
+    fn best_limit(&self, order_type: OrderType) -> Option<&Limit> {
+        let best_tick = self.best_ticks[&order_type]?;
+        let &limit_idx = self.limits_by_price[&order_type].get(&best_tick)?;
+        self.limits[&order_type].get(limit_idx)
+    }
+
+    // A single pass over the side to find the best non-empty limit, only needed when the
+    // cached best price got invalidated
+    fn scan_best_tick(&self, order_type: OrderType) -> Option<i64> {
+        let limits = self.limits[&order_type]
+            .iter()
+            .filter(|limit| has_volume(limit.total_volume));
+        let best_limit = match order_type {
+            OrderType::Bid => limits.max_by(|a, b| a.price.total_cmp(&b.price)),
+            OrderType::Ask => limits.min_by(|a, b| a.price.total_cmp(&b.price)),
+        }?;
+        Some(self.tick_of(best_limit.price))
+    }
+
+    // Limit prices are always on the tick grid so this is exact
+    fn tick_of(&self, price: f64) -> i64 {
+        (price / self.config.tick_size).round() as i64
+    }
+
+    // Has to be called whenever the volume of a limit changed. A limit that gained volume
+    // can only take over the top when it's priced better, and the top only has to be looked
+    // up again when the best limit itself ran dry. The limit's last update is stamped too.
+    fn refresh_best_price(&mut self, order_type: OrderType, limit_idx: usize) {
+        let now = self.now();
+        self.limits
+            .get_mut(&order_type)
+            .expect("Did not find limits for order type")[limit_idx]
+            .last_updated = now;
+        let limit = &self.limits[&order_type][limit_idx];
+        let tick = self.tick_of(limit.price);
+        let best_tick = self.best_ticks[&order_type];
+
+        let new_best_tick = if has_volume(limit.total_volume) {
+            match (order_type, best_tick) {
+                (_, None) => Some(tick),
+                (OrderType::Bid, Some(best)) => Some(best.max(tick)),
+                (OrderType::Ask, Some(best)) => Some(best.min(tick)),
+            }
+        } else if best_tick == Some(tick) {
+            self.scan_best_tick(order_type)
+        } else {
+            best_tick
+        };
+        self.best_ticks.insert(order_type, new_best_tick);
+        if new_best_tick != best_tick {
+            self.record_top_sample();
+        }
+
+        debug_assert_eq!(
+            self.best_ticks[&order_type],
+            self.scan_best_tick(order_type),
+            "Cached best price is out of sync with the book"
+        );
+        debug_assert!(
+            (self.total_volume(order_type) - self.recompute_volume(order_type)).abs() < 1e-6,
+            "Cached limit volumes are out of sync with the orders"
+        );
+    }
+
+    // Takes a resting order whose shown size got used up out of the indices, returning the
+    // next slice to rest when it is an iceberg with reserve left and archiving it otherwise
+    fn settle_filled(&mut self, filled_order: Order) -> Option<Order> {
+        self.forget_order(&filled_order);
+        if has_volume(filled_order.reserve) {
+            return filled_order.replenished();
+        }
+        self.archive_filled(filled_order);
+        None
+    }
+
+    fn archive_filled(&mut self, order: Order) {
+        let capacity = self.config.filled_order_capacity;
+        if capacity == 0 {
+            return;
+        }
+        if self.filled_orders.len() == capacity {
+            self.filled_orders.pop_front();
+        }
+        self.filled_orders.push_back(order);
+    }
+
+    // A completely filled order with its final fill totals, as long as the archive still
+    // holds it
+    pub fn filled_order(&self, order_id: Uuid) -> Option<&Order> {
+        self.filled_orders.iter().find(|o| o.id == order_id)
+    }
+
+    fn record_top_sample(&mut self) {
+        let capacity = self.config.top_history_capacity;
+        if capacity == 0 {
+            return;
+        }
+        if self.top_history.len() == capacity {
+            self.top_history.pop_front();
+        }
+        let best_price = |side| self.best_ticks[&side].map(|tick| self.tick_to_price(tick));
+        let sample = TopSample {
+            timestamp: self.now(),
+            bid: best_price(OrderType::Bid),
+            ask: best_price(OrderType::Ask),
+        };
+        self.top_history.push_back(sample);
+    }
+
+    pub fn top_history(&self) -> &VecDeque<TopSample> {
+        &self.top_history
+    }
+
+    // Standard deviation of the spread over the top of book samples taken within the last
+    // `window_secs` as of `now`. Every change of the touch counts once however long it
+    // lasted. None with fewer than two two-sided samples in the window.
+    pub fn spread_volatility(&self, window_secs: i64, now: i64) -> Option<f64> {
+        let since = now - window_secs;
+        let spreads: Vec<f64> = self
+            .top_history
+            .iter()
+            .filter(|sample| sample.timestamp >= since && sample.timestamp <= now)
+            .filter_map(|sample| Some(sample.ask? - sample.bid?))
+            .collect();
+        if spreads.len() < 2 {
+            return None;
+        }
+        let count = spreads.len() as f64;
+        let mean = spreads.iter().sum::<f64>() / count;
+        let variance = spreads
+            .iter()
+            .map(|spread| (spread - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        Some(variance.sqrt())
+    }
+
+    fn level_stats_entry(&mut self, side: OrderType, price: f64) -> &mut LevelFillStats {
+        let tick = self.tick_of(price);
+        self.level_stats.entry((side, tick)).or_default()
+    }
+
+    // Volume added to, filled at and cancelled from the level this session, None when
+    // nothing ever rested there
+    pub fn level_fill_stats(&self, side: OrderType, price: f64) -> Option<LevelFillStats> {
+        let tick = self.price_to_tick(price).ok()?;
+        self.level_stats.get(&(side, tick)).copied()
+    }
+
+    // A resting order with its fill progress, None once it left the book
+    pub fn get_order(&self, order_id: Uuid) -> Option<&Order> {
+        self.resting_order(order_id).or_else(|| {
+            self.midpoint_orders
+                .values()
+                .flatten()
+                .find(|o| o.id == order_id)
+        })
+    }
+
+    fn resting_order(&self, order_id: Uuid) -> Option<&Order> {
+        let &(order_type, limit_idx) = self.order_index.get(&order_id)?;
+        self.limits[&order_type]
+            .get(limit_idx)?
+            .orders
+            .iter()
+            .find(|o| o.id == order_id)
+    }
+
+    // Drops an order that left the book from the lookup indices
+    fn forget_order(&mut self, order: &Order) {
+        self.order_index.remove(&order.id);
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_order_ids
+                .remove(&(order.owner, client_order_id.clone()));
+        }
+        if let Some(order_ids) = self.orders_by_owner.get_mut(&order.owner) {
+            order_ids.remove(&order.id);
+            if order_ids.is_empty() {
+                self.orders_by_owner.remove(&order.owner);
+            }
+        }
+    }
+
+    pub(crate) fn view_order(&self, order_id: Uuid) -> Option<(OrderView, u64)> {
+        let &(order_type, limit_idx) = self.order_index.get(&order_id)?;
+        let limit = self.limits[&order_type].get(limit_idx)?;
+        let order = limit.orders.iter().find(|o| o.id == order_id)?;
+        let view = OrderView {
+            id: order.id,
+            owner: order.owner,
+            order_type: order.order_type,
+            price: limit.price,
+            size: order.size,
+            timestamp: order.timestamp,
+            visibility: order.visibility,
+        };
+        Some((view, order.sequence))
+    }
+
+    fn limit_for_order(&mut self, order_id: Uuid) -> Result<&mut Limit, OrderBookError> {
+        let &(order_type, limit_idx) = self
+            .order_index
+            .get(&order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        self.limits
+            .get_mut(&order_type)
+            .and_then(|limits| limits.get_mut(limit_idx))
+            .ok_or(OrderBookError::OrderNotFound(order_id))
+    }
+
+    pub fn cancel_order(&mut self, order_id: Uuid) -> Result<Order, OrderBookError> {
+        self.check_min_resting(order_id)?;
+        self.cancel_with_reason(order_id, CancelReason::User)
+    }
+
+    fn check_min_resting(&self, order_id: Uuid) -> Result<(), OrderBookError> {
+        let Some(min_resting_time_ms) = self.config.min_resting_time_ms else {
+            return Ok(());
+        };
+        match self.get_order(order_id) {
+            Some(order) if self.now_millis() - order.rested_at_millis < min_resting_time_ms => {
+                Err(OrderBookError::CancelTooSoon {
+                    order_id,
+                    min_resting_time_ms,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Why the engine took the order off the book, None when it wasn't cancelled
+    pub fn cancel_reason(&self, order_id: Uuid) -> Option<CancelReason> {
+        self.cancel_reasons.get(&order_id).copied()
+    }
+
+    fn cancel_with_reason(
+        &mut self,
+        order_id: Uuid,
+        reason: CancelReason,
+    ) -> Result<Order, OrderBookError> {
+        if let Some(order) = self.cancel_midpoint_order(order_id) {
+            self.cancel_reasons.insert(order_id, reason);
+            self.publish(BookEvent::OrderCancelled { order_id, reason });
+            self.record_activity(order.owner, Activity::Cancel);
+            return Ok(order);
+        }
+        let (price, order) = self.detach_order(order_id)?;
+        self.record_cancel(price, &order, reason);
+        Ok(order)
+    }
+
+    // The bookkeeping of a cancel for an order that has already been taken off the book
+    fn record_cancel(&mut self, price: f64, order: &Order, reason: CancelReason) {
+        let order_id = order.id;
+        self.level_stats_entry(order.order_type, price).cancelled += order.size;
+        self.unlink_oco(order_id);
+        self.cancel_reasons.insert(order_id, reason);
+        self.publish(BookEvent::OrderCancelled { order_id, reason });
+        self.record_activity(order.owner, Activity::Cancel);
+    }
+
+    // Cancels every resting order of the side, returning their ids oldest first. Meant for
+    // operators, so the minimum resting time doesn't hold it back.
+    pub fn cancel_side(&mut self, side: OrderType) -> Vec<Uuid> {
+        let mut orders: Vec<(u64, Uuid)> = self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter())
+            .map(|o| (o.sequence, o.id))
+            .collect();
+        orders.sort();
+
+        orders
+            .into_iter()
+            .filter_map(|(_, order_id)| {
+                self.cancel_with_reason(order_id, CancelReason::User)
+                    .ok()
+                    .map(|o| o.id)
+            })
+            .collect()
+    }
+
+    // A copy of the whole book to experiment on. It shares the clock and risk check, but
+    // has no subscribers and no trade log so nothing done to it leaks out of it.
+    pub(crate) fn detached_copy(&self) -> OrderBook {
+        OrderBook {
+            config: self.config.clone(),
+            limits: self.limits.clone(),
+            limits_by_price: self.limits_by_price.clone(),
+            order_index: self.order_index.clone(),
+            oco_links: self.oco_links.clone(),
+            orders_by_owner: self.orders_by_owner.clone(),
+            client_order_ids: self.client_order_ids.clone(),
+            best_ticks: self.best_ticks.clone(),
+            next_sequence: self.next_sequence,
+            trades: self.trades.clone(),
+            auction_in_progress: self.auction_in_progress,
+            batch_started_at: self.batch_started_at,
+            midpoint_orders: self.midpoint_orders.clone(),
+            clock: self.clock.clone(),
+            risk_check: self.risk_check.clone(),
+            timestamp_override: self.timestamp_override,
+            subscribers: vec![],
+            bounded_subscribers: vec![],
+            dropped_events: self.dropped_events,
+            activity: self.activity.clone(),
+            pending_stops: self.pending_stops.clone(),
+            delayed_orders: self.delayed_orders.clone(),
+            cooldown_until: self.cooldown_until,
+            top_history: self.top_history.clone(),
+            filled_orders: self.filled_orders.clone(),
+            mark_price: self.mark_price,
+            trade_log: None,
+            trade_log_error: None,
+            rate_buckets: self.rate_buckets.clone(),
+            level_stats: self.level_stats.clone(),
+            rfq_quotes: self.rfq_quotes.clone(),
+            cancel_reasons: self.cancel_reasons.clone(),
+            sessions: self.sessions.clone(),
+            mmp_fills: self.mmp_fills.clone(),
+            mmp_tripped: self.mmp_tripped.clone(),
+            idempotent_results: self.idempotent_results.clone(),
+            idempotency_keys: self.idempotency_keys.clone(),
+        }
+    }
+
+    // Runs several related reads (touch, depth, imbalance, ...) against one consistent state.
+    // Holding the view borrows the book, so a caller behind a lock keeps the lock for the
+    // whole closure instead of taking it once per read.
+    pub fn with_frozen<R>(&self, f: impl FnOnce(&FrozenBook) -> R) -> R {
+        f(&FrozenBook {
+            order_book: self,
+            sequence: self.next_sequence,
+            timestamp: self.now(),
+        })
+    }
+
+    pub fn side_snapshot(&self, side: OrderType) -> SideSnapshot {
+        let mut orders: Vec<(f64, Order)> = self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter().map(|o| (limit.price, o.clone())))
+            .collect();
+        orders.sort_by_key(|(_, o)| o.sequence);
+        SideSnapshot { side, orders }
+    }
+
+    // Replaces whatever rests on the snapshot's side with the snapshot, leaving the other
+    // side alone. Orders keep their original priority. The replaced orders are dropped
+    // without counting as cancels. Nothing changes when this book rejects any of the
+    // snapshot's orders.
+    pub fn restore_side(&mut self, snapshot: SideSnapshot) -> Result<(), OrderBookError> {
+        // A dry run on a detached copy finds any order that won't rest before the side is
+        // wiped, so a restore never stops half way
+        self.detached_copy()
+            .restore_side_unchecked(snapshot.clone())?;
+        self.restore_side_unchecked(snapshot)
+    }
+
+    fn restore_side_unchecked(&mut self, snapshot: SideSnapshot) -> Result<(), OrderBookError> {
+        let order_ids: Vec<Uuid> = self.limits[&snapshot.side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter().map(|o| o.id))
+            .collect();
+        for order_id in order_ids {
+            self.detach_order(order_id)?;
+            self.unlink_oco(order_id);
+        }
+        for (price, order) in snapshot.orders {
+            self.restore_order(price, order)?;
+        }
+        Ok(())
+    }
+
+    // Folds the other book's orders into this one, e.g. to reconcile a primary with its hot
+    // standby. Priority follows the original timestamps, so the resting orders of both books
+    // get renumbered in timestamp order with ties going to this book's orders. Conditional,
+    // delayed and midpoint orders are carried over as they are. Nothing changes when an id
+    // (or client order id) is already taken here, or when this book rejects any of the
+    // other book's orders.
+    pub fn merge(&mut self, other: OrderBook) -> Result<(), OrderBookError> {
+        // A dry run on detached copies finds any order that won't rest here before this book
+        // is touched, so a merge never stops half way
+        self.detached_copy()
+            .merge_unchecked(other.detached_copy())?;
+        self.merge_unchecked(other)
+    }
+
+    fn merge_unchecked(&mut self, other: OrderBook) -> Result<(), OrderBookError> {
+        let mut resting: Vec<(f64, Order)> = [OrderType::Bid, OrderType::Ask]
+            .into_iter()
+            .flat_map(|side| other.side_snapshot(side).orders)
+            .collect();
+        let is_taken = |order_id| {
+            self.order_index.contains_key(&order_id)
+                || self.pending_stops.iter().any(|(_, o)| o.id == order_id)
+                || self.delayed_orders.iter().any(|(_, o)| o.id == order_id)
+                || self
+                    .midpoint_orders
+                    .values()
+                    .flatten()
+                    .any(|o| o.id == order_id)
+        };
+        let other_orders = resting
+            .iter()
+            .map(|(_, o)| o)
+            .chain(other.pending_stops.iter().map(|(_, o)| o))
+            .chain(other.delayed_orders.iter().map(|(_, o)| o))
+            .chain(other.midpoint_orders.values().flatten());
+        for order in other_orders {
+            if is_taken(order.id) {
+                return Err(OrderBookError::DuplicateOrderId(order.id));
+            }
+            if let Some(client_order_id) = &order.client_order_id
+                && self
+                    .client_order_ids
+                    .contains_key(&(order.owner, client_order_id.clone()))
+            {
+                return Err(OrderBookError::DuplicateClientOrderId(
+                    client_order_id.clone(),
+                ));
+            }
+        }
+
+        // This book's orders are renumbered in place, the other book's ones on the way in
+        let mut priority: Vec<(i64, bool, u64, Uuid)> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter())
+            .map(|o| (o.timestamp, false, o.sequence, o.id))
+            .chain(
+                resting
+                    .iter()
+                    .map(|(_, o)| (o.timestamp, true, o.sequence, o.id)),
+            )
+            .collect();
+        priority.sort();
+        let sequences: HashMap<Uuid, u64> = priority
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, _, _, order_id))| (order_id, i as u64 + 1))
+            .collect();
+        for limit in self.limits.values_mut().flatten() {
+            for order in &mut limit.orders {
+                order.sequence = sequences[&order.id];
+            }
+            limit.orders.sort_by_key(|o| o.sequence);
+        }
+        for (_, order) in &mut resting {
+            order.sequence = sequences[&order.id];
+        }
+        let next_sequence = self.next_sequence.max(priority.len() as u64 + 1);
+        for (price, order) in resting {
+            self.restore_order(price, order)?;
+        }
+        self.next_sequence = next_sequence;
+
+        self.pending_stops.extend(other.pending_stops);
+        self.delayed_orders.extend(other.delayed_orders);
+        for (side, orders) in other.midpoint_orders {
+            self.midpoint_orders.entry(side).or_default().extend(orders);
+        }
+        self.oco_links.extend(other.oco_links);
+        self.trigger_stops();
+        Ok(())
+    }
+
+    // Rests a previously captured order with its original sequence, timestamp and resting
+    // clock. It rests again, so whatever reason it was cancelled for no longer applies.
+    pub(crate) fn restore_order(&mut self, price: f64, order: Order) -> Result<(), OrderBookError> {
+        self.cancel_reasons.remove(&order.id);
+        let (order_id, rested_at_millis) = (order.id, order.rested_at_millis);
+        let next_sequence = self.next_sequence.max(order.sequence + 1);
+        self.next_sequence = order.sequence;
+        let rested = self.at_timestamp(order.timestamp, |order_book| {
+            order_book.rest_order(price, order)
+        });
+        self.next_sequence = next_sequence;
+        rested?;
+
+        let &(side, limit_idx) = self
+            .order_index
+            .get(&order_id)
+            .expect("Did not find the restored order in the index");
+        let order = self
+            .limits
+            .get_mut(&side)
+            .expect("Did not find limits for order type")[limit_idx]
+            .orders
+            .iter_mut()
+            .find(|o| o.id == order_id)
+            .expect("Did not find the restored order at its level");
+        order.rested_at_millis = rested_at_millis;
+        Ok(())
+    }
+
+    // Puts a previously captured midpoint order back at the end of its side's queue with
+    // its original sequence and timestamp
+    #[cfg(feature = "binary-snapshot")]
+    pub(crate) fn restore_midpoint_order(&mut self, order: Order) -> Result<(), OrderBookError> {
+        self.validate_size(order.size)?;
+        let is_taken = self.order_index.contains_key(&order.id)
+            || self
+                .midpoint_orders
+                .values()
+                .flatten()
+                .any(|o| o.id == order.id);
+        if is_taken {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        self.next_sequence = self.next_sequence.max(order.sequence + 1);
+        self.midpoint_orders
+            .get_mut(&order.order_type)
+            .expect("Did not find midpoint orders for order type")
+            .push(order);
+        Ok(())
+    }
+
+    // Fills one side with a deterministic ladder for benchmarks and demos: `levels` prices
+    // `tick` apart starting at `start_price` and moving away from the spread, each holding
+    // `orders_per_level` orders. Everything goes through add_order, so the first order the
+    // book rejects stops the ladder there.
+    pub fn populate_synthetic(
+        &mut self,
+        side: OrderType,
+        start_price: f64,
+        tick: f64,
+        levels: usize,
+        size_per_order: f64,
+        orders_per_level: usize,
+    ) -> Result<(), OrderBookError> {
+        let step = match side {
+            OrderType::Bid => -tick,
+            OrderType::Ask => tick,
+        };
+        for level in 0..levels {
+            let price = self.round_to_tick(start_price + step * level as f64);
+            for _ in 0..orders_per_level {
+                self.add_order(price, Order::new(side, size_per_order))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Every resting order id across both sides, in no particular order
+    pub fn all_order_ids(&self) -> Vec<Uuid> {
+        self.order_index.keys().copied().collect()
+    }
+
+    // The server id of the owner's resting order with the given client order id
+    pub fn order_id_by_client_id(&self, owner: Uuid, client_order_id: &str) -> Option<Uuid> {
+        self.client_order_ids
+            .get(&(owner, client_order_id.to_string()))
+            .copied()
+    }
+
+    pub fn cancel_by_client_id(
+        &mut self,
+        owner: Uuid,
+        client_order_id: &str,
+    ) -> Result<Order, OrderBookError> {
+        let order_id = self
+            .order_id_by_client_id(owner, client_order_id)
+            .ok_or_else(|| OrderBookError::ClientOrderIdNotFound(client_order_id.to_string()))?;
+        self.cancel_order(order_id)
+    }
+
+    // Cancels the order at the front of the level's queue (the next one to fill), returning
+    // its id or None when nothing rests at the price
+    pub fn cancel_front(&mut self, side: OrderType, price: f64) -> Option<Uuid> {
+        let order_id = self.limit_at(side, price)?.next_order_to_fill()?.id;
+        self.cancel_order(order_id).ok().map(|order| order.id)
+    }
+
+    // Cancels the order at the back of the level's queue (the last one to fill)
+    pub fn cancel_back(&mut self, side: OrderType, price: f64) -> Option<Uuid> {
+        let orders = &self.limit_at(side, price)?.orders;
+        // Hidden orders queue behind every displayed one
+        let order_id = orders
+            .iter()
+            .rev()
+            .find(|o| o.visibility == Visibility::Hidden)
+            .or(orders.last())?
+            .id;
+        self.cancel_order(order_id).ok().map(|order| order.id)
+    }
+
+    // Cancels the owner's orders on one side resting deeper than the book's best
+    // `keep_levels` price levels, returning their ids in priority order
+    pub fn cancel_beyond(&mut self, owner: Uuid, side: OrderType, keep_levels: usize) -> Vec<Uuid> {
+        let limits = &self.limits[&side];
+        let kept_ticks: HashSet<i64> = self
+            .limit_indices_by_priority(side, true)
+            .into_iter()
+            .take(keep_levels)
+            .map(|limit_idx| self.tick_of(limits[limit_idx].price))
+            .collect();
+        let order_ids: Vec<Uuid> = self
+            .orders_by_owner(side, owner)
+            .into_iter()
+            .filter(|view| !kept_ticks.contains(&self.tick_of(view.price)))
+            .map(|view| view.id)
+            .collect();
+
+        order_ids
+            .into_iter()
+            .filter_map(|order_id| self.cancel_order(order_id).ok().map(|o| o.id))
+            .collect()
+    }
+
+    // Links two resting orders so that filling one cancels the other, e.g. the take-profit
+    // and stop-loss legs of a bracket. Partial fills shrink the sibling by the same fraction.
+    pub fn link_oco(&mut self, order_id: Uuid, sibling_id: Uuid) -> Result<(), OrderBookError> {
+        if order_id == sibling_id {
+            return Err(OrderBookError::InvalidOcoLink(order_id));
+        }
+        for id in [order_id, sibling_id] {
+            if !self.order_index.contains_key(&id) {
+                return Err(OrderBookError::OrderNotFound(id));
+            }
+            if self.oco_links.contains_key(&id) {
+                return Err(OrderBookError::OrderAlreadyLinked(id));
+            }
+        }
+
+        self.oco_links.insert(order_id, sibling_id);
+        self.oco_links.insert(sibling_id, order_id);
+        Ok(())
+    }
+
+    fn unlink_oco(&mut self, order_id: Uuid) {
+        if let Some(sibling_id) = self.oco_links.remove(&order_id) {
+            self.oco_links.remove(&sibling_id);
+        }
+    }
+
+    // Fills part of a resting order outside of the regular taker walk (e.g. in an auction),
+    // removing it from the book once nothing is left of it. An iceberg whose shown slice got
+    // used up rests its next slice just like in the taker walk.
+    fn fill_resting_order(
+        &mut self,
+        order_id: Uuid,
+        size: f64,
+        price: f64,
+    ) -> Result<(), OrderBookError> {
+        let &(order_type, limit_idx) = self
+            .order_index
+            .get(&order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        let limit = self.limit_for_order(order_id)?;
+        let order = limit
+            .orders
+            .iter_mut()
+            .find(|o| o.id == order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        order.record_fill(size, price);
+        let level_price = limit.price;
+        let remaining = limit.reduce_order(order_id, size)?;
+        let fill_event = limit
+            .orders
+            .iter()
+            .find(|o| o.id == order_id)
+            .map(|order| BookEvent::from_fill(order, size, price))
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        let refill = if has_volume(remaining) {
+            None
+        } else {
+            let filled_order = limit.remove_order(order_id)?;
+            self.settle_filled(filled_order)
+        };
+        self.level_stats_entry(order_type, level_price).filled += size;
+        self.publish(fill_event);
+        self.refresh_best_price(order_type, limit_idx);
+        if let Some(refill) = refill {
+            let _ = self.rest_order(level_price, refill);
+        }
+        self.reduce_oco_sibling(order_id, size);
+        Ok(())
+    }
+
+    // Replays fills computed elsewhere, e.g. by a primary this book replicates, onto the
+    // resting orders without matching anything itself. The side of each match that rests
+    // here is the maker, the taker needn't be known. Trades aren't recorded as the taker's
+    // details are missing. Nothing is applied unless every match fits a live order.
+    pub fn apply_matches(&mut self, matches: &[Match]) -> Result<(), OrderBookError> {
+        let mut makers = Vec::with_capacity(matches.len());
+        let mut needed: HashMap<Uuid, f64> = HashMap::new();
+        for m in matches {
+            let maker_id = match (
+                self.order_index.contains_key(&m.ask_id),
+                self.order_index.contains_key(&m.bid_id),
+            ) {
+                (true, false) => m.ask_id,
+                (false, true) => m.bid_id,
+                _ => {
+                    return Err(OrderBookError::MatchNotApplicable {
+                        ask_id: m.ask_id,
+                        bid_id: m.bid_id,
+                    });
+                }
+            };
+            let total = needed.entry(maker_id).or_default();
+            *total += m.size_filled;
+            let resting_size = self
+                .resting_order(maker_id)
+                .map_or(0.0, |o| o.size + o.reserve);
+            if !m.size_filled.is_finite()
+                || m.size_filled <= 0.0
+                || *total > resting_size + VOLUME_EPSILON
+            {
+                return Err(OrderBookError::MatchNotApplicable {
+                    ask_id: m.ask_id,
+                    bid_id: m.bid_id,
+                });
+            }
+            makers.push(maker_id);
+        }
+
+        for (m, maker_id) in matches.iter().zip(makers) {
+            self.fill_resting_order(maker_id, m.size_filled, m.price)?;
+        }
+        Ok(())
+    }
+
+    // Called after a resting order got filled by `size_filled` so its OCO sibling (if any)
+    // shrinks proportionally, or gets cancelled once the order is completely filled
+    fn reduce_oco_sibling(&mut self, order_id: Uuid, size_filled: f64) {
+        let Some(&sibling_id) = self.oco_links.get(&order_id) else {
+            return;
+        };
+        let Some(sibling_size) = self.resting_order(sibling_id).map(|o| o.size) else {
+            return;
+        };
+
+        let remaining = self.resting_order(order_id).map_or(0.0, |o| o.size);
+        if !has_volume(remaining) {
+            let _ = self.cancel_with_reason(sibling_id, CancelReason::OcoSibling);
+        } else {
+            let filled_fraction = size_filled / (remaining + size_filled);
+            let _ = self.reduce_order(sibling_id, sibling_size * filled_fraction);
+        }
+    }
+
+    // Moves a resting order to a new price and size. The order keeps its id but goes to the
+    // back of the queue at its new price.
+    pub fn amend_order(
+        &mut self,
+        order_id: Uuid,
+        price: f64,
+        size: f64,
+    ) -> Result<(), OrderBookError> {
+        if !self.order_index.contains_key(&order_id) {
+            return Err(OrderBookError::OrderNotFound(order_id));
+        }
+        self.take_rate_token(self.owner_of(order_id))?;
+        self.validate_size(size)?;
+        self.price_to_tick(price)?;
+        let side = self.resting_order(order_id).map(|o| o.order_type);
+        let (side, price) = match side {
+            Some(side) if self.crosses_book(side, price) => (side, price),
+            _ => {
+                self.move_order(order_id, price, size)?;
+                self.trigger_stops();
+                return Ok(());
+            }
+        };
+
+        match self.config.amend_cross_policy {
+            AmendCrossPolicy::Execute => self.move_and_execute_order(order_id, price, size)?,
+            AmendCrossPolicy::Reject => return Err(OrderBookError::AmendWouldCross(order_id)),
+            AmendCrossPolicy::Clamp => {
+                let opposite_side = side.opposite();
+                let limit_idx = self.limit_indices_by_priority(opposite_side, true)[0];
+                let touch = self.limits[&opposite_side][limit_idx].price;
+                let clamped = match side {
+                    OrderType::Bid => touch - self.tick_size_at(touch),
+                    OrderType::Ask => touch + self.tick_size_at(touch),
+                };
+                self.move_order(order_id, self.round_to_tick(clamped), size)?;
+            }
+        }
+        self.trigger_stops();
+        Ok(())
+    }
+
+    // Replaces a resting order with a new price and size. Left at the same price and not
+    // grown, the order keeps its exact place in the queue, anything else is an amend and goes
+    // to the back of the queue.
+    pub fn replace_order(
+        &mut self,
+        order_id: Uuid,
+        price: f64,
+        size: f64,
+    ) -> Result<(), OrderBookError> {
+        let (current_price, current_size) = self
+            .limit_for_order(order_id)
+            .ok()
+            .and_then(|limit| {
+                let order = limit.orders.iter().find(|o| o.id == order_id)?;
+                Some((limit.price, order.size))
+            })
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        let price_key = self.price_to_tick(price)?;
+        if price_key != self.tick_of(current_price) || size > current_size + VOLUME_EPSILON {
+            return self.amend_order(order_id, price, size);
+        }
+
+        self.take_rate_token(self.owner_of(order_id))?;
+        self.validate_size(size)?;
+        if has_volume(current_size - size) {
+            self.reduce_order(order_id, current_size - size)?;
+        }
+        Ok(())
+    }
+
+    // Re-adds a resting order at the new price and size, keeping its OCO link
+    fn move_order(&mut self, order_id: Uuid, price: f64, size: f64) -> Result<(), OrderBookError> {
+        self.move_with(order_id, size, |order_book, order| {
+            order_book.rest_order(price, order)
+        })
+    }
+
+    // Like move_order, but the order matches as a limit order at its new price first
+    fn move_and_execute_order(
+        &mut self,
+        order_id: Uuid,
+        price: f64,
+        size: f64,
+    ) -> Result<(), OrderBookError> {
+        self.move_with(order_id, size, |order_book, order| {
+            order_book.execute_limit_order(price, order).map(|_| ())
+        })
+    }
+
+    // Takes the order off the book and hands it to `place` resized. A move isn't a cancel, so
+    // it leaves no cancel reason, event or activity behind, and the OCO link is kept as long
+    // as the order still rests somewhere. Growing the order has to pass the risk check. When
+    // the order is rejected before anything traded it goes back to where it was as if it
+    // never moved.
+    fn move_with(
+        &mut self,
+        order_id: Uuid,
+        size: f64,
+        place: impl FnOnce(&mut OrderBook, Order) -> Result<(), OrderBookError>,
+    ) -> Result<(), OrderBookError> {
+        self.check_min_resting(order_id)?;
+        let (price, original) = self.detach_order(order_id)?;
+        let (side, size_before) = (original.order_type, original.size);
+        // An iceberg's new size is its new total, so the slice and reserve get split off it
+        // again. Refreshes already used up stay used up.
+        let mut order = original.clone();
+        order.size = size;
+        order.reserve = 0.0;
+        let trades_before = self.trades.len();
+        let grows = size > original.size + original.reserve + VOLUME_EPSILON;
+        let placed = if grows {
+            self.check_risk(&order)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = placed.and_then(|()| place(self, order)) {
+            if self.trades.len() == trades_before {
+                self.file_order(self.tick_of(price), price, original)?;
+            } else {
+                self.unlink_oco(order_id);
+            }
+            return Err(err);
+        }
+        self.level_stats_entry(side, price).cancelled += size_before;
+        if !self.order_index.contains_key(&order_id) {
+            self.unlink_oco(order_id);
+        }
+        Ok(())
+    }
+
+    // Pulls a resting order off its level without any of the bookkeeping of a cancel,
+    // returning it with the price it rested at
+    fn detach_order(&mut self, order_id: Uuid) -> Result<(f64, Order), OrderBookError> {
+        let &(order_type, limit_idx) = self
+            .order_index
+            .get(&order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        let order = self.limit_for_order(order_id)?.remove_order(order_id)?;
+        let price = self.limits[&order_type][limit_idx].price;
+        self.forget_order(&order);
+        self.refresh_best_price(order_type, limit_idx);
+        Ok((price, order))
+    }
+
+    // Moves every resting order of the owner on one side by the tick offset, keeping their
+    // sizes and relative order. Like an amend each order goes to the back of the queue at its
+    // new price, and an order shifted through the opposite touch matches as a limit order.
+    // Every new price is validated before anything moves.
+    pub fn shift_quotes(
+        &mut self,
+        owner: Uuid,
+        side: OrderType,
+        ticks: i64,
+    ) -> Result<(), OrderBookError> {
+        self.take_rate_token(owner)?;
+        let mut shifts = vec![];
+        for view in self.orders_by_owner(side, owner) {
+            self.check_min_resting(view.id)?;
+            let new_price = self.tick_to_price(self.tick_of(view.price) + ticks);
+            self.price_to_tick(new_price)?;
+            if let Some(order) = self.resting_order(view.id) {
+                self.validate_notional(new_price, order)?;
+            }
+            shifts.push((view.id, new_price, view.size));
+        }
+
+        for (order_id, new_price, size) in shifts {
+            if self.crosses_book(side, new_price) {
+                self.move_and_execute_order(order_id, new_price, size)?;
+            } else {
+                self.move_order(order_id, new_price, size)?;
+            }
+        }
+        self.trigger_stops();
+        Ok(())
+    }
+
+    // Swaps all of the owner's resting orders on one side for a new ladder of (price, size)
+    // quotes, returning the new order ids in ladder order. The whole swap costs one rate
+    // token. Quotes are post-only: nothing is replaced when one would trade on arrival. If
+    // any quote is rejected the ones already placed are pulled again and the old orders go
+    // back to their original place in the queue, so the book is left as it was.
+    pub fn replace_ladder(
+        &mut self,
+        owner: Uuid,
+        side: OrderType,
+        quotes: &[(f64, f64)],
+    ) -> Result<Vec<Uuid>, OrderBookError> {
+        self.take_rate_token(owner)?;
+        let views = self.orders_by_owner(side, owner);
+        for view in &views {
+            self.check_min_resting(view.id)?;
+        }
+        let orders: Vec<(f64, Order)> = quotes
+            .iter()
+            .map(|&(price, size)| (price, Order::new(side, size).with_owner(owner)))
+            .collect();
+        for (price, order) in &orders {
+            let rests_at_touch = self.check_touch_policy(order, *price)?;
+            if self.crosses_book(side, *price) && !rests_at_touch {
+                return Err(OrderBookError::PostOnlyWouldCross(order.id));
+            }
+        }
+        // The old quotes only count as cancelled once the new ladder has fully rested, until
+        // then they are put back as they were if one of the new quotes is rejected
+        let mut detached = vec![];
+        for view in views {
+            detached.push(self.detach_order(view.id)?);
+        }
+
+        let mut placed = vec![];
+        for (price, order) in orders {
+            let order_id = order.id;
+            if let Err(err) = self.add_order(price, order) {
+                for order_id in placed {
+                    let _ = self.cancel_with_reason(order_id, CancelReason::User);
+                }
+                for (price, order) in detached {
+                    self.file_order(self.tick_of(price), price, order)?;
+                }
+                return Err(err);
+            }
+            placed.push(order_id);
+        }
+        for (price, order) in detached {
+            self.record_cancel(price, &order, CancelReason::User);
+        }
+        Ok(placed)
+    }
+
+    // Partially cancels a resting order without losing its place in the queue, returning
+    // what is left of it. Reducing by the full remaining size (or more) cancels the order.
+    pub fn reduce_order(&mut self, order_id: Uuid, reduce_by: f64) -> Result<f64, OrderBookError> {
+        if !reduce_by.is_finite() || reduce_by <= 0.0 {
+            return Err(OrderBookError::InvalidSize(reduce_by));
+        }
+
+        let &(order_type, limit_idx) = self
+            .order_index
+            .get(&order_id)
+            .ok_or(OrderBookError::OrderNotFound(order_id))?;
+        // Reducing it away is a cancel, so it has to respect the minimum resting time
+        if self
+            .resting_order(order_id)
+            .is_some_and(|o| !has_volume(o.size - reduce_by))
+        {
+            self.check_min_resting(order_id)?;
+        }
+        let limit = self.limit_for_order(order_id)?;
+        let price = limit.price;
+        let size_before = limit
+            .orders
+            .iter()
+            .find(|o| o.id == order_id)
+            .map_or(0.0, |o| o.size);
+        let remaining = limit.reduce_order(order_id, reduce_by)?;
+        self.refresh_best_price(order_type, limit_idx);
+        self.level_stats_entry(order_type, price).cancelled += size_before - remaining;
+        if !has_volume(remaining) {
+            self.cancel_order(order_id)?;
+        } else {
+            self.publish(BookEvent::OrderReduced {
+                order_id,
+                remaining,
+            });
+        }
+        Ok(remaining)
+    }
+
+    // Matches the order against the opposite side of the book best price first. Whatever
+    // cannot be filled is discarded rather than left resting.
+    pub fn place_market_order(&mut self, order: Order) -> Result<Vec<Match>, OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        self.execute_market_order(order)
+    }
+
+    fn execute_market_order(&mut self, order: Order) -> Result<Vec<Match>, OrderBookError> {
+        self.execute_market_order_within(order, None)
+    }
+
+    // A market order that stops filling at `limit_price` when there is one, the remainder is
+    // discarded all the same
+    fn execute_market_order_within(
+        &mut self,
+        mut order: Order,
+        limit_price: Option<f64>,
+    ) -> Result<Vec<Match>, OrderBookError> {
+        if self.auction_in_progress {
+            return Err(OrderBookError::AuctionInProgress);
+        }
+        self.validate_size(order.size)?;
+        self.check_risk(&order)?;
+        let instructions = order.exec_instructions;
+        if instructions.conflicts() || instructions.contains(ExecInstructions::POST_ONLY) {
+            return Err(OrderBookError::ConflictingInstructions(instructions));
+        }
+        self.check_reduce_only(&order)?;
+        if order.class == OrderClass::Normal {
+            self.check_cooldown()?;
+        }
+
+        let opposite_side = order.order_type.opposite();
+
+        if self
+            .limit_indices_by_priority(opposite_side, true)
+            .is_empty()
+        {
+            return match self.config.market_order_fallback {
+                MarketOrderFallback::ReturnUnfilled => {
+                    self.discard_remainder(&order);
+                    Ok(vec![])
+                }
+                MarketOrderFallback::Reject => Err(OrderBookError::NoLiquidity(opposite_side)),
+                MarketOrderFallback::RestAtReferencePrice => {
+                    let reference_price = self
+                        .last_trade_price()
+                        .ok_or(OrderBookError::NoLiquidity(opposite_side))?;
+                    self.add_order(reference_price, order)?;
+                    Ok(vec![])
+                }
+            };
+        }
+
+        if order.class == OrderClass::Normal {
+            self.check_price_band(order.order_type, order.size)?;
+        }
+        self.check_self_trade(&order, limit_price, true)?;
+        if !self.can_fill_on_arrival(&order, limit_price) {
+            self.discard_remainder(&order);
+            return Ok(vec![]);
+        }
+        let matches = self.match_order(&mut order, limit_price);
+        if !has_volume(order.size) {
+            self.archive_filled(order);
+        } else {
+            self.discard_remainder(&order);
+        }
+        self.trigger_stops();
+        Ok(matches)
+    }
+
+    // Market order that only fills while the price stays within `max_slippage_pct` percent
+    // of the mid on arrival (the opposite touch for a one-sided book) and cancels the rest
+    // instead of sweeping deeper. Returns the matches along with the unfilled size. Apart
+    // from the price bound it goes through the same checks as any market order.
+    pub fn place_protected_market_order(
+        &mut self,
+        order: Order,
+        max_slippage_pct: f64,
+    ) -> Result<(Vec<Match>, f64), OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        let opposite_side = order.order_type.opposite();
+        let limits = &self.limits[&opposite_side];
+        let reference_price = self.touch_mid().or_else(|| {
+            self.limit_indices_by_priority(opposite_side, true)
+                .first()
+                .map(|&limit_idx| limits[limit_idx].price)
+        });
+        let limit_price = reference_price.map(|reference_price| {
+            let slippage = reference_price.abs() * max_slippage_pct / 100.0;
+            match order.order_type {
+                OrderType::Bid => reference_price + slippage,
+                OrderType::Ask => reference_price - slippage,
+            }
+        });
+
+        let size = order.size;
+        let matches = self.execute_market_order_within(order, limit_price)?;
+        let filled: f64 = matches.iter().map(|m| m.size_filled).sum();
+        Ok((matches, (size - filled).max(0.0)))
+    }
+
+    // Quotes the average price the book would fill `size` at right now. The quote is only
+    // indicative, nothing is reserved until it gets accepted.
+    pub fn request_quote(
+        &mut self,
+        side: OrderType,
+        size: f64,
+        owner: Uuid,
+    ) -> Result<RfqQuote, OrderBookError> {
+        self.take_rate_token(owner)?;
+        self.validate_size(size)?;
+        let price = self
+            .vwap_to_fill(side, size)
+            .ok_or(OrderBookError::NoLiquidity(side.opposite()))?;
+        let quote = RfqQuote {
+            quote_id: Uuid::new_v4(),
+            owner,
+            side,
+            size,
+            price,
+            expires_at: self.now() + self.config.rfq_validity_secs,
+        };
+        self.rfq_quotes.insert(quote.quote_id, quote);
+        Ok(quote)
+    }
+
+    // Sweeps the book for the quoted size with every fill at the quoted price, as long as
+    // the quote is still valid and the book's own price for the size is within the RFQ
+    // tolerance of it. A quote can only be accepted once.
+    pub fn accept_rfq(&mut self, quote_id: Uuid) -> Result<Vec<Match>, OrderBookError> {
+        let quote = self
+            .rfq_quotes
+            .remove(&quote_id)
+            .ok_or(OrderBookError::QuoteNotFound(quote_id))?;
+        if self.now() > quote.expires_at {
+            return Err(OrderBookError::QuoteExpired(quote_id));
+        }
+        if self.auction_in_progress {
+            return Err(OrderBookError::AuctionInProgress);
+        }
+        let current = self
+            .vwap_to_fill(quote.side, quote.size)
+            .ok_or(OrderBookError::NoLiquidity(quote.side.opposite()))?;
+        if (current - quote.price).abs() > quote.price.abs() * self.config.rfq_tolerance {
+            return Err(OrderBookError::QuoteMoved {
+                quoted: quote.price,
+                current,
+            });
+        }
+
+        let mut order = Order::new(quote.side, quote.size).with_owner(quote.owner);
+        self.check_risk(&order)?;
+        self.check_cooldown()?;
+        self.check_self_trade(&order, None, false)?;
+        let matches = self.match_order_at(&mut order, None, Some(quote.price));
+        if !has_volume(order.size) {
+            self.archive_filled(order);
+        } else {
+            self.discard_remainder(&order);
+        }
+        self.trigger_stops();
+        Ok(matches)
+    }
+
+    // Holds the order until the last trade price reaches the trigger (at or above it for a
+    // buy stop, at or below for a sell stop), then sends it as a market order
+    pub fn place_stop_order(
+        &mut self,
+        trigger_price: f64,
+        order: Order,
+    ) -> Result<(), OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        if self
+            .pending_stops
+            .iter()
+            .any(|(_, stop)| stop.id == order.id)
+        {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        self.check_session(&order)?;
+        self.validate_size(order.size)?;
+        self.price_to_tick(trigger_price)?;
+        self.pending_stops
+            .push((Trigger::Stop(trigger_price), order));
+        Ok(())
+    }
+
+    // Market-if-touched (no limit price) or limit-if-touched order. Unlike a stop it waits
+    // for the price to come its way: a buy triggers once the ask falls to the trigger, a sell
+    // once the bid rises to it. It triggers right away when the book is already there.
+    pub fn place_if_touched_order(
+        &mut self,
+        trigger_price: f64,
+        limit_price: Option<f64>,
+        order: Order,
+    ) -> Result<(), OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        if self
+            .pending_stops
+            .iter()
+            .any(|(_, pending)| pending.id == order.id)
+        {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        self.check_session(&order)?;
+        self.validate_size(order.size)?;
+        self.price_to_tick(trigger_price)?;
+        if let Some(limit_price) = limit_price {
+            self.price_to_tick(limit_price)?;
+        }
+        let trigger = Trigger::IfTouched {
+            trigger_price,
+            limit_price,
+        };
+        self.pending_stops.push((trigger, order));
+        self.trigger_stops();
+        Ok(())
+    }
+
+    pub fn pending_stops(&self) -> impl Iterator<Item = &Order> {
+        self.pending_stops.iter().map(|(_, stop)| stop)
+    }
+
+    fn is_triggered(&self, trigger: Trigger, side: OrderType) -> bool {
+        match trigger {
+            Trigger::Stop(trigger_price) => {
+                self.last_trade_price()
+                    .is_some_and(|last_price| match side {
+                        OrderType::Bid => last_price >= trigger_price,
+                        OrderType::Ask => last_price <= trigger_price,
+                    })
+            }
+            Trigger::IfTouched { trigger_price, .. } => {
+                let touch = self.best_ticks[&side.opposite()].map(|tick| self.tick_to_price(tick));
+                touch.is_some_and(|touch| match side {
+                    OrderType::Bid => touch <= trigger_price,
+                    OrderType::Ask => touch >= trigger_price,
+                })
+            }
+        }
+    }
+
+    // Activates every pending conditional order whose trigger was reached. Expired ones are
+    // dropped on the way and never activate.
+    fn trigger_stops(&mut self) {
+        let now = self.now();
+        self.pending_stops.retain(|(_, stop)| !stop.is_expired(now));
+
+        let Some(i) = self
+            .pending_stops
+            .iter()
+            .position(|&(trigger, ref stop)| self.is_triggered(trigger, stop.order_type))
+        else {
+            return;
+        };
+        let (trigger, stop) = self.pending_stops.remove(i);
+        let _ = match trigger {
+            Trigger::IfTouched {
+                limit_price: Some(limit_price),
+                ..
+            } => self.execute_limit_order(limit_price, stop),
+            _ => self.execute_market_order(stop),
+        };
+        // What it did to the book may trigger the next one
+        self.trigger_stops();
+    }
+
+    pub fn delayed_orders(&self) -> impl Iterator<Item = &Order> {
+        self.delayed_orders.iter().map(|(_, order)| order)
+    }
+
+    // Releases every delayed order whose not_before time has come as a limit order, in
+    // submission order, returning the ids of the released orders that were accepted
+    pub fn promote_due(&mut self, now: i64) -> Vec<Uuid> {
+        let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed_orders)
+            .into_iter()
+            .partition(|(_, order)| order.is_due(now));
+        self.delayed_orders = held;
+
+        due.into_iter()
+            .filter_map(|(price, mut order)| {
+                order.not_before = None;
+                let order_id = order.id;
+                self.execute_limit_order(price, order)
+                    .ok()
+                    .map(|_| order_id)
+            })
+            .collect()
+    }
+
+    // Cancels every resting order and drops every pending stop that expired, returning
+    // their ids
+    pub fn purge_expired(&mut self) -> Vec<Uuid> {
+        let now = self.now();
+        let expired_orders: Vec<Uuid> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter())
+            .chain(self.midpoint_orders.values().flatten())
+            .filter(|o| o.is_expired(now))
+            .map(|o| o.id)
+            .collect();
+        let mut purged: Vec<Uuid> = expired_orders
+            .into_iter()
+            .filter_map(|order_id| {
+                self.cancel_with_reason(order_id, CancelReason::Expired)
+                    .ok()
+                    .map(|o| o.id)
+            })
+            .collect();
+
+        purged.extend(
+            self.pending_stops
+                .iter()
+                .filter(|(_, stop)| stop.is_expired(now))
+                .map(|(_, stop)| stop.id),
+        );
+        self.pending_stops.retain(|(_, stop)| !stop.is_expired(now));
+        purged
+    }
+
+    // Rejects a market order that would walk the book further from the reference price
+    // than the configured band allows
+    fn check_price_band(&self, side: OrderType, size: f64) -> Result<(), OrderBookError> {
+        let Some(price_band) = self.config.price_band else {
+            return Ok(());
+        };
+        let opposite_side = side.opposite();
+        let limits = &self.limits[&opposite_side];
+        let priority = self.limit_indices_by_priority(opposite_side, true);
+        let Some(reference_price) = self
+            .last_trade_price()
+            .or_else(|| priority.first().map(|&limit_idx| limits[limit_idx].price))
+        else {
+            return Ok(());
+        };
+
+        let mut remaining = size;
+        for limit_idx in priority {
+            let limit = &limits[limit_idx];
+            if (limit.price - reference_price).abs() > price_band * reference_price.abs() + 1e-9 {
+                return Err(OrderBookError::PriceBandExceeded {
+                    price: limit.price,
+                    reference_price,
+                });
+            }
+            remaining -= limit.total_volume + limit.hidden_volume;
+            if !has_volume(remaining) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Market order sized in either unit, the order's own size is replaced. A quote amount is
+    // converted to the base size it buys (or sells for) walking the book, rounded down to
+    // the lot size so the amount is never exceeded.
+    pub fn place_sized_market_order(
+        &mut self,
+        mut order: Order,
+        size: SizeSpec,
+    ) -> Result<Vec<Match>, OrderBookError> {
+        order.size = match size {
+            SizeSpec::Base(base) => base,
+            SizeSpec::Quote(quote) => {
+                if !quote.is_finite() || quote <= 0.0 {
+                    return Err(OrderBookError::InvalidSize(quote));
+                }
+                let base = self.quote_to_base(order.order_type.opposite(), quote);
+                if base <= 0.0 {
+                    return Err(OrderBookError::NoLiquidity(order.order_type.opposite()));
+                }
+                base
+            }
+        };
+        self.place_market_order(order)
+    }
+
+    // Base size the quote amount is worth against the given side, hidden size included
+    fn quote_to_base(&self, side: OrderType, quote: f64) -> f64 {
+        let limits = &self.limits[&side];
+        let (mut base, mut remaining_quote) = (0.0, quote);
+        for limit_idx in self.limit_indices_by_priority(side, true) {
+            let limit = &limits[limit_idx];
+            let size =
+                (limit.total_volume + limit.hidden_volume).min(remaining_quote / limit.price);
+            base += size;
+            remaining_quote -= size * limit.price;
+            if remaining_quote <= 0.0 {
+                break;
+            }
+        }
+
+        let lot_size = self.config.lot_size;
+        ((base / lot_size) + 1e-9).floor() * lot_size
+    }
+
+    // Matches whatever part of a limit order crosses the opposite side and rests the rest
+    pub fn place_limit_order(
+        &mut self,
+        price: f64,
+        order: Order,
+    ) -> Result<Vec<Match>, OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        self.execute_limit_order(price, order)
+    }
+
+    // place_limit_order that is safe to retry: a submission repeating an earlier accepted
+    // one's idempotency key returns that one's order id and matches instead of placing a
+    // second order. Rejected submissions are not remembered, so they can be retried.
+    pub fn submit_limit_order(
+        &mut self,
+        price: f64,
+        order: Order,
+        idempotency_key: Option<&str>,
+    ) -> Result<(Uuid, Vec<Match>), OrderBookError> {
+        self.submit_idempotent(order, idempotency_key, |order_book, order| {
+            order_book.place_limit_order(price, order)
+        })
+    }
+
+    // Like submit_limit_order for a market order
+    pub fn submit_market_order(
+        &mut self,
+        order: Order,
+        idempotency_key: Option<&str>,
+    ) -> Result<(Uuid, Vec<Match>), OrderBookError> {
+        self.submit_idempotent(order, idempotency_key, OrderBook::place_market_order)
+    }
+
+    fn submit_idempotent(
+        &mut self,
+        order: Order,
+        idempotency_key: Option<&str>,
+        place: impl FnOnce(&mut OrderBook, Order) -> Result<Vec<Match>, OrderBookError>,
+    ) -> Result<(Uuid, Vec<Match>), OrderBookError> {
+        let (owner, order_id) = (order.owner, order.id);
+        let Some(key) = idempotency_key else {
+            return place(self, order).map(|matches| (order_id, matches));
+        };
+        if let Some(result) = self.idempotent_results.get(&(owner, key.to_string())) {
+            return Ok(result.clone());
+        }
+
+        let matches = place(self, order)?;
+        let capacity = self.config.idempotency_keys_per_owner;
+        if capacity > 0 {
+            let keys = self.idempotency_keys.entry(owner).or_default();
+            if keys.len() == capacity
+                && let Some(oldest) = keys.pop_front()
+            {
+                self.idempotent_results.remove(&(owner, oldest));
+            }
+            keys.push_back(key.to_string());
+            self.idempotent_results
+                .insert((owner, key.to_string()), (order_id, matches.clone()));
+        }
+        Ok((order_id, matches))
+    }
+
+    // place_limit_order with the limit given as a yield, see yield_to_price. A bid's yield
+    // is the lowest it accepts and an ask's the highest, mirroring their limit prices.
+    pub fn place_limit_order_by_yield(
+        &mut self,
+        yield_pct: f64,
+        order: Order,
+    ) -> Result<Vec<Match>, OrderBookError> {
+        let price = self.yield_to_price(yield_pct)?;
+        self.place_limit_order(price, order)
+    }
+
+    fn execute_limit_order(
+        &mut self,
+        price: f64,
+        mut order: Order,
+    ) -> Result<Vec<Match>, OrderBookError> {
+        if self.order_index.contains_key(&order.id) {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        self.validate_size(order.size)?;
+        self.check_mmp(order.owner)?;
+        self.check_session(&order)?;
+        self.check_risk(&order)?;
+        let instructions = order.exec_instructions;
+        if instructions.conflicts() {
+            return Err(OrderBookError::ConflictingInstructions(instructions));
+        }
+        let price = self.tick_to_price(self.price_to_tick(price)?);
+        self.validate_notional(price, &order)?;
+        if !order.is_due(self.now()) {
+            self.delayed_orders.push((price, order));
+            return Ok(vec![]);
+        }
+        let rests_at_touch = self.check_touch_policy(&order, price)?;
+        let crosses_book = self.crosses_book(order.order_type, price) && !rests_at_touch;
+        if instructions.contains(ExecInstructions::POST_ONLY) && crosses_book {
+            return Err(OrderBookError::PostOnlyWouldCross(order.id));
+        }
+        if !crosses_book {
+            self.check_level_count(order.order_type, price)?;
+        }
+        self.check_reduce_only(&order)?;
+        if order.class == OrderClass::Normal && crosses_book {
+            self.check_cooldown()?;
+        }
+        if instructions.contains(ExecInstructions::HIDDEN) {
+            order.visibility = Visibility::Hidden;
+        }
+
+        let can_fill = self.can_fill_on_arrival(&order, Some(price));
+        if !can_fill && instructions.contains(ExecInstructions::FILL_OR_KILL) {
+            self.discard_remainder(&order);
+            return Ok(vec![]);
+        }
+        let matches = if self.auction_in_progress || !can_fill || rests_at_touch {
+            vec![]
+        } else {
+            self.check_self_trade(&order, Some(price), true)?;
+            self.match_order(&mut order, Some(price))
+        };
+        let cancels_remainder = instructions.contains(ExecInstructions::IMMEDIATE_OR_CANCEL)
+            || instructions.contains(ExecInstructions::FILL_OR_KILL);
+        if !has_volume(order.size) {
+            self.archive_filled(order);
+        } else if cancels_remainder {
+            self.discard_remainder(&order);
+        } else {
+            self.rest_order(price, order)?;
+        }
+        self.trigger_stops();
+        Ok(matches)
+    }
+
+    // Accounts for the unfilled part of an immediate order that is dropped instead of
+    // resting, so downstream systems see where that size went
+    fn discard_remainder(&mut self, order: &Order) {
+        let reason = CancelReason::IocRemainder;
+        self.cancel_reasons.insert(order.id, reason);
+        self.publish(BookEvent::OrderCancelled {
+            order_id: order.id,
+            reason,
+        });
+    }
+
+    // Walks the opposite side best price first filling the order, stopping at the first
+    // limit that is priced worse than `limit_price` (if there is one)
+    fn match_order(&mut self, order: &mut Order, limit_price: Option<f64>) -> Vec<Match> {
+        self.match_order_at(order, limit_price, None)
+    }
+
+    // Like match_order, with every fill at `locked_price` when there is one. A locked price
+    // is quoted off the levels, so resting midpoint orders are left out.
+    fn match_order_at(
+        &mut self,
+        order: &mut Order,
+        limit_price: Option<f64>,
+        locked_price: Option<f64>,
+    ) -> Vec<Match> {
+        let opposite_side = order.order_type.opposite();
+        let previous_price = self.last_trade_price();
+
+        let mut matches = vec![];
+        // Resting midpoint orders give a better price than the touch so they go first, as
+        // long as the incoming order is willing to trade at the mid
+        if let Some(mid) = self.touch_mid()
+            && locked_price.is_none()
+        {
+            let takes_mid = match (order.order_type, limit_price) {
+                (_, None) => true,
+                (OrderType::Bid, Some(limit_price)) => limit_price >= mid,
+                (OrderType::Ask, Some(limit_price)) => limit_price <= mid,
+            };
+            if takes_mid {
+                matches.extend(self.fill_at_mid(order, mid));
+            }
+        }
+
+        for limit_idx in self.limit_indices_by_priority(opposite_side, true) {
+            if !has_volume(order.size) {
+                break;
+            }
+
+            let limit = &self.limits[&opposite_side][limit_idx];
+            let crosses = match (order.order_type, limit_price) {
+                (_, None) => true,
+                (OrderType::Bid, Some(limit_price)) => limit.price <= limit_price,
+                (OrderType::Ask, Some(limit_price)) => limit.price >= limit_price,
+            };
+            if !crosses {
+                break;
+            }
+
+            let mid_at_trade = self.touch_mid();
+            let min_increment = self
+                .config
+                .min_fill_increment
+                .map(|min_increment| (min_increment, self.config.dust_policy));
+            // One trade price feeds the fill totals, the matches, the events and the tape
+            let trade_price = match (order.execution_price, limit_price) {
+                (ExecutionPrice::Taker, Some(limit_price)) => Some(limit_price),
+                _ => locked_price,
+            };
+            let limit = &mut self
+                .limits
+                .get_mut(&opposite_side)
+                .expect("Did not find limits for order type")[limit_idx];
+            let (limit_matches, filled_orders) = limit.fill(order, min_increment, trade_price);
+            let level_price = limit.price;
+            self.level_stats_entry(opposite_side, level_price).filled +=
+                limit_matches.iter().map(|m| m.size_filled).sum::<f64>();
+            let filled_owners: HashMap<Uuid, Uuid> = filled_orders
+                .iter()
+                .map(|filled_order| (filled_order.id, filled_order.owner))
+                .collect();
+            let fill_events: Vec<BookEvent> = limit_matches
+                .iter()
+                .filter_map(|m| {
+                    let maker_id = match order.order_type {
+                        OrderType::Bid => m.ask_id,
+                        OrderType::Ask => m.bid_id,
+                    };
+                    let maker = filled_orders
+                        .iter()
+                        .find(|o| o.id == maker_id)
+                        .or_else(|| self.resting_order(maker_id))?;
+                    Some(BookEvent::from_fill(maker, m.size_filled, m.price))
+                })
+                .collect();
+            for fill_event in fill_events {
+                self.publish(fill_event);
+            }
+            let refills: Vec<Order> = filled_orders
+                .into_iter()
+                .filter_map(|filled_order| self.settle_filled(filled_order))
+                .collect();
+            self.refresh_best_price(opposite_side, limit_idx);
+            for m in &limit_matches {
+                let maker_id = match order.order_type {
+                    OrderType::Bid => m.ask_id,
+                    OrderType::Ask => m.bid_id,
+                };
+                let maker_owner = match filled_owners.get(&maker_id) {
+                    Some(&owner) => owner,
+                    None => self.owner_of(maker_id),
+                };
+                self.record_trade(
+                    (maker_id, maker_owner),
+                    (order.id, order.owner, order.class),
+                    order.order_type,
+                    m.price,
+                    m.size_filled,
+                    mid_at_trade,
+                );
+                self.reduce_oco_sibling(maker_id, m.size_filled);
+            }
+            matches.extend(limit_matches);
+
+            // Replenished slices may still be the best price around, so matching starts
+            // over from the top of the book
+            let mut replenished = false;
+            for refill in refills {
+                replenished |= self.rest_order(level_price, refill).is_ok();
+            }
+            if replenished && has_volume(order.size) {
+                matches.extend(self.match_order_at(order, limit_price, locked_price));
+                break;
+            }
+        }
+
+        self.start_cooldown_on_large_move(previous_price);
+        self.pull_tripped_quotes();
+        matches
+    }
+
+    // Whether the order is priced exactly at the opposite touch and has to rest there
+    // without trading, or an error when the touch policy refuses locking orders
+    fn check_touch_policy(&self, order: &Order, price: f64) -> Result<bool, OrderBookError> {
+        let opposite_side = order.order_type.opposite();
+        let at_touch = self.price_to_tick(price).ok() == self.best_ticks[&opposite_side];
+        match self.config.touch_policy {
+            _ if !at_touch => Ok(false),
+            TouchPolicy::Match => Ok(false),
+            TouchPolicy::Rest => Ok(true),
+            TouchPolicy::Reject => Err(OrderBookError::WouldLockBook(order.id)),
+        }
+    }
+
+    fn crosses_book(&self, side: OrderType, price: f64) -> bool {
+        let opposite_side = side.opposite();
+        let Some(&limit_idx) = self.limit_indices_by_priority(opposite_side, true).first() else {
+            return false;
+        };
+        let best_price = self.limits[&opposite_side][limit_idx].price;
+        match side {
+            OrderType::Bid => best_price <= price,
+            OrderType::Ask => best_price >= price,
+        }
+    }
+
+    // Spends one of the account's order entry tokens, refilling the bucket for the time
+    // passed since it was last touched
+    fn take_rate_token(&mut self, owner: Uuid) -> Result<(), OrderBookError> {
+        let Some(rate_limit) = self.config.order_rate_limit else {
+            return Ok(());
+        };
+        let now = self.now();
+        let capacity = rate_limit.max_actions as f64;
+        let (tokens, refilled_at) = self.rate_buckets.entry(owner).or_insert((capacity, now));
+        let refill = (now - *refilled_at) as f64 * capacity / rate_limit.window_secs as f64;
+        *tokens = (*tokens + refill).min(capacity);
+        *refilled_at = now;
+        if *tokens < 1.0 {
+            return Err(OrderBookError::RateLimited(owner));
+        }
+        *tokens -= 1.0;
+        Ok(())
+    }
+
+    // Walks the orders the incoming order would fill in the order match_order_at fills them,
+    // midpoint orders first (unless the order skips them) and then the levels with displayed
+    // orders before hidden ones, looking for one of the same account. Orders of the
+    // anonymous (nil) owner never count as self-trades.
+    fn check_self_trade(
+        &self,
+        order: &Order,
+        limit_price: Option<f64>,
+        with_midpoint: bool,
+    ) -> Result<(), OrderBookError> {
+        if self.config.self_trade_prevention.is_none() || order.owner.is_nil() {
+            return Ok(());
+        }
+        let crosses = |price: f64| match (order.order_type, limit_price) {
+            (_, None) => true,
+            (OrderType::Bid, Some(limit_price)) => price <= limit_price,
+            (OrderType::Ask, Some(limit_price)) => price >= limit_price,
+        };
+        let opposite_side = order.order_type.opposite();
+        let mut remaining = order.size;
+        if with_midpoint
+            && let Some(mid) = self.touch_mid()
+            && crosses(mid)
+        {
+            for resting_order in &self.midpoint_orders[&opposite_side] {
+                if resting_order.owner == order.owner {
+                    return Err(OrderBookError::SelfTradeRejected(order.id));
+                }
+                remaining -= resting_order.size;
+                if !has_volume(remaining) {
+                    return Ok(());
+                }
+            }
+        }
+        let limits = &self.limits[&opposite_side];
+        for limit_idx in self.limit_indices_by_priority(opposite_side, true) {
+            let limit = &limits[limit_idx];
+            if !crosses(limit.price) {
+                break;
+            }
+            let queue = [Visibility::Displayed, Visibility::Hidden]
+                .into_iter()
+                .flat_map(|visibility| {
+                    limit
+                        .orders
+                        .iter()
+                        .filter(move |o| o.visibility == visibility)
+                });
+            for resting_order in queue {
+                if resting_order.owner == order.owner {
+                    return Err(OrderBookError::SelfTradeRejected(order.id));
+                }
+                remaining -= resting_order.size;
+                if !has_volume(remaining) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Whether enough crosses the book for a fill-or-kill or minimum fill order to trade on
+    // arrival, always true for other orders. Midpoint orders are left out.
+    fn can_fill_on_arrival(&self, order: &Order, limit_price: Option<f64>) -> bool {
+        let instructions = order.exec_instructions;
+        let required = if instructions.contains(ExecInstructions::FILL_OR_KILL) {
+            order.size
+        } else if instructions.contains(ExecInstructions::MIN_FILL) {
+            order.min_fill_size.unwrap_or(0.0).min(order.size)
+        } else {
+            return true;
+        };
+        let available: f64 = self
+            .limits_by_priority(order.order_type.opposite())
+            .into_iter()
+            .take_while(|limit| match (order.order_type, limit_price) {
+                (_, None) => true,
+                (OrderType::Bid, Some(limit_price)) => limit.price <= limit_price,
+                (OrderType::Ask, Some(limit_price)) => limit.price >= limit_price,
+            })
+            .map(|limit| limit.total_volume)
+            .sum();
+        available >= required - VOLUME_EPSILON
+    }
+
+    fn check_reduce_only(&self, order: &Order) -> Result<(), OrderBookError> {
+        if !order
+            .exec_instructions
+            .contains(ExecInstructions::REDUCE_ONLY)
+        {
+            return Ok(());
+        }
+        let position = self.net_position(order.owner);
+        let reduces = match order.order_type {
+            OrderType::Bid => position < 0.0 && order.size <= -position + VOLUME_EPSILON,
+            OrderType::Ask => position > 0.0 && order.size <= position + VOLUME_EPSILON,
+        };
+        if !reduces {
+            return Err(OrderBookError::ReduceOnlyWouldIncrease(order.id));
+        }
+        Ok(())
+    }
+
+    fn check_cooldown(&self) -> Result<(), OrderBookError> {
+        match self.cooldown_until {
+            Some(until) if self.now() < until => Err(OrderBookError::CooldownInProgress { until }),
+            _ => Ok(()),
+        }
+    }
+
+    // Compares the last trade price against the one before the incoming order matched
+    fn start_cooldown_on_large_move(&mut self, previous_price: Option<f64>) {
+        let (Some(cooldown_move), Some(previous_price), Some(last_price)) = (
+            self.config.cooldown_move,
+            previous_price,
+            self.last_trade_price(),
+        ) else {
+            return;
+        };
+        if (last_price - previous_price).abs() > cooldown_move * previous_price.abs() {
+            self.cooldown_until = Some(self.now() + self.config.cooldown_secs);
+        }
+    }
+
+    // Depth based equilibrium estimate over the top `levels` of each side. Both cumulative
+    // depth curves are walked out to the volume the thinner side holds, and the fair price
+    // is halfway between the two prices where that volume is reached. A thin side is easy to
+    // push through so the estimate leans towards it, a balanced book gives the mid. None
+    // unless both sides have depth.
+    pub fn implied_fair(&self, levels: usize) -> Option<f64> {
+        let (bids, asks) = self.depth(levels);
+        let side_volume = |side: &[PriceLevel]| side.iter().map(|&(_, size)| size).sum::<f64>();
+        let balanced_volume = side_volume(&bids).min(side_volume(&asks));
+        if !has_volume(balanced_volume) {
+            return None;
+        }
+        let price_reaching = |side: &[PriceLevel]| {
+            let mut cumulative = 0.0;
+            side.iter().find_map(|&(price, size)| {
+                cumulative += size;
+                (cumulative >= balanced_volume - VOLUME_EPSILON).then_some(price)
+            })
+        };
+        Some((price_reaching(&bids)? + price_reaching(&asks)?) / 2.0)
+    }
+
+    // The worst price a market order on `side` of the given size would reach while walking
+    // the opposite side of the book, which is the limit price that guarantees a full fill
+    pub fn price_to_fill(&self, side: OrderType, size: f64) -> Option<f64> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+
+        let opposite_side = side.opposite();
+
+        let mut remaining = size;
+        for limit in self.limits_by_priority(opposite_side) {
+            remaining -= limit.total_volume;
+            if !has_volume(remaining) {
+                return Some(limit.price);
+            }
+        }
+        None
+    }
+
+    // Average price a market order on `side` of the given size would fill at against the
+    // displayed book, None when the book is too thin to fill it
+    fn vwap_to_fill(&self, side: OrderType, size: f64) -> Option<f64> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+
+        let (mut remaining, mut notional) = (size, 0.0);
+        for limit in self.limits_by_priority(side.opposite()) {
+            let size_filled = remaining.min(limit.total_volume);
+            notional += self.notional(limit.price, size_filled);
+            remaining -= size_filled;
+            if !has_volume(remaining) {
+                // Inverse contracts average prices harmonically, weighted by contract size
+                return Some(if self.config.inverse {
+                    size / notional
+                } else {
+                    notional / size
+                });
+            }
+        }
+        None
+    }
+
+    // Samples the price impact curve: for each probe size how far the average fill price of
+    // a market order on `side` lands from the mid, as (size, impact). Sizes the book is too
+    // thin to fill are left out, and nothing is returned without a two-sided book.
+    pub fn price_impact(&self, side: OrderType, sizes: &[f64]) -> Vec<(f64, f64)> {
+        let Some(mid) = self.mid_price() else {
+            return vec![];
+        };
+        sizes
+            .iter()
+            .filter_map(|&size| {
+                let vwap = self.vwap_to_fill(side, size)?;
+                let impact = match side {
+                    OrderType::Bid => vwap - mid,
+                    OrderType::Ask => mid - vwap,
+                };
+                Some((size, impact))
+            })
+            .collect()
+    }
+
+    // What a market order on `side` has to take to push the opposite best price `ticks` ticks
+    // away, as (volume, notional): every level closer than that gets swept. None when the
+    // opposite side is empty or too thin to move that far.
+    pub fn cost_to_move(&self, side: OrderType, ticks: u64) -> Option<(f64, f64)> {
+        let best_tick = self.best_ticks[&side.opposite()]?;
+        let to_tick = |price: f64| (price / self.config.tick_size).round() as i64;
+        let levels = self.limits_by_priority(side.opposite());
+        let swept = levels
+            .iter()
+            .take_while(|limit| to_tick(limit.price).abs_diff(best_tick) < ticks)
+            .count();
+        if swept == levels.len() && ticks > 0 {
+            return None;
+        }
+        Some(
+            levels[..swept]
+                .iter()
+                .fold((0.0, 0.0), |(volume, notional), limit| {
+                    (
+                        volume + limit.total_volume,
+                        notional + self.notional(limit.price, limit.total_volume),
+                    )
+                }),
+        )
+    }
+
+    // The resting orders at a price as (order id, remaining size) in the order they would
+    // be matched, so clients can see exactly where they stand in the queue
+    pub fn level_queue(&self, side: OrderType, price: f64) -> Option<Vec<(Uuid, f64)>> {
+        let limit = self.limit_at(side, price)?;
+        let queue: Vec<(Uuid, f64)> = limit
+            .orders
+            .iter()
+            .filter(|o| o.visibility == Visibility::Displayed)
+            .map(|o| (o.id, o.size))
+            .collect();
+        if queue.is_empty() {
+            return None;
+        }
+        Some(queue)
+    }
+
+    // When the level last changed, so a client can age out levels of its local copy that
+    // went quiet. None when nothing ever rested at the price.
+    pub fn level_last_updated(&self, side: OrderType, price: f64) -> Option<i64> {
+        self.limit_at(side, price).map(|limit| limit.last_updated)
+    }
+
+    // Like level_queue, with hidden orders too. Display priority puts every displayed order
+    // ahead of the hidden ones whatever their arrival, so hidden orders come last.
+    pub fn level_queue_detailed(
+        &self,
+        side: OrderType,
+        price: f64,
+    ) -> Option<Vec<(Uuid, f64, Visibility)>> {
+        let limit = self.limit_at(side, price)?;
+        let mut queue: Vec<(Uuid, f64, Visibility)> = limit
+            .orders
+            .iter()
+            .map(|o| (o.id, o.size, o.visibility))
+            .collect();
+        if queue.is_empty() {
+            return None;
+        }
+        queue.sort_by_key(|&(_, _, visibility)| visibility == Visibility::Hidden);
+        Some(queue)
+    }
+
+    // Best bid and ask together with the volume resting at each, as
+    // (best_bid, best_bid_size, best_ask, best_ask_size)
+    pub fn touch(&self) -> Option<(f64, f64, f64, f64)> {
+        let best_bid = self.best_limit(OrderType::Bid)?;
+        let best_ask = self.best_limit(OrderType::Ask)?;
+        Some((
+            best_bid.price,
+            best_bid.total_volume,
+            best_ask.price,
+            best_ask.total_volume,
+        ))
+    }
+
+    // Ids of every resting order of an account on both sides, oldest first
+    pub fn open_orders(&self, owner: Uuid) -> Vec<Uuid> {
+        let mut orders: Vec<(OrderView, u64)> = self
+            .orders_by_owner
+            .get(&owner)
+            .into_iter()
+            .flatten()
+            .filter_map(|&order_id| self.view_order(order_id))
+            .collect();
+        orders.sort_by_key(|(_, sequence)| *sequence);
+        orders.into_iter().map(|(view, _)| view.id).collect()
+    }
+
+    // Full views of an account's resting orders on one side in matching priority
+    pub fn orders_by_owner(&self, side: OrderType, owner: Uuid) -> Vec<OrderView> {
+        let mut orders: Vec<(OrderView, u64)> = self
+            .orders_by_owner
+            .get(&owner)
+            .into_iter()
+            .flatten()
+            .filter_map(|&order_id| self.view_order(order_id))
+            .filter(|(view, _)| view.order_type == side)
+            .collect();
+        orders.sort_by(|(a, a_sequence), (b, b_sequence)| {
+            let by_price = match side {
+                OrderType::Bid => b.price.total_cmp(&a.price),
+                OrderType::Ask => a.price.total_cmp(&b.price),
+            };
+            by_price.then(a_sequence.cmp(b_sequence))
+        });
+        orders.into_iter().map(|(view, _)| view).collect()
+    }
+
+    // Cancels every session order and returns their ids oldest first, good-till-cancel
+    // orders stay on the book. The per level fill stats start over.
+    pub fn end_session(&mut self) -> Vec<Uuid> {
+        let mut session_orders: Vec<(u64, Uuid)> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter())
+            .filter(|o| o.time_in_force == TimeInForce::Session)
+            .map(|o| (o.sequence, o.id))
+            .collect();
+        session_orders.sort();
+
+        let cancelled = session_orders
+            .into_iter()
+            .filter_map(|(_, order_id)| {
+                self.cancel_with_reason(order_id, CancelReason::SessionEnd)
+                    .ok()
+                    .map(|o| o.id)
+            })
+            .collect();
+        self.level_stats.clear();
+        cancelled
+    }
+
+    // The farthest resting price from the touch, handy for spotting stale deep orders
+    pub fn worst_bid(&self) -> Option<f64> {
+        self.limits[&OrderType::Bid]
+            .iter()
+            .filter(|limit| has_volume(limit.total_volume))
+            .map(|limit| limit.price)
+            .min_by(f64::total_cmp)
+    }
+
+    pub fn worst_ask(&self) -> Option<f64> {
+        self.limits[&OrderType::Ask]
+            .iter()
+            .filter(|limit| has_volume(limit.total_volume))
+            .map(|limit| limit.price)
+            .max_by(f64::total_cmp)
+    }
+
+    // Resting volume of one side as tracked by the limits
+    pub fn total_volume(&self, side: OrderType) -> f64 {
+        self.limits[&side]
+            .iter()
+            .map(|limit| limit.total_volume)
+            .sum()
+    }
+
+    // Displayed volume of one side summed from the orders themselves, ignoring the cached
+    // limit volumes, so drift in the cached figures can be detected
+    pub fn recompute_volume(&self, side: OrderType) -> f64 {
+        self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter())
+            .filter(|o| o.visibility == Visibility::Displayed)
+            .map(|o| o.size)
+            .sum()
+    }
+
+    // Aggregated displayed volume of the best `levels` price levels of each side as
+    // (bids, asks), best price first. Levels are keyed by their tick, so prices that only
+    // differ by float noise share one level quoted at the grid price.
+    pub fn depth(&self, levels: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let side_depth = |side| {
+            self.limits_by_priority(side)
+                .into_iter()
+                .take(levels)
+                .map(|limit| (limit.price, limit.total_volume))
+                .collect()
+        };
+        (side_depth(OrderType::Bid), side_depth(OrderType::Ask))
+    }
+
+    // Like depth, with the order count of each level added
+    pub fn depth_detailed(&self, levels: usize) -> (Vec<LevelDetail>, Vec<LevelDetail>) {
+        let side_depth = |side| {
+            self.limits_by_priority(side)
+                .into_iter()
+                .take(levels)
+                .map(|limit| LevelDetail {
+                    price: limit.price,
+                    volume: limit.total_volume,
+                    order_count: limit
+                        .orders
+                        .iter()
+                        .filter(|o| o.visibility == Visibility::Displayed)
+                        .count(),
+                })
+                .collect()
+        };
+        (side_depth(OrderType::Bid), side_depth(OrderType::Ask))
+    }
+
+    // Resting orders of the side as (displayed, total). Icebergs show their peak so they
+    // count as displayed, hidden orders only count towards the total.
+    pub fn order_count_detailed(&self, side: OrderType) -> (usize, usize) {
+        self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter())
+            .fold((0, 0), |(displayed, total), o| {
+                let is_displayed = o.visibility == Visibility::Displayed;
+                (displayed + usize::from(is_displayed), total + 1)
+            })
+    }
+
+    // Size weighted average of how long (seconds) the side's resting orders have been on the
+    // book as of `now`, hidden ones included. None for an empty side.
+    pub fn avg_resting_age(&self, side: OrderType, now: i64) -> Option<f64> {
+        let (weighted_age, volume) = self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter())
+            .fold((0.0, 0.0), |(weighted_age, volume), o| {
+                (
+                    weighted_age + (now - o.timestamp) as f64 * o.size,
+                    volume + o.size,
+                )
+            });
+        has_volume(volume).then(|| weighted_age / volume)
+    }
+
+    // Resting orders on either side that have been on the book for more than `max_age_secs`
+    // as of `now`, oldest first, so forgotten liquidity can be found and pulled
+    pub fn stale_orders(&self, max_age_secs: i64, now: i64) -> Vec<Uuid> {
+        let mut stale: Vec<&Order> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter())
+            .filter(|o| now - o.timestamp > max_age_secs)
+            .collect();
+        stale.sort_by_key(|o| (o.timestamp, o.sequence));
+        stale.into_iter().map(|o| o.id).collect()
+    }
+
+    // Herfindahl index of the side's resting orders, the sum of each order's squared share of
+    // the side's volume (hidden orders included). Ranges from 1/n for n equal orders up to
+    // 1 for a side that is a single order. None for an empty side.
+    pub fn concentration(&self, side: OrderType) -> Option<f64> {
+        let sizes: Vec<f64> = self.limits[&side]
+            .iter()
+            .flat_map(|limit| limit.orders.iter().map(|o| o.size))
+            .collect();
+        let volume: f64 = sizes.iter().sum();
+        has_volume(volume).then(|| sizes.iter().map(|size| (size / volume).powi(2)).sum())
+    }
+
+    // Displayed bid minus ask volume at each distance of 1 to `ticks` ticks from the mid, as
+    // (distance, skew). A level's distance is rounded up to whole ticks, so with the mid
+    // halfway between two ticks both touches sit at distance 1. Distances with nothing
+    // resting report 0, and nothing is returned without a mid.
+    pub fn skew_profile(&self, ticks: u64) -> Vec<(u64, f64)> {
+        let Some(mid) = self.mid_price() else {
+            return vec![];
+        };
+        let mut skew = vec![0.0; ticks as usize];
+        for (side, sign) in [(OrderType::Bid, 1.0), (OrderType::Ask, -1.0)] {
+            for limit in self.limits_by_priority(side) {
+                let distance = ((limit.price - mid).abs() / self.config.tick_size - 1e-9).ceil();
+                let distance = (distance as u64).max(1);
+                if distance > ticks {
+                    break;
+                }
+                skew[distance as usize - 1] += sign * limit.total_volume;
+            }
+        }
+        (1..=ticks).zip(skew).collect()
+    }
+
+    // Displayed volume resting within `pct_from_mid` percent of the mid on each side as
+    // (bids, asks), zeros for a one-sided book
+    pub fn volume_within(&self, pct_from_mid: f64) -> (f64, f64) {
+        let Some(mid) = self.mid_price() else {
+            return (0.0, 0.0);
+        };
+        let band = mid.abs() * pct_from_mid / 100.0;
+        let side_volume = |side| {
+            self.limits_by_priority(side)
+                .into_iter()
+                .take_while(|limit| (limit.price - mid).abs() <= band + 1e-9)
+                .map(|limit| limit.total_volume)
+                .sum()
+        };
+        (side_volume(OrderType::Bid), side_volume(OrderType::Ask))
+    }
+
+    // Displayed notional (price * volume) resting within `depth_pct` percent of the mid on
+    // each side as (bids, asks). Without a mid, a one-sided book is measured from its own
+    // best price.
+    pub fn value_at_touch(&self, depth_pct: f64) -> (f64, f64) {
+        let side_value = |side| {
+            let Some(reference_price) = self
+                .mid_price()
+                .or_else(|| self.best_limit(side).map(|limit| limit.price))
+            else {
+                return 0.0;
+            };
+            let band = reference_price.abs() * depth_pct / 100.0;
+            self.limits_by_priority(side)
+                .into_iter()
+                .take_while(|limit| (limit.price - reference_price).abs() <= band + 1e-9)
+                .map(|limit| self.notional(limit.price, limit.total_volume))
+                .sum()
+        };
+        (side_value(OrderType::Bid), side_value(OrderType::Ask))
+    }
+
+    // Displayed (price, volume) of every non-empty level of one side priced within
+    // [low, high], best price first
+    pub fn levels_in_range(&self, side: OrderType, low: f64, high: f64) -> Vec<PriceLevel> {
+        self.limits_by_priority(side)
+            .into_iter()
+            .filter(|limit| (low..=high).contains(&limit.price))
+            .map(|limit| (limit.price, limit.total_volume))
+            .collect()
+    }
+
+    // Every resting order of one side in matching priority. Hidden orders are only part of
+    // it when explicitly asked for.
+    pub fn orders_l3(&self, side: OrderType, include_hidden: bool) -> Vec<OrderView> {
+        let limits = &self.limits[&side];
+        let mut views = vec![];
+        for limit_idx in self.limit_indices_by_priority(side, include_hidden) {
+            let limit = &limits[limit_idx];
+            let mut orders: Vec<&Order> = limit
+                .orders
+                .iter()
+                .filter(|o| include_hidden || o.visibility == Visibility::Displayed)
+                .collect();
+            orders.sort_by_key(|o| o.visibility == Visibility::Hidden);
+            views.extend(orders.into_iter().map(|o| OrderView {
+                id: o.id,
+                owner: o.owner,
+                order_type: o.order_type,
+                price: limit.price,
+                size: o.size,
+                timestamp: o.timestamp,
+                visibility: o.visibility,
+            }));
+        }
+        views
+    }
+
+    // The mid of the touch, or the configured fallback reference price for a one-sided book
+    pub fn mid_price(&self) -> Option<f64> {
+        self.touch_mid().or(match self.config.mid_fallback {
+            MidFallback::Strict => None,
+            MidFallback::LastTrade => self.last_trade_price(),
+            MidFallback::Mark => self.mark_price,
+        })
+    }
+
+    // Matching only ever uses the real mid, never a fallback
+    fn touch_mid(&self) -> Option<f64> {
+        let (best_bid, _, best_ask, _) = self.touch()?;
+        Some((best_bid + best_ask) / 2.0)
+    }
+
+    // Externally supplied reference price, e.g. an index, used by MidFallback::Mark
+    pub fn set_mark_price(&mut self, mark_price: f64) {
+        self.mark_price = Some(mark_price);
+    }
+
+    // The spread counted in ticks of the band the best bid sits in. None for a one-sided or
+    // crossed book.
+    pub fn spread_ticks(&self) -> Option<u64> {
+        let (best_bid, _, best_ask, _) = self.touch()?;
+        let ticks = ((best_ask - best_bid) / self.tick_size_at(best_bid)).round();
+        (ticks >= 0.0).then_some(ticks as u64)
+    }
+
+    // The spread in basis points of the mid, comparable across instruments whatever their
+    // price. None for a one-sided book or a mid at or below zero.
+    pub fn spread_bps(&self) -> Option<f64> {
+        let (best_bid, _, best_ask, _) = self.touch()?;
+        let mid = (best_bid + best_ask) / 2.0;
+        (mid > 0.0).then(|| (best_ask - best_bid) / mid * 10_000.0)
+    }
+
+    pub fn trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    // Trades the account took part in as maker or taker with a timestamp within `from..=to`.
+    // The tape is in time order so the window is found by binary search before filtering.
+    pub fn account_trades(&self, owner: Uuid, from: i64, to: i64) -> Vec<Trade> {
+        let start = self.trades.partition_point(|trade| trade.timestamp < from);
+        let end = self.trades.partition_point(|trade| trade.timestamp <= to);
+        self.trades[start..end.max(start)]
+            .iter()
+            .filter(|trade| trade.maker_owner == owner || trade.taker_owner == owner)
+            .cloned()
+            .collect()
+    }
+
+    // One netted record per account that traded within `from..=to`, in the order the
+    // accounts first traded. Buys and sells of an account on this book are combined.
+    pub fn settlement_records(&self, from: i64, to: i64) -> Vec<SettlementRecord> {
+        let start = self.trades.partition_point(|trade| trade.timestamp < from);
+        let end = self.trades.partition_point(|trade| trade.timestamp <= to);
+        let mut records: Vec<SettlementRecord> = vec![];
+        for trade in &self.trades[start..end.max(start)] {
+            let notional = self.notional(trade.price, trade.size);
+            let (maker_fee, taker_fee) = self.config.fee_schedule.notional_fees(notional);
+            let buyer = match trade.aggressor {
+                OrderType::Bid => trade.taker_owner,
+                OrderType::Ask => trade.maker_owner,
+            };
+            for (owner, fee) in [
+                (trade.maker_owner, maker_fee),
+                (trade.taker_owner, taker_fee),
+            ] {
+                let position = match records.iter().position(|record| record.owner == owner) {
+                    Some(position) => position,
+                    None => {
+                        records.push(SettlementRecord {
+                            owner,
+                            base_delta: 0.0,
+                            quote_delta: 0.0,
+                            fees: 0.0,
+                            trade_count: 0,
+                        });
+                        records.len() - 1
+                    }
+                };
+                let record = &mut records[position];
+                let direction = if owner == buyer { 1.0 } else { -1.0 };
+                record.base_delta += direction * trade.size;
+                record.quote_delta -= direction * notional;
+                record.fees += fee;
+                record.trade_count += 1;
+            }
+        }
+        records
+    }
+
+    pub fn last_trade_price(&self) -> Option<f64> {
+        self.trades.last().map(|trade| trade.price)
+    }
+
+    // Signed share of the volume traded in the window that buyers initiated, from -1 (only
+    // sells hit the bid) to 1 (only buys lifted the ask). None when nothing traded.
+    pub fn trade_flow_imbalance(&self, window_secs: i64, now: i64) -> Option<f64> {
+        let since = now - window_secs;
+        let (buy_volume, sell_volume) = self
+            .trades
+            .iter()
+            .filter(|trade| trade.timestamp >= since && trade.timestamp <= now)
+            .fold((0.0, 0.0), |(buys, sells), trade| match trade.aggressor {
+                OrderType::Bid => (buys + trade.size, sells),
+                OrderType::Ask => (buys, sells + trade.size),
+            });
+        let total = buy_volume + sell_volume;
+        has_volume(total).then(|| (buy_volume - sell_volume) / total)
+    }
+
+    // Traded size the account provided as maker, the basis for volume tiered rebates
+    pub fn maker_volume(&self, owner: Uuid) -> f64 {
+        self.trades
+            .iter()
+            .filter(|trade| trade.maker_owner == owner)
+            .map(|trade| trade.size)
+            .sum()
+    }
+
+    // Signed size the account bought minus what it sold over all its trades on the book
+    pub fn net_position(&self, owner: Uuid) -> f64 {
+        self.trades
+            .iter()
+            .map(|trade| {
+                let (buyer, seller) = match trade.aggressor {
+                    OrderType::Bid => (trade.taker_owner, trade.maker_owner),
+                    OrderType::Ask => (trade.maker_owner, trade.taker_owner),
+                };
+                let mut position = 0.0;
+                if buyer == owner {
+                    position += trade.size;
+                }
+                if seller == owner {
+                    position -= trade.size;
+                }
+                position
+            })
+            .sum()
+    }
+
+    // Traded size the account took from the book as taker
+    pub fn taker_volume(&self, owner: Uuid) -> f64 {
+        self.trades
+            .iter()
+            .filter(|trade| trade.taker_owner == owner)
+            .map(|trade| trade.size)
+            .sum()
+    }
+
+    // Fees the account paid over all its trades, negative when maker rebates outweigh them
+    pub fn net_fees(&self, owner: Uuid) -> f64 {
+        self.trades
+            .iter()
+            .map(|trade| {
+                let (maker_fee, taker_fee) = self
+                    .config
+                    .fee_schedule
+                    .notional_fees(self.notional(trade.price, trade.size));
+                let mut fees = 0.0;
+                if trade.maker_owner == owner {
+                    fees += maker_fee;
+                }
+                if trade.taker_owner == owner {
+                    fees += taker_fee;
+                }
+                fees
+            })
+            .sum()
+    }
+
+    pub fn depth_columns(&self, levels: usize) -> DepthColumns {
+        self.depth_columns_padded(levels, DepthPadding::None)
+    }
+
+    pub fn depth_columns_padded(&self, levels: usize, padding: DepthPadding) -> DepthColumns {
+        let (bids, asks) = self.depth(levels);
+        let mut columns = DepthColumns {
+            bid_prices: bids.iter().map(|&(price, _)| price).collect(),
+            bid_sizes: bids.iter().map(|&(_, size)| size).collect(),
+            ask_prices: asks.iter().map(|&(price, _)| price).collect(),
+            ask_sizes: asks.iter().map(|&(_, size)| size).collect(),
+        };
+
+        let fill_value = match padding {
+            DepthPadding::None => return columns,
+            DepthPadding::Nan => f64::NAN,
+            DepthPadding::Zero => 0.0,
+        };
+        let rows = bids.len().max(asks.len());
+        for column in [
+            &mut columns.bid_prices,
+            &mut columns.bid_sizes,
+            &mut columns.ask_prices,
+            &mut columns.ask_sizes,
+        ] {
+            column.resize(rows, fill_value);
+        }
+        columns
+    }
+
+    // Old entries are dropped as new ones come in so the log never outgrows the window
+    fn record_activity(&mut self, owner: Uuid, activity: Activity) {
+        let now = self.now();
+        let since = now - self.config.activity_window_secs;
+        let log = self.activity.entry(owner).or_default();
+        log.retain(|&(timestamp, _)| timestamp >= since);
+        log.push((now, activity));
+    }
+
+    // Adds, cancels and trades of the account within the activity window
+    pub fn account_activity(&self, owner: Uuid) -> ActivityStats {
+        let since = self.now() - self.config.activity_window_secs;
+        let mut stats = ActivityStats::default();
+        for &(_, activity) in self
+            .activity
+            .get(&owner)
+            .into_iter()
+            .flatten()
+            .filter(|&&(timestamp, _)| timestamp >= since)
+        {
+            match activity {
+                Activity::Add => stats.adds += 1,
+                Activity::Cancel => stats.cancels += 1,
+                Activity::Trade => stats.trades += 1,
+            }
+        }
+        stats
+    }
+
+    // Owner of a resting order, the anonymous nil owner if it is not resting
+    fn owner_of(&self, order_id: Uuid) -> Uuid {
+        self.view_order(order_id)
+            .map(|(view, _)| view.owner)
+            .unwrap_or_default()
+    }
+
+    // The next order an incoming order would match on one side as (id, size, sequence)
+    fn top_order(&self, side: OrderType) -> Option<(Uuid, f64, u64)> {
+        let &limit_idx = self.limit_indices_by_priority(side, true).first()?;
+        let order = self.limits[&side][limit_idx].next_order_to_fill()?;
+        Some((order.id, order.size, order.sequence))
+    }
+
+    // `maker` is an (order id, owner) pair, `taker` also carries the order class
+    fn record_trade(
+        &mut self,
+        (maker_order_id, maker_owner): (Uuid, Uuid),
+        (taker_order_id, taker_owner, taker_class): (Uuid, Uuid, OrderClass),
+        aggressor: OrderType,
+        price: f64,
+        size: f64,
+        mid_at_trade: Option<f64>,
+    ) {
+        let trade = Trade {
+            maker_order_id,
+            taker_order_id,
+            maker_owner,
+            taker_owner,
+            aggressor,
+            price,
+            size,
+            timestamp: self.now(),
+            mid_at_trade,
+            taker_class,
+        };
+        self.publish(BookEvent::Traded(trade.clone()));
+        self.append_to_trade_log(&trade);
+        self.trades.push(trade);
+        self.record_activity(maker_owner, Activity::Trade);
+        self.record_activity(taker_owner, Activity::Trade);
+        self.record_maker_fill(maker_owner, size);
+    }
+
+    fn record_maker_fill(&mut self, maker_owner: Uuid, size: f64) {
+        let Some(protection) = self.config.market_maker_protection else {
+            return;
+        };
+        if maker_owner.is_nil() {
+            return;
+        }
+        let now = self.now();
+        let fills = self.mmp_fills.entry(maker_owner).or_default();
+        fills.retain(|&(timestamp, _)| now - timestamp < protection.window_secs);
+        fills.push((now, size));
+        let volume: f64 = fills.iter().map(|&(_, size)| size).sum();
+        if fills.len() as u32 > protection.max_fills || volume > protection.max_volume {
+            self.mmp_tripped.insert(maker_owner);
+        }
+    }
+
+    // Cancels what is left of the quotes of accounts that tripped during the last match,
+    // midpoint orders included
+    fn pull_tripped_quotes(&mut self) {
+        let tripped: Vec<Uuid> = self.mmp_tripped.iter().copied().collect();
+        for owner in tripped {
+            let midpoint_ids: Vec<Uuid> = self
+                .midpoint_orders
+                .values()
+                .flatten()
+                .filter(|o| o.owner == owner)
+                .map(|o| o.id)
+                .collect();
+            for order_id in self.open_orders(owner).into_iter().chain(midpoint_ids) {
+                let _ = self.cancel_with_reason(order_id, CancelReason::MmpTrip);
+            }
+        }
+    }
+
+    // Opens a session for the account, orders placed with it are cancelled on disconnect
+    pub fn register_session(&mut self, owner: Uuid) -> Uuid {
+        let session = Uuid::new_v4();
+        self.sessions.insert(session, owner);
+        session
+    }
+
+    // Cancels every order that came in on the session, resting, midpoint, conditional or
+    // delayed, and closes the session. Returns the ids of the cancelled orders, all of which
+    // are reported as cancelled for the disconnect.
+    pub fn on_disconnect(&mut self, session: Uuid) -> Result<Vec<Uuid>, OrderBookError> {
+        self.sessions
+            .remove(&session)
+            .ok_or(OrderBookError::SessionNotFound(session))?;
+        let order_ids: Vec<Uuid> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter())
+            .chain(self.midpoint_orders.values().flatten())
+            .filter(|o| o.session == Some(session))
+            .map(|o| o.id)
+            .collect();
+        let mut cancelled: Vec<Uuid> = order_ids
+            .into_iter()
+            .filter_map(|order_id| {
+                self.cancel_with_reason(order_id, CancelReason::Disconnect)
+                    .ok()
+                    .map(|o| o.id)
+            })
+            .collect();
+
+        // Conditional and delayed orders never rested, so only their cancel gets reported
+        let mut held_back = vec![];
+        self.pending_stops.retain(|(_, stop)| {
+            let keep = stop.session != Some(session);
+            if !keep {
+                held_back.push((stop.id, stop.owner));
+            }
+            keep
+        });
+        self.delayed_orders.retain(|(_, delayed)| {
+            let keep = delayed.session != Some(session);
+            if !keep {
+                held_back.push((delayed.id, delayed.owner));
+            }
+            keep
+        });
+        for (order_id, owner) in held_back {
+            let reason = CancelReason::Disconnect;
+            self.cancel_reasons.insert(order_id, reason);
+            self.publish(BookEvent::OrderCancelled { order_id, reason });
+            self.record_activity(owner, Activity::Cancel);
+            cancelled.push(order_id);
+        }
+        Ok(cancelled)
+    }
+
+    fn check_level_count(&self, side: OrderType, price: f64) -> Result<(), OrderBookError> {
+        let Some(max_levels) = self.config.max_levels_per_side else {
+            return Ok(());
+        };
+        if self
+            .limit_at(side, price)
+            .is_some_and(|limit| !limit.orders.is_empty())
+        {
+            return Ok(());
+        }
+        let levels = self.limits[&side]
+            .iter()
+            .filter(|limit| !limit.orders.is_empty())
+            .count();
+        if levels >= max_levels {
+            return Err(OrderBookError::LevelCountExceeded { price, max_levels });
+        }
+        Ok(())
+    }
+
+    fn check_session(&self, order: &Order) -> Result<(), OrderBookError> {
+        match order.session {
+            Some(session) if self.sessions.get(&session) != Some(&order.owner) => {
+                Err(OrderBookError::SessionNotFound(session))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_mmp(&self, owner: Uuid) -> Result<(), OrderBookError> {
+        if self.mmp_tripped.contains(&owner) {
+            return Err(OrderBookError::MmpTripped(owner));
+        }
+        Ok(())
+    }
+
+    pub fn is_mmp_tripped(&self, owner: Uuid) -> bool {
+        self.mmp_tripped.contains(&owner)
+    }
+
+    // Lets the account quote again with an empty fill window
+    pub fn reset_mmp(&mut self, owner: Uuid) {
+        self.mmp_tripped.remove(&owner);
+        self.mmp_fills.remove(&owner);
+    }
+
+    // Drives batch auction mode: once the current batch has been open for the configured
+    // interval everything is uncrossed at one price and the next batch starts. Returns None
+    // when no batch is due, or when the book trades continuously.
+    pub fn tick(&mut self) -> Option<(f64, Vec<Match>)> {
+        let batch_interval = self.config.batch_interval?;
+        let batch_started_at = self.batch_started_at?;
+        if self.now() - batch_started_at < batch_interval {
+            return None;
+        }
+
+        let uncrossed = self.uncross();
+        self.batch_started_at = None;
+        self.start_auction();
+        Some(uncrossed)
+    }
+
+    // Starts an auction: orders keep resting (and may cross) without matching until the
+    // book is uncrossed
+    pub fn start_auction(&mut self) {
+        self.auction_in_progress = true;
+    }
+
+    // Price that maximizes the executable volume of a crossed book, together with that volume.
+    // Ties are broken by the smallest imbalance between the two sides, then by the lowest
+    // price.
+    fn clearing_price(&self) -> Option<(f64, f64)> {
+        let levels = |side| -> Vec<(f64, f64)> {
+            self.limits[&side]
+                .iter()
+                .filter(|limit| limit.has_liquidity())
+                .map(|limit| {
+                    // Iceberg reserves replenish as their slices fill, so they clear too
+                    let reserve: f64 = limit.orders.iter().map(|o| o.reserve).sum();
+                    (
+                        limit.price,
+                        limit.total_volume + limit.hidden_volume + reserve,
+                    )
+                })
+                .collect()
+        };
+        let (bids, asks) = (levels(OrderType::Bid), levels(OrderType::Ask));
+
+        let mut candidates: Vec<f64> = bids.iter().chain(asks.iter()).map(|&(p, _)| p).collect();
+        candidates.sort_by(f64::total_cmp);
+        candidates.dedup();
+
+        let mut best: Option<(f64, f64, f64)> = None;
+        for price in candidates {
+            let demand: f64 = bids
+                .iter()
+                .filter(|&&(p, _)| p >= price)
+                .map(|&(_, v)| v)
+                .sum();
+            let supply: f64 = asks
+                .iter()
+                .filter(|&&(p, _)| p <= price)
+                .map(|&(_, v)| v)
+                .sum();
+            let executable = demand.min(supply);
+            let imbalance = (demand - supply).abs();
+            if !has_volume(executable) {
+                continue;
+            }
+            let is_better = match best {
+                None => true,
+                Some((_, best_executable, best_imbalance)) => {
+                    executable > best_executable
+                        || (executable == best_executable && imbalance < best_imbalance)
+                }
+            };
+            if is_better {
+                best = Some((price, executable, imbalance));
+            }
+        }
+        best.map(|(price, executable, _)| (price, executable))
+    }
+
+    // Ends the auction by crossing every eligible order at the single price that executes
+    // the most volume. Orders are matched in regular price-time priority on both sides. The
+    // returned price is NaN when the book does not cross.
+    pub fn uncross(&mut self) -> (f64, Vec<Match>) {
+        self.auction_in_progress = false;
+        let Some((clearing_price, mut remaining)) = self.clearing_price() else {
+            return (f64::NAN, vec![]);
+        };
+
+        let mut matches = vec![];
+        while has_volume(remaining) {
+            let (Some(bid), Some(ask)) = (
+                self.top_order(OrderType::Bid),
+                self.top_order(OrderType::Ask),
+            ) else {
+                break;
+            };
+            let ((bid_id, bid_size, bid_sequence), (ask_id, ask_size, ask_sequence)) = (bid, ask);
+            let size_filled = remaining.min(bid_size).min(ask_size);
+            remaining -= size_filled;
+
+            // The order that arrived last is the one that crossed the book
+            let (maker_id, taker_id, aggressor) = if bid_sequence > ask_sequence {
+                (ask_id, bid_id, OrderType::Bid)
+            } else {
+                (bid_id, ask_id, OrderType::Ask)
+            };
+            let mid_at_trade = self.touch_mid();
+            let (maker_owner, taker_owner) = (self.owner_of(maker_id), self.owner_of(taker_id));
+            let taker_class = self
+                .resting_order(taker_id)
+                .map_or(OrderClass::Normal, |taker| taker.class);
+            self.fill_resting_order(bid_id, size_filled, clearing_price)
+                .expect("Top bid has to be resting");
+            self.fill_resting_order(ask_id, size_filled, clearing_price)
+                .expect("Top ask has to be resting");
+            self.record_trade(
+                (maker_id, maker_owner),
+                (taker_id, taker_owner, taker_class),
+                aggressor,
+                clearing_price,
+                size_filled,
+                mid_at_trade,
+            );
+            matches.push(Match {
+                ask_id,
+                bid_id,
+                size_filled,
+                price: clearing_price,
+            });
+        }
+        (clearing_price, matches)
+    }
+
+    // Checks the internal bookkeeping of the book, returning a description of the first
+    // inconsistency found. Meant for tests and fuzzing rather than the hot path.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if let (Some(best_bid), Some(best_ask)) = (
+            self.scan_best_tick(OrderType::Bid),
+            self.scan_best_tick(OrderType::Ask),
+        ) {
+            if best_bid > best_ask {
+                return Err(format!(
+                    "Book is crossed, bid tick {best_bid} > ask tick {best_ask}"
+                ));
+            }
+            // Only a touch policy that rests orders at the touch may lock the book
+            if best_bid == best_ask && self.config.touch_policy != TouchPolicy::Rest {
+                return Err(format!("Book is locked at tick {best_bid}"));
+            }
+        }
+
+        let mut resting_orders = 0;
+        for side in [OrderType::Bid, OrderType::Ask] {
+            if self.best_ticks[&side] != self.scan_best_tick(side) {
+                return Err(format!("Cached best price of the {side} side is stale"));
+            }
+            let (cached, recomputed) = (self.total_volume(side), self.recompute_volume(side));
+            if (cached - recomputed).abs() > 1e-6 {
+                return Err(format!(
+                    "Cached {side} volume {cached} differs from recomputed {recomputed}"
+                ));
+            }
+
+            for (limit_idx, limit) in self.limits[&side].iter().enumerate() {
+                for order in &limit.orders {
+                    resting_orders += 1;
+                    if self.order_index.get(&order.id) != Some(&(side, limit_idx)) {
+                        return Err(format!("Order {} is not indexed correctly", order.id));
+                    }
+                    if !has_volume(order.size) {
+                        return Err(format!("Order {} rests without size", order.id));
+                    }
+                    let owned = self
+                        .orders_by_owner
+                        .get(&order.owner)
+                        .is_some_and(|order_ids| order_ids.contains(&order.id));
+                    if !owned {
+                        return Err(format!("Order {} is missing from its owner", order.id));
+                    }
+                }
+            }
+        }
+
+        if self.order_index.len() != resting_orders {
+            return Err(format!(
+                "Order index has {} entries for {resting_orders} resting orders",
+                self.order_index.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // Pegs the order to the mid price. It first trades against resting midpoint orders of the
+    // other side and whatever is left rests, repricing with the mid as the touch moves.
+    pub fn place_midpoint_order(&mut self, mut order: Order) -> Result<Vec<Match>, OrderBookError> {
+        self.take_rate_token(order.owner)?;
+        let is_resting = |order_book: &Self, order_id| {
+            order_book.order_index.contains_key(&order_id)
+                || order_book
+                    .midpoint_orders
+                    .values()
+                    .flatten()
+                    .any(|order| order.id == order_id)
+        };
+        if is_resting(self, order.id) {
+            return Err(OrderBookError::DuplicateOrderId(order.id));
+        }
+        self.validate_size(order.size)?;
+        self.check_mmp(order.owner)?;
+        self.check_risk(&order)?;
+        self.check_session(&order)?;
+
+        let mut matches = vec![];
+        if let (false, Some(mid)) = (self.auction_in_progress, self.touch_mid()) {
+            matches = self.fill_at_mid(&mut order, mid);
+            self.pull_tripped_quotes();
+        }
+        if has_volume(order.size) {
+            order.sequence = self.next_sequence;
+            self.next_sequence += 1;
+            order.timestamp = self.now();
+            self.midpoint_orders
+                .get_mut(&order.order_type)
+                .expect("Did not find midpoint orders for order type")
+                .push(order);
+        }
+        Ok(matches)
+    }
+
+    pub fn midpoint_orders(&self, side: OrderType) -> &[Order] {
+        &self.midpoint_orders[&side]
+    }
+
+    fn cancel_midpoint_order(&mut self, order_id: Uuid) -> Option<Order> {
+        self.midpoint_orders.values_mut().find_map(|orders| {
+            let i = orders.iter().position(|order| order.id == order_id)?;
+            Some(orders.remove(i))
+        })
+    }
+
+    // Fills the order against the midpoint orders of the other side in arrival order
+    fn fill_at_mid(&mut self, order: &mut Order, mid: f64) -> Vec<Match> {
+        let opposite_side = order.order_type.opposite();
+
+        let mut matches = vec![];
+        while has_volume(order.size) {
+            let resting_orders = self
+                .midpoint_orders
+                .get_mut(&opposite_side)
+                .expect("Did not find midpoint orders for order type");
+            let Some(resting_order) = resting_orders.first_mut() else {
+                break;
+            };
+            let size_filled = order.size.min(resting_order.size);
+            order.size -= size_filled;
+            resting_order.size -= size_filled;
+            resting_order.record_fill(size_filled, mid);
+            order.record_fill(size_filled, mid);
+            let fill_event = BookEvent::from_fill(resting_order, size_filled, mid);
+            let (resting_order_id, resting_owner) = (resting_order.id, resting_order.owner);
+            let filled_order = (!has_volume(resting_order.size)).then(|| resting_orders.remove(0));
+
+            let (ask_id, bid_id) = match order.order_type {
+                OrderType::Bid => (resting_order_id, order.id),
+                OrderType::Ask => (order.id, resting_order_id),
+            };
+            matches.push(Match {
+                ask_id,
+                bid_id,
+                size_filled,
+                price: mid,
+            });
+            self.publish(fill_event);
+            self.record_trade(
+                (resting_order_id, resting_owner),
+                (order.id, order.owner, order.class),
+                order.order_type,
+                mid,
+                size_filled,
+                Some(mid),
+            );
+            if let Some(filled_order) = filled_order {
+                self.archive_filled(filled_order);
+            }
+        }
+
+        matches
+    }
+
+    // Read-only and linear in the number of orders, cheap enough to poll periodically
+    pub fn integrity_report(&self) -> IntegrityReport {
+        let side_integrity = |side| {
+            let limits = &self.limits[&side];
+            let levels = limits
+                .iter()
+                .filter(|limit| !limit.orders.is_empty())
+                .count();
+            SideIntegrity {
+                orders: limits.iter().map(|limit| limit.orders.len()).sum(),
+                levels,
+                empty_limits: limits.len() - levels,
+                cached_volume: self.total_volume(side),
+                recomputed_volume: self.recompute_volume(side),
+            }
+        };
+        let crossed = match (
+            self.best_limit(OrderType::Bid),
+            self.best_limit(OrderType::Ask),
+        ) {
+            (Some(best_bid), Some(best_ask)) => best_bid.price > best_ask.price,
+            _ => false,
+        };
+
+        IntegrityReport {
+            bids: side_integrity(OrderType::Bid),
+            asks: side_integrity(OrderType::Ask),
+            index_entries: self.order_index.len(),
+            crossed,
+            locked: self.is_locked(),
+        }
+    }
+
+    // Repairs a side holding several limits for one tick, as a book built before prices were
+    // keyed by tick could. Orders of the duplicates join the first limit in sequence order,
+    // the merged limit is quoted at its grid price and the indices are rebuilt. Returns how
+    // many duplicate limits were merged away.
+    pub fn dedup_levels(&mut self, side: OrderType) -> usize {
+        let limits = std::mem::take(
+            self.limits
+                .get_mut(&side)
+                .expect("Did not find limits for order type"),
+        );
+        let mut merged: Vec<Limit> = vec![];
+        let mut limits_by_tick: HashMap<i64, usize> = HashMap::new();
+        let mut duplicates = 0;
+        for limit in limits {
+            let tick = self.tick_of(limit.price);
+            match limits_by_tick.get(&tick) {
+                Some(&limit_idx) => {
+                    duplicates += 1;
+                    let target = &mut merged[limit_idx];
+                    target.last_updated = target.last_updated.max(limit.last_updated);
+                    for o in limit.orders {
+                        target.add_order(o);
+                    }
+                }
+                None => {
+                    let mut limit = limit;
+                    limit.price = self.tick_to_price(tick);
+                    limits_by_tick.insert(tick, merged.len());
+                    merged.push(limit);
+                }
+            }
+        }
+
+        for (limit_idx, limit) in merged.iter().enumerate() {
+            for o in &limit.orders {
+                self.order_index.insert(o.id, (side, limit_idx));
+            }
+        }
+        self.limits.insert(side, merged);
+        self.limits_by_price.insert(side, limits_by_tick);
+        self.best_ticks.insert(side, self.scan_best_tick(side));
+        duplicates
+    }
+
+    // Both touches resting at the same price, which resting orders that never matched at
+    // the touch can leave behind
+    pub fn is_locked(&self) -> bool {
+        match (
+            self.best_ticks[&OrderType::Bid],
+            self.best_ticks[&OrderType::Ask],
+        ) {
+            (Some(best_bid), Some(best_ask)) => best_bid == best_ask,
+            _ => false,
+        }
+    }
+
+    // Warms up a fresh book from an external L2 snapshot. Every level has to be on the tick
+    // grid with a valid size and the snapshot must not be crossed, so bad feed data is
+    // rejected instead of ending up as a crossed local book.
+    pub fn from_l2_snapshot(
+        config: OrderBookConfig,
+        bids: &[PriceLevel],
+        asks: &[PriceLevel],
+    ) -> Result<OrderBook, OrderBookError> {
+        let mut order_book = OrderBook::with_config(config);
+        for &(price, size) in bids.iter().chain(asks) {
+            order_book.price_to_tick(price)?;
+            order_book.validate_size(size)?;
+        }
+        let best_bid = bids.iter().map(|&(price, _)| price).reduce(f64::max);
+        let best_ask = asks.iter().map(|&(price, _)| price).reduce(f64::min);
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask)
+            && best_bid >= best_ask
+        {
+            return Err(OrderBookError::CrossedSnapshot { best_bid, best_ask });
+        }
+
+        order_book.apply_l2_update(bids, asks)?;
+        Ok(order_book)
+    }
+
+    // Applies an incremental L2 diff, setting each given level's displayed volume to the
+    // given size and removing it on zero. Levels are replaced by a single synthetic order.
+    // Every update is validated before anything changes so a bad diff leaves the book as is.
+    pub fn apply_l2_update(
+        &mut self,
+        bids: &[PriceLevel],
+        asks: &[PriceLevel],
+    ) -> Result<(), OrderBookError> {
+        for &(price, size) in bids.iter().chain(asks) {
+            self.price_to_tick(price)?;
+            if size != 0.0 {
+                self.validate_size(size)?;
+            }
+        }
+        // A dry run on a detached copy catches level caps, minimum notionals and crossing
+        // levels before this book is touched
+        self.detached_copy().apply_l2_update_unchecked(bids, asks)?;
+        self.apply_l2_update_unchecked(bids, asks)
+    }
+
+    fn apply_l2_update_unchecked(
+        &mut self,
+        bids: &[PriceLevel],
+        asks: &[PriceLevel],
+    ) -> Result<(), OrderBookError> {
+        let updates = || {
+            bids.iter()
+                .map(|&level| (OrderType::Bid, level))
+                .chain(asks.iter().map(|&level| (OrderType::Ask, level)))
+        };
+        for (side, (price, size)) in updates() {
+            let order_ids: Vec<Uuid> = self
+                .limit_at(side, price)
+                .map(|limit| limit.orders.iter().map(|o| o.id).collect())
+                .unwrap_or_default();
+            for order_id in order_ids {
+                self.cancel_with_reason(order_id, CancelReason::Feed)?;
+            }
+            if size > 0.0 {
+                self.rest_order(price, Order::new(side, size))?;
+            }
+        }
+
+        // Only levels this diff rests can cross, a book locked from before is left alone
+        let best_price = |side| self.best_ticks[&side].map(|tick| self.tick_to_price(tick));
+        if let (Some(best_bid), Some(best_ask)) =
+            (best_price(OrderType::Bid), best_price(OrderType::Ask))
+        {
+            let crosses = updates().any(|(side, (price, size))| {
+                size > 0.0
+                    && match side {
+                        OrderType::Bid => price >= best_ask,
+                        OrderType::Ask => price <= best_bid,
+                    }
+            });
+            if crosses {
+                return Err(OrderBookError::CrossedSnapshot { best_bid, best_ask });
+            }
+        }
+        self.trigger_stops();
+        Ok(())
+    }
+
+    // Displayed volume queued ahead of a resting order at its own price. An iceberg ahead only
+    // counts with its visible slice, since its refreshed reserve rejoins the back of the
+    // queue, and hidden orders ahead don't count at all. None when the order is not resting.
+    pub fn displayed_queue_ahead(&self, order_id: Uuid) -> Option<f64> {
+        let order = self.resting_order(order_id)?;
+        let &(side, limit_idx) = self.order_index.get(&order_id)?;
+        let ahead = self.limits[&side][limit_idx]
+            .orders
+            .iter()
+            .filter(|o| o.visibility == Visibility::Displayed)
+            .filter(|o| order.visibility == Visibility::Hidden || o.sequence < order.sequence)
+            .map(|o| o.size)
+            .sum();
+        Some(ahead)
+    }
+
+    // Heuristic chance that a resting order fills within the horizon. Taker volume hitting
+    // the order's side over the last FILL_RATE_LOOKBACK_SECS gives a rate, and volume is
+    // assumed to arrive as a Poisson process at that rate. The order fills once everything
+    // queued ahead of it plus the order itself has traded, so with `need` that volume the
+    // estimate is 1 - exp(-rate * horizon / need). None when the order is not resting.
+    pub fn fill_probability(&self, order_id: Uuid, horizon_secs: i64) -> Option<f64> {
+        let order = self.resting_order(order_id)?;
+        let side = order.order_type;
+        let &(_, own_limit_idx) = self.order_index.get(&order_id)?;
+
+        let limits = &self.limits[&side];
+        let mut volume_ahead = 0.0;
+        for limit_idx in self.limit_indices_by_priority(side, true) {
+            let limit = &limits[limit_idx];
+            if limit_idx != own_limit_idx {
+                volume_ahead += limit.total_volume + limit.hidden_volume;
+                continue;
+            }
+            // Displayed orders fill before hidden ones, each group in arrival order
+            volume_ahead += limit
+                .orders
+                .iter()
+                .filter(|o| match (o.visibility, order.visibility) {
+                    (Visibility::Displayed, Visibility::Hidden) => true,
+                    (Visibility::Hidden, Visibility::Displayed) => false,
+                    _ => o.sequence < order.sequence,
+                })
+                .map(|o| o.size)
+                .sum::<f64>();
+            break;
+        }
+
+        let since = self.now() - FILL_RATE_LOOKBACK_SECS;
+        let traded_volume: f64 = self
+            .trades
+            .iter()
+            .filter(|trade| trade.aggressor == side.opposite() && trade.timestamp >= since)
+            .map(|trade| trade.size)
+            .sum();
+        let rate = traded_volume / FILL_RATE_LOOKBACK_SECS as f64;
+        let need = volume_ahead + order.size;
+        Some(1.0 - (-rate * horizon_secs as f64 / need).exp())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::clock::ManualClock;
+    use crate::events::BookEvent;
+    use crate::fees::FeeSchedule;
+    use crate::order_book::{
+        ActivityStats, AmendCrossPolicy, CancelReason, DepthPadding, DustPolicy, ExecInstructions,
+        ExecutionPrice, IntegrityReport, LevelDetail, LevelFillStats, Limit, LiquidityFlag,
+        MarketMakerProtection, MarketOrderFallback, Match, MidFallback, Order, OrderBook,
+        OrderBookConfig, OrderBookError, OrderClass, OrderType, OrderTypeParseError, RateLimit,
+        SelfTradePrevention, SideIntegrity, SizeSpec, TickSchedule, TimeInForce, TopSample,
+        TouchPolicy, Trade, Visibility, YieldConvention,
+    };
+
+    #[test]
+    fn successfully_adds_a_buy_order_to_a_limit() {
+        // Given
+        let mut limit = Limit::new(10_000.0);
+        let buy_order = Order::new(OrderType::Bid, 5.0);
+
+        // When
+        limit.add_order(buy_order);
+
+        // Then
+        assert_eq!(limit.orders.len(), 1);
+    }
+
+    #[test]
+    fn successfully_removes_a_buy_order_from_a_limit() {
+        // Given
+        let mut limit = Limit::new(10_000.0);
+        let buy_order_a = Order::new(OrderType::Bid, 5.0);
+        let buy_order_b = Order::new(OrderType::Bid, 8.0);
+        let buy_order_c = Order::new(OrderType::Bid, 10.0);
+
+        // Store the ID before moving the order
+        let buy_order_b_id = buy_order_b.id;
+
+        limit.add_order(buy_order_a);
+        limit.add_order(buy_order_b);
+        limit.add_order(buy_order_c);
+
+        // When
+        let _result = limit.remove_order(buy_order_b_id);
+
+        // Then
+        assert_eq!(limit.total_volume, 15.0);
+    }
+
+    #[test]
+    fn successfully_adds_a_buy_order_to_an_order_book() {
+        //Given
+        let mut order_book = OrderBook::new();
+        let buy_order = Order::new(OrderType::Bid, 10.0);
+
+        //When
+        let _result = order_book.add_order(15_000.0, buy_order);
+
+        //Then
+        assert_eq!(order_book.limits.get(&OrderType::Bid).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn successfully_reduces_a_resting_order_without_losing_priority() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let buy_order_a = Order::new(OrderType::Bid, 5.0);
+        let buy_order_b = Order::new(OrderType::Bid, 8.0);
+        let buy_order_a_id = buy_order_a.id;
+        order_book.add_order(15_000.0, buy_order_a).unwrap();
+        order_book.add_order(15_000.0, buy_order_b).unwrap();
+
+        // When
+        let remaining = order_book.reduce_order(buy_order_a_id, 2.0);
+
+        // Then
+        assert_eq!(remaining, Ok(3.0));
+        let limit = &order_book.limits.get(&OrderType::Bid).unwrap()[0];
+        assert_eq!(limit.orders[0].id, buy_order_a_id);
+        assert_eq!(limit.orders[0].size, 3.0);
+        assert_eq!(limit.total_volume, 11.0);
+    }
+
+    #[test]
+    fn successfully_cancels_an_order_reduced_by_its_full_size() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let buy_order = Order::new(OrderType::Bid, 5.0);
+        let buy_order_id = buy_order.id;
+        order_book.add_order(15_000.0, buy_order).unwrap();
+
+        // When
+        let remaining = order_book.reduce_order(buy_order_id, 7.0);
+
+        // Then
+        assert_eq!(remaining, Ok(0.0));
+        let limit = &order_book.limits.get(&OrderType::Bid).unwrap()[0];
+        assert!(limit.orders.is_empty());
+        assert_eq!(limit.total_volume, 0.0);
+        assert_eq!(
+            order_book.reduce_order(buy_order_id, 1.0),
+            Err(OrderBookError::OrderNotFound(buy_order_id))
+        );
+    }
+
+    #[test]
+    fn successfully_finds_the_price_needed_to_fill_a_market_order() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+
+        // When
+        let within_first_level = order_book.price_to_fill(OrderType::Bid, 2.0);
+        let into_second_level = order_book.price_to_fill(OrderType::Bid, 4.0);
+        let whole_book = order_book.price_to_fill(OrderType::Bid, 10.0);
+        let beyond_book = order_book.price_to_fill(OrderType::Bid, 10.5);
+
+        // Then
+        assert_eq!(within_first_level, Some(100.0));
+        assert_eq!(into_second_level, Some(101.0));
+        assert_eq!(whole_book, Some(102.0));
+        assert_eq!(beyond_book, None);
+    }
+
+    #[test]
+    fn successfully_rejects_invalid_prices() {
+        // Given
+        let mut order_book = OrderBook::new();
+
+        // When
+        let nan = order_book.add_order(f64::NAN, Order::new(OrderType::Bid, 1.0));
+        let infinity = order_book.add_order(f64::INFINITY, Order::new(OrderType::Bid, 1.0));
+        let zero = order_book.add_order(0.0, Order::new(OrderType::Bid, 1.0));
+        let off_tick = order_book.add_order(100.005, Order::new(OrderType::Bid, 1.0));
+
+        // Then
+        assert!(matches!(nan, Err(OrderBookError::InvalidPrice(_))));
+        assert!(matches!(infinity, Err(OrderBookError::InvalidPrice(_))));
+        assert!(matches!(
+            zero,
+            Err(OrderBookError::PriceBelowMinimum { .. })
+        ));
+        assert!(matches!(off_tick, Err(OrderBookError::PriceOffTick { .. })));
+        assert!(order_book.order_index.is_empty());
+    }
+
+    #[test]
+    fn successfully_orders_and_matches_prices_around_zero() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_size: 0.5,
+            min_price: -5.0,
+            ..OrderBookConfig::default()
+        });
+        let ask_at_half = Order::new(OrderType::Ask, 2.0);
+        let ask_at_zero = Order::new(OrderType::Ask, 1.0);
+        let ask_at_half_id = ask_at_half.id;
+        let ask_at_zero_id = ask_at_zero.id;
+        order_book
+            .add_order(-1.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(-0.5, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book.add_order(0.5, ask_at_half).unwrap();
+        order_book.add_order(0.0, ask_at_zero).unwrap();
+
+        // When
+        let below_min = order_book.add_order(-5.5, Order::new(OrderType::Bid, 1.0));
+        let bid_prices: Vec<f64> = order_book
+            .limits_by_priority(OrderType::Bid)
+            .iter()
+            .map(|limit| limit.price)
+            .collect();
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // Then
+        assert!(matches!(
+            below_min,
+            Err(OrderBookError::PriceBelowMinimum { .. })
+        ));
+        assert_eq!(bid_prices, vec![-0.5, -1.0]);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].ask_id, ask_at_zero_id);
+        assert_eq!(matches[0].price, 0.0);
+        assert_eq!(matches[1].ask_id, ask_at_half_id);
+        assert_eq!(matches[1].price, 0.5);
+        assert_eq!(matches[1].size_filled, 1.0);
+        assert_eq!(order_book.price_to_fill(OrderType::Bid, 1.0), Some(0.5));
+    }
+
+    #[test]
+    fn successfully_returns_the_queue_at_a_price_in_priority_order() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let buy_order_a = Order::new(OrderType::Bid, 5.0);
+        let buy_order_b = Order::new(OrderType::Bid, 8.0);
+        let buy_order_c = Order::new(OrderType::Bid, 10.0);
+        let buy_order_d = Order::new(OrderType::Bid, 3.0);
+        let (id_a, id_b, id_c, id_d) = (
+            buy_order_a.id,
+            buy_order_b.id,
+            buy_order_c.id,
+            buy_order_d.id,
+        );
+        order_book.add_order(15_000.0, buy_order_a).unwrap();
+        order_book.add_order(15_000.0, buy_order_b).unwrap();
+        order_book.add_order(15_000.0, buy_order_c).unwrap();
+        order_book.add_order(15_000.0, buy_order_d).unwrap();
+
+        // When
+        order_book.cancel_order(id_b).unwrap();
+        let queue = order_book.level_queue(OrderType::Bid, 15_000.0);
+
+        // Then
+        assert_eq!(queue, Some(vec![(id_a, 5.0), (id_c, 10.0), (id_d, 3.0)]));
+        assert_eq!(order_book.level_queue(OrderType::Bid, 14_000.0), None);
+        assert_eq!(order_book.level_queue(OrderType::Ask, 15_000.0), None);
+    }
+
+    #[test]
+    fn successfully_returns_the_touch_of_a_two_sided_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 4.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 6.0))
+            .unwrap();
+
+        // When
+        let touch = order_book.touch();
+
+        // Then
+        assert_eq!(touch, Some((100.0, 3.0, 101.0, 3.0)));
+    }
+
+    #[test]
+    fn successfully_returns_no_touch_for_a_one_sided_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let touch = order_book.touch();
+
+        // Then
+        assert_eq!(touch, None);
+    }
+
+    #[test]
+    fn successfully_cancels_the_stop_leg_when_the_take_profit_leg_fills() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let take_profit = Order::new(OrderType::Ask, 2.0);
+        let stop_leg = Order::new(OrderType::Ask, 2.0);
+        let (take_profit_id, stop_leg_id) = (take_profit.id, stop_leg.id);
+        order_book.add_order(101.0, take_profit).unwrap();
+        order_book.add_order(105.0, stop_leg).unwrap();
+        order_book.link_oco(take_profit_id, stop_leg_id).unwrap();
+
+        // When
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].ask_id, take_profit_id);
+        assert!(!order_book.order_index.contains_key(&stop_leg_id));
+        assert_eq!(order_book.level_queue(OrderType::Ask, 105.0), None);
+        assert!(order_book.oco_links.is_empty());
+    }
+
+    #[test]
+    fn successfully_reduces_the_sibling_proportionally_on_a_partial_fill() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let take_profit = Order::new(OrderType::Ask, 2.0);
+        let stop_leg = Order::new(OrderType::Ask, 4.0);
+        let (take_profit_id, stop_leg_id) = (take_profit.id, stop_leg.id);
+        order_book.add_order(101.0, take_profit).unwrap();
+        order_book.add_order(105.0, stop_leg).unwrap();
+        order_book.link_oco(take_profit_id, stop_leg_id).unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 0.5))
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            order_book.level_queue(OrderType::Ask, 105.0),
+            Some(vec![(stop_leg_id, 3.0)])
+        );
+        assert_eq!(
+            order_book.link_oco(take_profit_id, stop_leg_id),
+            Err(OrderBookError::OrderAlreadyLinked(take_profit_id))
+        );
+    }
+
+    #[test]
+    fn successfully_returns_matches_in_price_then_time_priority() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let first_at_101 = Order::new(OrderType::Ask, 1.0);
+        let first_at_100 = Order::new(OrderType::Ask, 1.0);
+        let second_at_101 = Order::new(OrderType::Ask, 1.0);
+        let second_at_100 = Order::new(OrderType::Ask, 1.0);
+        let expected_sequence = vec![
+            (first_at_100.id, 100.0),
+            (second_at_100.id, 100.0),
+            (first_at_101.id, 101.0),
+            (second_at_101.id, 101.0),
+        ];
+        order_book.add_order(101.0, first_at_101).unwrap();
+        order_book.add_order(100.0, first_at_100).unwrap();
+        order_book.add_order(101.0, second_at_101).unwrap();
+        order_book.add_order(100.0, second_at_100).unwrap();
+
+        // When
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 4.0))
+            .unwrap();
+
+        // Then
+        let match_sequence: Vec<(Uuid, f64)> =
+            matches.iter().map(|m| (m.ask_id, m.price)).collect();
+        assert_eq!(match_sequence, expected_sequence);
+    }
+
+    #[test]
+    fn successfully_rejects_an_order_with_a_duplicate_id() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let order_id = Uuid::new_v4();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0).with_id(order_id))
+            .unwrap();
+
+        // When
+        let result = order_book.add_order(101.0, Order::new(OrderType::Ask, 2.0).with_id(order_id));
+
+        // Then
+        assert_eq!(result, Err(OrderBookError::DuplicateOrderId(order_id)));
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 100.0),
+            Some(vec![(order_id, 1.0)])
+        );
+        assert_eq!(order_book.level_queue(OrderType::Ask, 101.0), None);
+    }
+
+    #[test]
+    fn successfully_returns_only_the_requested_owners_orders() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice_low = Order::new(OrderType::Bid, 1.0).with_owner(alice);
+        let bob_high = Order::new(OrderType::Bid, 2.0).with_owner(bob);
+        let alice_high = Order::new(OrderType::Bid, 3.0).with_owner(alice);
+        let alice_ask = Order::new(OrderType::Ask, 4.0).with_owner(alice);
+        let (alice_low_id, alice_high_id, alice_ask_id) =
+            (alice_low.id, alice_high.id, alice_ask.id);
+        order_book.add_order(99.0, alice_low).unwrap();
+        order_book.add_order(100.0, bob_high).unwrap();
+        order_book.add_order(100.0, alice_high).unwrap();
+        order_book.add_order(101.0, alice_ask).unwrap();
+
+        // When
+        let alice_bids = order_book.orders_by_owner(OrderType::Bid, alice);
+
+        // Then
+        let alice_bid_ids: Vec<Uuid> = alice_bids.iter().map(|view| view.id).collect();
+        assert_eq!(alice_bid_ids, vec![alice_high_id, alice_low_id]);
+        assert_eq!(alice_bids[0].price, 100.0);
+        assert_eq!(alice_bids[0].size, 3.0);
+        assert!(alice_bids.iter().all(|view| view.owner == alice));
+        assert_eq!(
+            order_book.open_orders(alice),
+            vec![alice_low_id, alice_high_id, alice_ask_id]
+        );
+    }
+
+    #[test]
+    fn successfully_keeps_the_cached_best_prices_in_sync_with_the_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let mut resting_order_ids = vec![];
+        let mut seed: u64 = 42;
+        let mut next_random = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            seed >> 33
+        };
+
+        // When
+        for _ in 0..2_000 {
+            let roll = next_random();
+            if roll % 3 == 0 && !resting_order_ids.is_empty() {
+                let idx = (next_random() as usize) % resting_order_ids.len();
+                let order_id = resting_order_ids.swap_remove(idx);
+                order_book.cancel_order(order_id).unwrap();
+            } else if roll % 7 == 0 {
+                let order_type = if next_random() % 2 == 0 {
+                    OrderType::Bid
+                } else {
+                    OrderType::Ask
+                };
+                order_book
+                    .place_market_order(Order::new(order_type, (next_random() % 5 + 1) as f64))
+                    .unwrap();
+                resting_order_ids.retain(|id| order_book.order_index.contains_key(id));
+            } else {
+                // Bids rest between 90 and 99, asks between 101 and 110
+                let offset = (next_random() % 10) as f64;
+                let (order_type, price) = if next_random() % 2 == 0 {
+                    (OrderType::Bid, 99.0 - offset)
+                } else {
+                    (OrderType::Ask, 101.0 + offset)
+                };
+                let order = Order::new(order_type, (next_random() % 5 + 1) as f64);
+                resting_order_ids.push(order.id);
+                order_book.add_order(price, order).unwrap();
+            }
+
+            // Then
+            for side in [OrderType::Bid, OrderType::Ask] {
+                assert_eq!(
+                    order_book.best_ticks[&side],
+                    order_book.scan_best_tick(side)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn successfully_cancels_only_session_orders_at_the_end_of_the_session() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let session_order =
+            Order::new(OrderType::Bid, 1.0).with_time_in_force(TimeInForce::Session);
+        let gtc_order = Order::new(OrderType::Bid, 2.0);
+        let (session_order_id, gtc_order_id) = (session_order.id, gtc_order.id);
+        order_book.add_order(100.0, session_order).unwrap();
+        order_book.add_order(100.0, gtc_order).unwrap();
+
+        // When
+        let cancelled = order_book.end_session();
+
+        // Then
+        assert_eq!(cancelled, vec![session_order_id]);
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 100.0),
+            Some(vec![(gtc_order_id, 2.0)])
+        );
+    }
+
+    #[test]
+    fn successfully_returns_the_worst_price_of_each_side() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let deep_bid = Order::new(OrderType::Bid, 1.0);
+        let deep_bid_id = deep_bid.id;
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(95.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book.add_order(90.0, deep_bid).unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(107.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(103.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // When
+        order_book.cancel_order(deep_bid_id).unwrap();
+
+        // Then
+        assert_eq!(order_book.worst_bid(), Some(95.0));
+        assert_eq!(order_book.worst_ask(), Some(107.0));
+        assert_eq!(OrderBook::new().worst_bid(), None);
+    }
+
+    #[test]
+    fn successfully_keeps_cached_volume_equal_to_recomputed_volume() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let mut order_ids = vec![];
+        for i in 0..10 {
+            let order = Order::new(OrderType::Ask, 0.1 * (i + 1) as f64);
+            order_ids.push(order.id);
+            order_book.add_order(101.0 + i as f64, order).unwrap();
+        }
+
+        // When
+        for (i, order_id) in order_ids.into_iter().enumerate() {
+            let mut order = order_book.cancel_order(order_id).unwrap();
+            order.size = 0.3;
+            order_book.add_order(105.0 - i as f64 * 0.5, order).unwrap();
+        }
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 0.7))
+            .unwrap();
+
+        // Then
+        let cached = order_book.total_volume(OrderType::Ask);
+        let recomputed = order_book.recompute_volume(OrderType::Ask);
+        assert!((cached - recomputed).abs() < 1e-9);
+        assert!((recomputed - 2.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn successfully_fills_a_hidden_order_that_is_absent_from_depth() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let hidden_bid = Order::new(OrderType::Bid, 2.0).with_visibility(Visibility::Hidden);
+        let hidden_bid_id = hidden_bid.id;
+        order_book.add_order(100.0, hidden_bid).unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let depth = order_book.depth(10);
+        let l3_without_hidden = order_book.orders_l3(OrderType::Bid, false);
+        let l3_with_hidden = order_book.orders_l3(OrderType::Bid, true);
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(depth, (vec![(99.0, 1.0)], vec![]));
+        assert_eq!(order_book.touch(), None);
+        assert_eq!(order_book.total_volume(OrderType::Bid), 1.0);
+        assert_eq!(l3_without_hidden.len(), 1);
+        assert_eq!(l3_with_hidden[0].id, hidden_bid_id);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].bid_id, hidden_bid_id);
+        assert_eq!(matches[0].price, 100.0);
+    }
+
+    #[test]
+    fn successfully_fills_displayed_orders_before_hidden_ones_at_the_same_price() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let hidden_ask = Order::new(OrderType::Ask, 1.0).with_visibility(Visibility::Hidden);
+        let displayed_ask = Order::new(OrderType::Ask, 1.0);
+        let (hidden_ask_id, displayed_ask_id) = (hidden_ask.id, displayed_ask.id);
+        order_book.add_order(101.0, hidden_ask).unwrap();
+        order_book.add_order(101.0, displayed_ask).unwrap();
+
+        // When
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // Then
+        let filled: Vec<Uuid> = matches.iter().map(|m| m.ask_id).collect();
+        assert_eq!(filled, vec![displayed_ask_id, hidden_ask_id]);
+    }
+
+    #[test]
+    fn successfully_records_the_mid_at_the_time_of_each_trade() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ask = Order::new(OrderType::Ask, 1.0);
+        let ask_id = ask.id;
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book.add_order(101.0, ask).unwrap();
+        order_book
+            .add_order(103.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let market_order = Order::new(OrderType::Bid, 2.0);
+        let market_order_id = market_order.id;
+
+        // When
+        order_book.place_market_order(market_order).unwrap();
+
+        // Then
+        let trades = order_book.trades();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].maker_order_id, ask_id);
+        assert_eq!(trades[0].taker_order_id, market_order_id);
+        assert_eq!(trades[0].aggressor, OrderType::Bid);
+        assert_eq!(trades[0].mid_at_trade, Some(100.0));
+        assert_eq!(trades[1].price, 103.0);
+        assert_eq!(trades[1].mid_at_trade, Some(101.0));
+    }
+
+    #[test]
+    fn successfully_exports_depth_as_columns_matching_the_tuple_depth() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 4.0))
+            .unwrap();
+
+        // When
+        let (bids, asks) = order_book.depth(5);
+        let columns = order_book.depth_columns(5);
+        let zero_padded = order_book.depth_columns_padded(5, DepthPadding::Zero);
+        let nan_padded = order_book.depth_columns_padded(5, DepthPadding::Nan);
+
+        // Then
+        let bid_rows: Vec<(f64, f64)> = columns
+            .bid_prices
+            .iter()
+            .copied()
+            .zip(columns.bid_sizes.iter().copied())
+            .collect();
+        let ask_rows: Vec<(f64, f64)> = columns
+            .ask_prices
+            .iter()
+            .copied()
+            .zip(columns.ask_sizes.iter().copied())
+            .collect();
+        assert_eq!(bid_rows, bids);
+        assert_eq!(ask_rows, asks);
+        assert_eq!(zero_padded.ask_prices, vec![101.0, 0.0, 0.0]);
+        assert_eq!(zero_padded.ask_sizes, vec![4.0, 0.0, 0.0]);
+        assert_eq!(nan_padded.ask_sizes.len(), 3);
+        assert!(nan_padded.ask_prices[1].is_nan());
+    }
+
+    #[test]
+    fn successfully_uncrosses_an_auction_at_the_volume_maximizing_price() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book.start_auction();
+        for (price, size) in [(102.0, 3.0), (101.0, 2.0), (100.0, 5.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, size))
+                .unwrap();
+        }
+        for (price, size) in [(99.0, 2.0), (100.0, 2.0), (101.0, 4.0), (103.0, 1.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+        let rejected_market_order = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+
+        // When
+        let (clearing_price, matches) = order_book.uncross();
+
+        // Then
+        assert_eq!(
+            rejected_market_order,
+            Err(OrderBookError::AuctionInProgress)
+        );
+        assert_eq!(clearing_price, 101.0);
+        assert!(matches.iter().all(|m| m.price == 101.0));
+        let executed: f64 = matches.iter().map(|m| m.size_filled).sum();
+        assert_eq!(executed, 5.0);
+        assert_eq!(order_book.touch(), Some((100.0, 5.0, 101.0, 3.0)));
+        assert_eq!(order_book.trades().len(), matches.len());
+    }
+
+    #[test]
+    fn successfully_replenishes_an_iceberg_filled_in_an_auction() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book.start_auction();
+        let iceberg = Order::new(OrderType::Ask, 5.0).with_iceberg(1.0);
+        let iceberg_id = iceberg.id();
+        order_book.add_order(100.0, iceberg).unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+
+        // When
+        let (clearing_price, matches) = order_book.uncross();
+
+        // Then
+        assert_eq!(clearing_price, 100.0);
+        let executed: f64 = matches.iter().map(|m| m.size_filled).sum();
+        assert_eq!(executed, 3.0);
+        let iceberg = order_book.get_order(iceberg_id).unwrap();
+        assert_eq!((iceberg.size(), iceberg.reserve()), (1.0, 1.0));
+        assert_eq!(order_book.depth(10), (vec![], vec![(100.0, 1.0)]));
+    }
+
+    #[test]
+    fn successfully_breaks_clearing_price_ties_by_smallest_imbalance() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book.start_auction();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // When
+        let (clearing_price, matches) = order_book.uncross();
+
+        // Then
+        // Both 100 and 101 execute 2, but at 101 demand and supply are balanced
+        assert_eq!(clearing_price, 101.0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].size_filled, 2.0);
+    }
+
+    fn order_book_with_fallback(market_order_fallback: MarketOrderFallback) -> OrderBook {
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            market_order_fallback,
+            ..OrderBookConfig::default()
+        });
+        // Trade once at 100 so there is a reference price, leaving the ask side empty
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+    }
+
+    #[test]
+    fn successfully_returns_unfilled_market_orders_against_an_empty_side_by_default() {
+        // Given
+        let mut order_book = order_book_with_fallback(MarketOrderFallback::ReturnUnfilled);
+
+        // When
+        let result = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+
+        // Then
+        assert_eq!(result, Ok(vec![]));
+        assert_eq!(order_book.depth(1), (vec![], vec![]));
+    }
+
+    #[test]
+    fn successfully_rejects_market_orders_against_an_empty_side() {
+        // Given
+        let mut order_book = order_book_with_fallback(MarketOrderFallback::Reject);
+
+        // When
+        let result = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+
+        // Then
+        assert_eq!(result, Err(OrderBookError::NoLiquidity(OrderType::Ask)));
+    }
+
+    #[test]
+    fn successfully_rests_market_orders_against_an_empty_side_at_the_reference_price() {
+        // Given
+        let mut order_book = order_book_with_fallback(MarketOrderFallback::RestAtReferencePrice);
+        let market_order = Order::new(OrderType::Bid, 2.0);
+        let market_order_id = market_order.id;
+
+        // When
+        let result = order_book.place_market_order(market_order);
+
+        // Then
+        assert_eq!(result, Ok(vec![]));
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 100.0),
+            Some(vec![(market_order_id, 2.0)])
+        );
+    }
+
+    #[test]
+    fn successfully_matches_the_crossing_part_of_a_limit_order_and_rests_the_rest() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        let buy_order = Order::new(OrderType::Bid, 5.0);
+        let buy_order_id = buy_order.id;
+
+        // When
+        let matches = order_book.place_limit_order(101.0, buy_order).unwrap();
+
+        // Then
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].price, 100.0);
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 101.0),
+            Some(vec![(buy_order_id, 3.0)])
+        );
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_matches_two_midpoint_orders_at_the_mid() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        let midpoint_buy = Order::new(OrderType::Bid, 2.0);
+        let midpoint_buy_id = midpoint_buy.id;
+        let midpoint_sell = Order::new(OrderType::Ask, 3.0);
+        let midpoint_sell_id = midpoint_sell.id;
+
+        // When
+        let resting = order_book.place_midpoint_order(midpoint_buy).unwrap();
+        let matches = order_book.place_midpoint_order(midpoint_sell).unwrap();
+
+        // Then
+        assert!(resting.is_empty());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].bid_id, midpoint_buy_id);
+        assert_eq!(matches[0].ask_id, midpoint_sell_id);
+        assert_eq!(matches[0].price, 101.0);
+        assert_eq!(matches[0].size_filled, 2.0);
+        assert_eq!(order_book.midpoint_orders(OrderType::Ask)[0].size, 1.0);
+        let (bids, asks) = order_book.depth(5);
+        assert_eq!(bids, vec![(100.0, 5.0)]);
+        assert_eq!(asks, vec![(102.0, 5.0)]);
+    }
+
+    #[test]
+    fn successfully_reprices_a_resting_midpoint_order_as_the_mid_moves() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        order_book
+            .place_midpoint_order(Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let low_bid = order_book.place_limit_order(101.0, Order::new(OrderType::Bid, 1.0));
+        let market_buy = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+
+        // Then
+        assert_eq!(order_book.mid_price(), Some(101.5));
+        assert!(low_bid.unwrap().is_empty());
+        let matches = market_buy.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].price, 101.5);
+    }
+
+    #[test]
+    fn successfully_rejects_an_order_that_would_push_a_level_over_its_cap() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            max_level_volume: Some(10.0),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 6.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 3.5))
+            .unwrap();
+
+        // When
+        let breaching = order_book.add_order(100.0, Order::new(OrderType::Bid, 1.0));
+        let filling_up = order_book.add_order(100.0, Order::new(OrderType::Bid, 0.5));
+
+        // Then
+        assert_eq!(
+            breaching,
+            Err(OrderBookError::LevelVolumeExceeded {
+                price: 100.0,
+                max_volume: 10.0
+            })
+        );
+        assert_eq!(filling_up, Ok(()));
+        assert_eq!(order_book.total_volume(OrderType::Bid), 10.0);
+    }
+
+    #[test]
+    fn successfully_keeps_an_order_whose_amend_breaches_the_level_cap() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            max_level_volume: Some(10.0),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(101.0, Order::new(OrderType::Bid, 9.0))
+            .unwrap();
+        let bid = Order::new(OrderType::Bid, 5.0);
+        let bid_id = bid.id();
+        order_book.add_order(100.0, bid).unwrap();
+        let behind = Order::new(OrderType::Bid, 1.0);
+        let behind_id = behind.id();
+        order_book.add_order(100.0, behind).unwrap();
+        let ask = Order::new(OrderType::Ask, 1.0);
+        let ask_id = ask.id();
+        order_book.add_order(105.0, ask).unwrap();
+        order_book.link_oco(bid_id, ask_id).unwrap();
+
+        // When
+        let amended = order_book.amend_order(bid_id, 101.0, 5.0);
+
+        // Then
+        assert_eq!(
+            amended,
+            Err(OrderBookError::LevelVolumeExceeded {
+                price: 101.0,
+                max_volume: 10.0
+            })
+        );
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 100.0),
+            Some(vec![(bid_id, 5.0), (behind_id, 1.0)])
+        );
+        assert_eq!(
+            order_book.link_oco(bid_id, behind_id),
+            Err(OrderBookError::OrderAlreadyLinked(bid_id))
+        );
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_flips_an_order_type_to_the_opposite_side() {
+        // Given
+        let (bid, ask) = (OrderType::Bid, OrderType::Ask);
+
+        // When
+        let (bid_opposite, ask_opposite) = (bid.opposite(), ask.opposite());
+
+        // Then
+        assert_eq!(bid_opposite, OrderType::Ask);
+        assert_eq!(ask_opposite, OrderType::Bid);
+        assert_eq!(bid.opposite().opposite(), bid);
+    }
+
+    #[test]
+    fn successfully_avoids_leaving_dust_on_a_partially_filled_resting_order() {
+        // Given
+        let config = |dust_policy| OrderBookConfig {
+            min_fill_increment: Some(1.0),
+            dust_policy,
+            ..OrderBookConfig::default()
+        };
+        let mut filling_book = OrderBook::with_config(config(DustPolicy::FillResting));
+        let mut skipping_book = OrderBook::with_config(config(DustPolicy::SkipResting));
+        let mut resting_ids = vec![];
+        for order_book in [&mut filling_book, &mut skipping_book] {
+            let ask_a = Order::new(OrderType::Ask, 5.0);
+            let ask_b = Order::new(OrderType::Ask, 5.0);
+            resting_ids.push((ask_a.id, ask_b.id));
+            order_book.add_order(100.0, ask_a).unwrap();
+            order_book.add_order(100.0, ask_b).unwrap();
+        }
+
+        // When
+        let filled = filling_book
+            .place_market_order(Order::new(OrderType::Bid, 4.5))
+            .unwrap();
+        let skipped = skipping_book
+            .place_market_order(Order::new(OrderType::Bid, 4.5))
+            .unwrap();
+
+        // Then
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].ask_id, resting_ids[0].0);
+        assert_eq!(filled[0].size_filled, 5.0);
+        assert_eq!(
+            filling_book.level_queue(OrderType::Ask, 100.0),
+            Some(vec![(resting_ids[0].1, 5.0)])
+        );
+        assert!(skipped.is_empty());
+        assert_eq!(
+            skipping_book.level_queue(OrderType::Ask, 100.0),
+            Some(vec![(resting_ids[1].0, 5.0), (resting_ids[1].1, 5.0)])
+        );
+    }
+
+    #[test]
+    fn successfully_lists_only_the_levels_within_a_price_range() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for (price, size) in [
+            (95.0, 1.0),
+            (97.0, 2.0),
+            (98.5, 3.0),
+            (99.0, 4.0),
+            (101.0, 5.0),
+        ] {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, size))
+                .unwrap();
+        }
+
+        // When
+        let levels = order_book.levels_in_range(OrderType::Bid, 97.0, 99.0);
+
+        // Then
+        assert_eq!(levels, vec![(99.0, 4.0), (98.5, 3.0), (97.0, 2.0)]);
+    }
+
+    #[test]
+    fn successfully_validates_prices_against_the_tick_of_their_band() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_schedule: Some(TickSchedule::new(vec![(0.0, 0.01), (1_000.0, 0.5)])),
+            ..OrderBookConfig::default()
+        });
+
+        // When
+        let low_band = order_book.add_order(999.25, Order::new(OrderType::Bid, 1.0));
+        let high_band = order_book.add_order(1_000.25, Order::new(OrderType::Ask, 1.0));
+        let high_band_on_grid = order_book.add_order(1_000.5, Order::new(OrderType::Ask, 1.0));
+
+        // Then
+        assert_eq!(low_band, Ok(()));
+        assert_eq!(
+            high_band,
+            Err(OrderBookError::PriceOffTick {
+                price: 1_000.25,
+                tick_size: 0.5
+            })
+        );
+        assert_eq!(high_band_on_grid, Ok(()));
+        assert_eq!(order_book.round_to_tick(1_000.3), 1_000.5);
+        assert_eq!(order_book.touch(), Some((999.25, 1.0, 1_000.5, 1.0)));
+    }
+
+    #[test]
+    fn successfully_attributes_maker_and_taker_volume_to_each_owner() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 3.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 4.0).with_owner(maker))
+            .unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0).with_owner(taker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.5).with_owner(taker))
+            .unwrap();
+
+        // Then
+        assert_eq!(order_book.maker_volume(maker), 3.5);
+        assert_eq!(order_book.taker_volume(maker), 0.0);
+        assert_eq!(order_book.maker_volume(taker), 0.0);
+        assert_eq!(order_book.taker_volume(taker), 3.5);
+        let trade = &order_book.trades()[0];
+        assert_eq!(
+            trade.liquidity_flag(OrderType::Ask),
+            LiquidityFlag::MakerAdd
+        );
+        assert_eq!(
+            trade.liquidity_flag(OrderType::Bid),
+            LiquidityFlag::TakerRemove
+        );
+    }
+
+    #[test]
+    fn successfully_spends_a_quote_amount_on_a_market_buy() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // When
+        let matches = order_book
+            .place_sized_market_order(Order::new(OrderType::Bid, 1.0), SizeSpec::Quote(251.5))
+            .unwrap();
+
+        // Then
+        let base_filled: f64 = matches.iter().map(|m| m.size_filled).sum();
+        let quote_spent: f64 = matches.iter().map(|m| m.size_filled * m.price).sum();
+        assert_eq!(matches.len(), 2);
+        assert!((base_filled - 2.5).abs() < 1e-9);
+        assert!((quote_spent - 251.5).abs() < 1e-6);
+        assert_eq!(matches[1].price, 101.0);
+    }
+
+    #[test]
+    fn successfully_reports_the_integrity_figures_of_a_known_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let bid = Order::new(OrderType::Bid, 2.0);
+        let bid_id = bid.id;
+        order_book.add_order(99.0, bid).unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.5))
+            .unwrap();
+        order_book.cancel_order(bid_id).unwrap();
+
+        // When
+        let report = order_book.integrity_report();
+
+        // Then
+        assert_eq!(
+            report,
+            IntegrityReport {
+                bids: SideIntegrity {
+                    orders: 1,
+                    levels: 1,
+                    empty_limits: 1,
+                    cached_volume: 1.0,
+                    recomputed_volume: 1.0,
+                },
+                asks: SideIntegrity {
+                    orders: 2,
+                    levels: 1,
+                    empty_limits: 0,
+                    cached_volume: 4.5,
+                    recomputed_volume: 4.5,
+                },
+                index_entries: 3,
+                crossed: false,
+                locked: false,
+            }
+        );
+    }
+
+    #[test]
+    fn successfully_applies_l2_updates_that_add_change_and_remove_levels() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .apply_l2_update(&[(99.0, 2.0), (98.0, 3.0)], &[(101.0, 1.0), (102.0, 4.0)])
+            .unwrap();
+
+        // When
+        let applied =
+            order_book.apply_l2_update(&[(99.0, 5.0), (97.0, 1.0)], &[(101.0, 0.0), (103.0, 2.0)]);
+        let rejected = order_book.apply_l2_update(&[(96.0, 1.0)], &[(104.005, 1.0)]);
+
+        // Then
+        assert_eq!(applied, Ok(()));
+        assert_eq!(
+            rejected,
+            Err(OrderBookError::PriceOffTick {
+                price: 104.005,
+                tick_size: 0.01
+            })
+        );
+        let (bids, asks) = order_book.depth(5);
+        assert_eq!(bids, vec![(99.0, 5.0), (98.0, 3.0), (97.0, 1.0)]);
+        assert_eq!(asks, vec![(102.0, 4.0), (103.0, 2.0)]);
+    }
+
+    #[test]
+    fn successfully_rejects_an_l2_update_that_would_only_partly_apply_or_cross() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            max_level_volume: Some(10.0),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .apply_l2_update(&[(99.0, 2.0)], &[(101.0, 1.0)])
+            .unwrap();
+        let bid_id = order_book.limit_at(OrderType::Bid, 99.0).unwrap().orders[0].id;
+
+        // When
+        let over_cap = order_book.apply_l2_update(&[(99.0, 0.0), (98.0, 11.0)], &[]);
+        let crossing = order_book.apply_l2_update(&[(99.0, 0.0), (101.0, 1.0)], &[]);
+        let untouched = order_book.get_order(bid_id).is_some();
+        let removed = order_book.apply_l2_update(&[(99.0, 0.0)], &[]);
+
+        // Then
+        assert_eq!(
+            over_cap,
+            Err(OrderBookError::LevelVolumeExceeded {
+                price: 98.0,
+                max_volume: 10.0
+            })
+        );
+        assert_eq!(
+            crossing,
+            Err(OrderBookError::CrossedSnapshot {
+                best_bid: 101.0,
+                best_ask: 101.0
+            })
+        );
+        assert!(untouched);
+        assert_eq!(removed, Ok(()));
+        assert_eq!(order_book.cancel_reason(bid_id), Some(CancelReason::Feed));
+        let (bids, asks) = order_book.depth(5);
+        assert!(bids.is_empty());
+        assert_eq!(asks, vec![(101.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_executes_at_the_taker_price_when_asked_to() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        let quoted_buy =
+            Order::new(OrderType::Bid, 1.0).with_execution_price(ExecutionPrice::Taker);
+
+        // When
+        let quoted = order_book.place_limit_order(100.5, quoted_buy).unwrap();
+        let default = order_book
+            .place_limit_order(100.5, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(quoted[0].price, 100.5);
+        assert_eq!(order_book.trades()[0].price, 100.5);
+        assert_eq!(default[0].price, 100.0);
+        assert_eq!(order_book.trades()[1].price, 100.0);
+    }
+
+    #[test]
+    fn successfully_reports_order_counts_and_average_sizes_per_level() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 50.0))
+            .unwrap();
+        for _ in 0..4 {
+            order_book
+                .add_order(101.0, Order::new(OrderType::Ask, 2.5))
+                .unwrap();
+        }
+        order_book
+            .add_order(
+                101.0,
+                Order::new(OrderType::Ask, 5.0).with_visibility(Visibility::Hidden),
+            )
+            .unwrap();
+
+        // When
+        let (bids, asks) = order_book.depth_detailed(5);
+
+        // Then
+        assert!(bids.is_empty());
+        assert_eq!(
+            asks,
+            vec![
+                LevelDetail {
+                    price: 100.0,
+                    volume: 50.0,
+                    order_count: 1
+                },
+                LevelDetail {
+                    price: 101.0,
+                    volume: 10.0,
+                    order_count: 4
+                },
+            ]
+        );
+        assert_eq!(asks[0].average_order_size(), 50.0);
+        assert_eq!(asks[1].average_order_size(), 2.5);
+    }
+
+    #[test]
+    fn successfully_cancels_the_front_and_back_of_a_level_queue() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let orders: Vec<Order> = (0..3).map(|_| Order::new(OrderType::Bid, 1.0)).collect();
+        let ids: Vec<Uuid> = orders.iter().map(|o| o.id).collect();
+        for order in orders {
+            order_book.add_order(100.0, order).unwrap();
+        }
+
+        // When
+        let front = order_book.cancel_front(OrderType::Bid, 100.0);
+        let back = order_book.cancel_back(OrderType::Bid, 100.0);
+        let empty_level = order_book.cancel_front(OrderType::Bid, 99.0);
+
+        // Then
+        assert_eq!(front, Some(ids[0]));
+        assert_eq!(back, Some(ids[2]));
+        assert_eq!(empty_level, None);
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 100.0),
+            Some(vec![(ids[1], 1.0)])
+        );
+    }
+
+    #[test]
+    fn successfully_lets_a_liquidation_order_through_the_price_band() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            price_band: Some(0.05),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(90.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+
+        // When
+        let normal = order_book.place_market_order(Order::new(OrderType::Ask, 3.0));
+        let liquidation = order_book.place_market_order(
+            Order::new(OrderType::Ask, 3.0).with_class(OrderClass::Liquidation),
+        );
+
+        // Then
+        assert_eq!(
+            normal,
+            Err(OrderBookError::PriceBandExceeded {
+                price: 90.0,
+                reference_price: 100.0
+            })
+        );
+        assert_eq!(liquidation.unwrap().len(), 2);
+        assert_eq!(order_book.trades().len(), 2);
+        assert!(
+            order_book
+                .trades()
+                .iter()
+                .all(|trade| trade.taker_class == OrderClass::Liquidation)
+        );
+    }
+
+    #[test]
+    fn successfully_clears_a_batch_at_one_price_once_the_interval_passes() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            batch_interval: Some(10),
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        order_book
+            .place_limit_order(101.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book
+            .place_limit_order(99.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        clock.advance(5);
+        let early_tick = order_book.tick();
+        order_book
+            .place_limit_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // When
+        clock.advance(5);
+        let batch = order_book.tick();
+
+        // Then
+        assert!(early_tick.is_none());
+        let (clearing_price, matches) = batch.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.price == clearing_price));
+        assert_eq!(order_book.touch(), None);
+        assert_eq!(order_book.tick(), None);
+    }
+
+    #[test]
+    fn successfully_sums_the_notional_resting_within_a_band_of_the_mid() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for (side, price, size) in [
+            (OrderType::Bid, 99.5, 2.0),
+            (OrderType::Bid, 99.0, 1.0),
+            (OrderType::Bid, 98.0, 10.0),
+            (OrderType::Ask, 100.5, 3.0),
+            (OrderType::Ask, 102.0, 10.0),
+        ] {
+            order_book.add_order(price, Order::new(side, size)).unwrap();
+        }
+        let mut one_sided_book = OrderBook::new();
+        one_sided_book
+            .add_order(50.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // When
+        let (bid_value, ask_value) = order_book.value_at_touch(1.0);
+        let one_sided = one_sided_book.value_at_touch(1.0);
+
+        // Then
+        assert_eq!(bid_value, 99.5 * 2.0 + 99.0 * 1.0);
+        assert_eq!(ask_value, 100.5 * 3.0);
+        assert_eq!(one_sided, (0.0, 50.0));
+    }
+
+    #[test]
+    fn successfully_estimates_a_higher_fill_probability_at_the_front_of_the_touch() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        let front = Order::new(OrderType::Bid, 1.0);
+        let front_id = front.id;
+        order_book.add_order(100.0, front).unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 4.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 10.0))
+            .unwrap();
+        let deep = Order::new(OrderType::Bid, 1.0);
+        let deep_id = deep.id;
+        order_book.add_order(99.0, deep).unwrap();
+
+        // When
+        let front_probability = order_book.fill_probability(front_id, 60).unwrap();
+        let deep_probability = order_book.fill_probability(deep_id, 60).unwrap();
+
+        // Then
+        assert!(front_probability > deep_probability);
+        assert!(deep_probability > 0.0);
+        assert!(front_probability < 1.0);
+        assert_eq!(order_book.fill_probability(Uuid::new_v4(), 60), None);
+    }
+
+    #[test]
+    fn successfully_adds_an_order_given_as_decimal_strings() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let order = Order::from_decimal_str(OrderType::Bid, "0.3").unwrap();
+
+        // When
+        let added = order_book.add_order_decimal_str("100.25", order);
+        let invalid = order_book
+            .add_order_decimal_str("1e3", Order::from_decimal_str(OrderType::Bid, "1").unwrap());
+
+        // Then
+        assert_eq!(added, Ok(()));
+        assert!(matches!(invalid, Err(OrderBookError::InvalidDecimal(_))));
+        assert_eq!(order_book.depth(1).0, vec![(100.25, 0.3)]);
+    }
+
+    #[test]
+    fn successfully_tracks_the_cancel_ratio_of_an_account_over_the_window() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let (spoofer, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let stale = Order::new(OrderType::Bid, 1.0).with_owner(spoofer);
+        let stale_id = stale.id;
+        order_book.add_order(90.0, stale).unwrap();
+        order_book.cancel_order(stale_id).unwrap();
+        clock.advance(120);
+
+        // When
+        for _ in 0..9 {
+            let order = Order::new(OrderType::Bid, 1.0).with_owner(spoofer);
+            let order_id = order.id;
+            order_book.add_order(99.0, order).unwrap();
+            order_book.cancel_order(order_id).unwrap();
+        }
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0).with_owner(spoofer))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0).with_owner(other))
+            .unwrap();
+
+        // Then
+        let stats = order_book.account_activity(spoofer);
+        assert_eq!(
+            stats,
+            ActivityStats {
+                adds: 10,
+                cancels: 9,
+                trades: 1
+            }
+        );
+        assert_eq!(stats.cancel_to_add_ratio(), 0.9);
+        assert_eq!(stats.order_to_trade_ratio(), 10.0);
+        assert_eq!(order_book.account_activity(other).trades, 1);
+    }
+
+    #[test]
+    fn successfully_triggers_a_stop_order_once_the_last_trade_reaches_it() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        let stop = Order::new(OrderType::Ask, 2.0);
+        let stop_id = stop.id;
+        order_book.place_stop_order(100.0, stop).unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(order_book.pending_stops().count(), 0);
+        let last_trade = order_book.trades().last().unwrap();
+        assert_eq!(last_trade.taker_order_id, stop_id);
+        assert_eq!(last_trade.price, 98.0);
+    }
+
+    #[test]
+    fn successfully_drops_a_stop_order_that_expired_before_triggering() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        order_book
+            .place_stop_order(100.0, Order::new(OrderType::Ask, 2.0).with_expiry(1_010))
+            .unwrap();
+        let expiring_stop = Order::new(OrderType::Bid, 2.0).with_expiry(1_015);
+        let expiring_stop_id = expiring_stop.id;
+        order_book.place_stop_order(500.0, expiring_stop).unwrap();
+        let expiring_bid = Order::new(OrderType::Bid, 1.0).with_expiry(1_015);
+        let expiring_bid_id = expiring_bid.id;
+        order_book.add_order(97.0, expiring_bid).unwrap();
+        let purged_early = order_book.purge_expired();
+        clock.advance(20);
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let purged = order_book.purge_expired();
+
+        // Then
+        assert!(purged_early.is_empty());
+        assert_eq!(order_book.trades().len(), 1);
+        assert_eq!(order_book.pending_stops().count(), 0);
+        assert_eq!(purged, vec![expiring_bid_id]);
+        assert_eq!(order_book.total_volume(OrderType::Bid), 5.0);
+        assert!(
+            !order_book
+                .pending_stops()
+                .any(|stop| stop.id == expiring_stop_id)
+        );
+    }
+
+    #[test]
+    fn successfully_treats_a_limit_drained_by_many_partial_fills_as_empty() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for size in [0.1, 0.2, 0.3] {
+            order_book
+                .add_order(100.0, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+
+        // When
+        for _ in 0..6 {
+            order_book
+                .place_market_order(Order::new(OrderType::Bid, 0.1))
+                .unwrap();
+        }
+
+        // Then
+        assert_ne!(
+            (0..6).fold(0.1 + 0.2 + 0.3, |volume: f64, _| volume - 0.1),
+            0.0
+        );
+        assert_eq!(order_book.total_volume(OrderType::Ask), 0.0);
+        assert_eq!(order_book.level_queue(OrderType::Ask, 100.0), None);
+        assert_eq!(order_book.depth(1).1, vec![]);
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_cancels_an_owners_quotes_beyond_the_top_levels() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let (market_maker, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut quote_ids = vec![];
+        for price in [100.0, 99.0, 98.0, 97.0, 96.0] {
+            let quote = Order::new(OrderType::Bid, 1.0).with_owner(market_maker);
+            quote_ids.push(quote.id);
+            order_book.add_order(price, quote).unwrap();
+        }
+        let other_deep_bid = Order::new(OrderType::Bid, 1.0).with_owner(other);
+        let other_deep_bid_id = other_deep_bid.id;
+        order_book.add_order(95.0, other_deep_bid).unwrap();
+
+        // When
+        let cancelled = order_book.cancel_beyond(market_maker, OrderType::Bid, 2);
+
+        // Then
+        assert_eq!(cancelled, quote_ids[2..].to_vec());
+        assert_eq!(
+            order_book.open_orders(market_maker),
+            quote_ids[..2].to_vec()
+        );
+        assert_eq!(order_book.open_orders(other), vec![other_deep_bid_id]);
+    }
+
+    #[test]
+    fn successfully_looks_up_and_cancels_an_order_by_its_client_order_id() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let order = Order::new(OrderType::Bid, 1.0)
+            .with_owner(owner)
+            .with_client_order_id("ladder-1");
+        let order_id = order.id;
+        order_book.add_order(99.0, order).unwrap();
+
+        // When
+        let duplicate = order_book.add_order(
+            98.0,
+            Order::new(OrderType::Bid, 1.0)
+                .with_owner(owner)
+                .with_client_order_id("ladder-1"),
+        );
+        let other_owner = order_book.add_order(
+            98.0,
+            Order::new(OrderType::Bid, 1.0)
+                .with_owner(other)
+                .with_client_order_id("ladder-1"),
+        );
+        let looked_up = order_book.order_id_by_client_id(owner, "ladder-1");
+        let cancelled = order_book.cancel_by_client_id(owner, "ladder-1");
+
+        // Then
+        assert_eq!(
+            duplicate,
+            Err(OrderBookError::DuplicateClientOrderId(
+                "ladder-1".to_string()
+            ))
+        );
+        assert_eq!(other_owner, Ok(()));
+        assert_eq!(looked_up, Some(order_id));
+        assert_eq!(cancelled.unwrap().id, order_id);
+        assert_eq!(order_book.order_id_by_client_id(owner, "ladder-1"), None);
+        assert_eq!(
+            order_book.cancel_by_client_id(owner, "ladder-1").err(),
+            Some(OrderBookError::ClientOrderIdNotFound(
+                "ladder-1".to_string()
+            ))
+        );
+        assert!(
+            order_book
+                .order_id_by_client_id(other, "ladder-1")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn successfully_detects_a_locked_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let unlocked = order_book.is_locked();
+
+        // When
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // Then
+        assert!(!unlocked);
+        assert!(order_book.is_locked());
+        let report = order_book.integrity_report();
+        assert!(report.locked);
+        assert!(!report.crossed);
+    }
+
+    #[test]
+    fn successfully_credits_maker_rebates_in_net_fees() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            fee_schedule: FeeSchedule::new(-2.0, 5.0),
+            ..OrderBookConfig::default()
+        });
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 10.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 2.0).with_owner(taker))
+            .unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 10.0).with_owner(taker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0).with_owner(maker))
+            .unwrap();
+
+        // Then
+        let (maker_fee, taker_fee) = order_book
+            .config()
+            .fee_schedule
+            .trade_fees(&order_book.trades()[0]);
+        assert_eq!(maker_fee, -0.2);
+        assert_eq!(taker_fee, 0.5);
+        // Each side pays 5bps as taker and gets 2bps back as maker
+        assert!((order_book.net_fees(taker) - (0.5 - 0.0198)).abs() < 1e-12);
+        assert!((order_book.net_fees(maker) - (-0.2 + 0.0495)).abs() < 1e-12);
+        assert!(order_book.net_fees(maker) < 0.0);
+    }
+
+    #[test]
+    fn successfully_lists_all_resting_order_ids() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let orders = [
+            (99.0, Order::new(OrderType::Bid, 1.0)),
+            (98.0, Order::new(OrderType::Bid, 2.0)),
+            (101.0, Order::new(OrderType::Ask, 1.0)),
+            (101.0, Order::new(OrderType::Ask, 3.0)),
+        ];
+        let ids: Vec<Uuid> = orders.iter().map(|(_, order)| order.id).collect();
+        for (price, order) in orders {
+            order_book.add_order(price, order).unwrap();
+        }
+
+        // When
+        order_book.cancel_order(ids[1]).unwrap();
+        let all_order_ids: HashSet<Uuid> = order_book.all_order_ids().into_iter().collect();
+
+        // Then
+        assert_eq!(all_order_ids, HashSet::from([ids[0], ids[2], ids[3]]));
+    }
+
+    #[test]
+    fn successfully_holds_a_delayed_order_until_it_is_promoted() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let ask = Order::new(OrderType::Ask, 2.0).with_not_before(1_060);
+        let ask_id = ask.id;
+        order_book.place_limit_order(100.0, ask).unwrap();
+
+        // When
+        let early_match = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+        let early_promotion = order_book.promote_due(order_book.now());
+        clock.advance(60);
+        let promoted = order_book.promote_due(order_book.now());
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(early_match, Ok(vec![]));
+        assert!(early_promotion.is_empty());
+        assert_eq!(promoted, vec![ask_id]);
+        assert_eq!(order_book.delayed_orders().count(), 0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].ask_id, ask_id);
+        assert_eq!(order_book.get_order(ask_id).unwrap().size(), 1.0);
+    }
+
+    #[test]
+    fn successfully_rejects_aggressive_orders_during_the_cooldown_after_a_large_move() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            cooldown_move: Some(0.05),
+            cooldown_secs: 10,
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(110.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        order_book
+            .place_limit_order(110.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let during_cooldown = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+        let passive = order_book.place_limit_order(105.0, Order::new(OrderType::Bid, 1.0));
+        clock.advance(10);
+        let after_cooldown = order_book.place_market_order(Order::new(OrderType::Bid, 1.0));
+
+        // Then
+        assert_eq!(
+            during_cooldown,
+            Err(OrderBookError::CooldownInProgress { until: 1_010 })
+        );
+        assert_eq!(passive, Ok(vec![]));
+        assert_eq!(after_cooldown.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn successfully_computes_the_trade_flow_imbalance_over_a_window() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 10.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 10.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 4.0))
+            .unwrap();
+        clock.advance(60);
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(order_book.trade_flow_imbalance(30, 1_060), Some(0.5));
+        assert_eq!(order_book.trade_flow_imbalance(60, 1_060), Some(-0.25));
+        assert_eq!(order_book.trade_flow_imbalance(20, 1_030), None);
+    }
+
+    #[test]
+    fn successfully_rejects_a_crossed_or_off_tick_l2_snapshot() {
+        // Given
+        let bids = [(100.0, 1.0), (99.0, 2.0)];
+        let crossed_asks = [(100.0, 1.0), (101.0, 2.0)];
+        let off_tick_asks = [(101.005, 1.0)];
+        let asks = [(101.0, 1.0)];
+
+        // When
+        let crossed = OrderBook::from_l2_snapshot(OrderBookConfig::default(), &bids, &crossed_asks);
+        let off_tick =
+            OrderBook::from_l2_snapshot(OrderBookConfig::default(), &bids, &off_tick_asks);
+        let order_book = OrderBook::from_l2_snapshot(OrderBookConfig::default(), &bids, &asks);
+
+        // Then
+        assert_eq!(
+            crossed.err(),
+            Some(OrderBookError::CrossedSnapshot {
+                best_bid: 100.0,
+                best_ask: 100.0
+            })
+        );
+        assert!(matches!(
+            off_tick.err(),
+            Some(OrderBookError::PriceOffTick { .. })
+        ));
+        assert_eq!(order_book.unwrap().touch(), Some((100.0, 1.0, 101.0, 1.0)));
+    }
+
+    #[test]
+    fn successfully_records_the_history_of_top_of_book_changes() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            top_history_capacity: 3,
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        clock.advance(1);
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // When
+        clock.advance(1);
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        clock.advance(1);
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        let sample = |timestamp, bid, ask| TopSample {
+            timestamp,
+            bid,
+            ask,
+        };
+        assert_eq!(
+            *order_book.top_history(),
+            [
+                sample(1_001, Some(99.0), Some(101.0)),
+                sample(1_002, Some(99.0), Some(100.0)),
+                sample(1_003, Some(98.0), Some(100.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn successfully_rejects_an_order_below_the_minimum_notional() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            min_notional: Some(10.0),
+            ..OrderBookConfig::default()
+        });
+
+        // When
+        let low_price = order_book.add_order(5.0, Order::new(OrderType::Bid, 1.0));
+        let high_price = order_book.add_order(50.0, Order::new(OrderType::Bid, 1.0));
+        let crossing = order_book.place_limit_order(5.0, Order::new(OrderType::Ask, 1.0));
+
+        // Then
+        assert_eq!(
+            low_price,
+            Err(OrderBookError::NotionalBelowMinimum {
+                notional: 5.0,
+                min_notional: 10.0
+            })
+        );
+        assert_eq!(high_price, Ok(()));
+        assert!(matches!(
+            crossing,
+            Err(OrderBookError::NotionalBelowMinimum { .. })
+        ));
+        assert_eq!(order_book.total_volume(OrderType::Bid), 1.0);
+    }
+
+    #[test]
+    fn successfully_shifts_an_owners_quote_ladder_by_ticks() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let ladder: Vec<Uuid> = [99.0, 98.0, 97.0]
+            .into_iter()
+            .map(|price| {
+                let order = Order::new(OrderType::Bid, 1.0).with_owner(owner);
+                let order_id = order.id;
+                order_book.add_order(price, order).unwrap();
+                order_id
+            })
+            .collect();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0).with_owner(other))
+            .unwrap();
+
+        // When
+        order_book.shift_quotes(owner, OrderType::Bid, 2).unwrap();
+
+        // Then
+        let shifted: Vec<(Uuid, f64)> = order_book
+            .orders_by_owner(OrderType::Bid, owner)
+            .into_iter()
+            .map(|view| (view.id, view.price))
+            .collect();
+        assert_eq!(
+            shifted,
+            vec![(ladder[0], 99.02), (ladder[1], 98.02), (ladder[2], 97.02)]
+        );
+        assert_eq!(
+            order_book.orders_by_owner(OrderType::Bid, other)[0].price,
+            99.0
+        );
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_shows_the_whole_reserve_once_an_iceberg_runs_out_of_refreshes() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let iceberg = Order::new(OrderType::Ask, 10.0)
+            .with_iceberg(2.0)
+            .with_max_refreshes(2);
+        let iceberg_id = iceberg.id;
+        order_book.add_order(101.0, iceberg).unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let shown = order_book.get_order(iceberg_id).unwrap().size();
+
+        // When
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        let after_refreshes = order_book.get_order(iceberg_id).unwrap().clone();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(shown, 2.0);
+        assert_eq!(
+            matches.iter().map(|m| m.size_filled).collect::<Vec<_>>(),
+            vec![2.0, 1.0, 2.0]
+        );
+        assert_eq!(after_refreshes.size(), 2.0);
+        assert_eq!(after_refreshes.refreshes_remaining(), Some(0));
+        assert_eq!(after_refreshes.reserve(), 4.0);
+        let remainder = order_book.get_order(iceberg_id).unwrap();
+        assert_eq!(remainder.size(), 4.0);
+        assert_eq!(remainder.reserve(), 0.0);
+        assert_eq!(order_book.total_volume(OrderType::Ask), 4.0);
+    }
+
+    #[test]
+    fn successfully_samples_an_increasing_price_impact_curve() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        for (price, size) in [(101.0, 1.0), (102.0, 2.0), (104.0, 3.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+
+        // When
+        let impact = order_book.price_impact(OrderType::Bid, &[1.0, 2.0, 3.0, 6.0, 7.0]);
+
+        // Then
+        assert_eq!(impact.len(), 4);
+        assert_eq!(impact[0], (1.0, 1.0));
+        assert_eq!(impact[2], (3.0, (101.0 + 2.0 * 102.0) / 3.0 - 100.0));
+        assert!(impact.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    fn successfully_rejects_order_entry_beyond_the_accounts_rate_limit() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            order_rate_limit: Some(RateLimit {
+                max_actions: 3,
+                window_secs: 6,
+            }),
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let bid = |owner| Order::new(OrderType::Bid, 1.0).with_owner(owner);
+
+        // When
+        let burst: Vec<_> = (0..5)
+            .map(|_| order_book.place_limit_order(99.0, bid(owner)).map(|_| ()))
+            .collect();
+        let other_account = order_book.place_limit_order(99.0, bid(other));
+        clock.advance(2);
+        let after_refill = order_book.place_limit_order(99.0, bid(owner));
+        let still_limited = order_book.place_limit_order(99.0, bid(owner));
+
+        // Then
+        assert_eq!(
+            burst,
+            vec![
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err(OrderBookError::RateLimited(owner)),
+                Err(OrderBookError::RateLimited(owner))
+            ]
+        );
+        assert!(other_account.is_ok());
+        assert!(after_refill.is_ok());
+        assert_eq!(still_limited, Err(OrderBookError::RateLimited(owner)));
+        assert_eq!(order_book.open_orders(owner).len(), 4);
+    }
+
+    #[test]
+    fn successfully_returns_the_spread_in_basis_points_of_the_mid() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.5, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let one_sided = order_book.spread_bps();
+
+        // When
+        order_book
+            .add_order(100.5, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(one_sided, None);
+        assert_eq!(order_book.spread_bps(), Some(100.0));
+    }
+
+    #[test]
+    fn successfully_counts_the_volume_added_filled_and_cancelled_at_a_level() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ask = Order::new(OrderType::Ask, 5.0);
+        let ask_id = ask.id;
+        order_book.add_order(101.0, ask).unwrap();
+        let other_ask = Order::new(OrderType::Ask, 2.0);
+        let other_ask_id = other_ask.id;
+        order_book.add_order(101.0, other_ask).unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        order_book.reduce_order(ask_id, 1.0).unwrap();
+        order_book.cancel_order(other_ask_id).unwrap();
+
+        // Then
+        assert_eq!(
+            order_book.level_fill_stats(OrderType::Ask, 101.0),
+            Some(LevelFillStats {
+                added: 7.0,
+                filled: 3.0,
+                cancelled: 3.0,
+            })
+        );
+        assert_eq!(order_book.level_fill_stats(OrderType::Bid, 101.0), None);
+    }
+
+    #[test]
+    fn successfully_posts_a_limit_if_touched_buy_once_the_ask_falls_to_its_trigger() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(103.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let buy = Order::new(OrderType::Bid, 2.0);
+        let buy_id = buy.id;
+        order_book
+            .place_if_touched_order(100.0, Some(99.5), buy)
+            .unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let pending_after_rise = order_book.pending_stops().count();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(pending_after_rise, 1);
+        assert_eq!(order_book.pending_stops().count(), 0);
+        assert_eq!(order_book.touch(), Some((99.5, 2.0, 100.0, 1.0)));
+        assert_eq!(order_book.get_order(buy_id).unwrap().size(), 2.0);
+    }
+
+    #[test]
+    fn successfully_parses_every_accepted_spelling_of_a_side() {
+        // Given
+        let bids = ["buy", "BID", "b", "Buy"];
+        let asks = ["sell", "Ask", "S", " ask "];
+
+        // When
+        let parsed_bids: Vec<_> = bids.iter().map(|s| s.parse::<OrderType>()).collect();
+        let parsed_asks: Vec<_> = asks.iter().map(|s| s.parse::<OrderType>()).collect();
+
+        // Then
+        assert!(parsed_bids.iter().all(|side| *side == Ok(OrderType::Bid)));
+        assert!(parsed_asks.iter().all(|side| *side == Ok(OrderType::Ask)));
+        for side in [OrderType::Bid, OrderType::Ask] {
+            assert_eq!(side.as_str().parse(), Ok(side));
+            assert_eq!(OrderType::from_code(side.code()), Some(side));
+        }
+    }
+
+    #[test]
+    fn successfully_rejects_an_unknown_side() {
+        // When
+        let parsed = "short".parse::<OrderType>();
+
+        // Then
+        assert_eq!(parsed, Err(OrderTypeParseError("short".to_string())));
+        assert_eq!(OrderType::from_code(3), None);
+    }
+
+    #[test]
+    fn successfully_stops_filling_a_protected_market_order_past_its_slippage_cap() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        for (price, size) in [(101.0, 1.0), (102.0, 1.0), (104.0, 5.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+
+        // When
+        let (matches, unfilled) = order_book
+            .place_protected_market_order(Order::new(OrderType::Bid, 4.0), 2.5)
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            matches.iter().map(|m| m.price).collect::<Vec<_>>(),
+            vec![101.0, 102.0]
+        );
+        assert_eq!(unfilled, 2.0);
+        assert_eq!(order_book.touch(), Some((99.0, 1.0, 104.0, 5.0)));
+    }
+
+    #[test]
+    fn successfully_applies_the_market_order_checks_to_a_protected_market_order() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            self_trade_prevention: Some(SelfTradePrevention::RejectAggressor),
+            market_order_fallback: MarketOrderFallback::Reject,
+            ..OrderBookConfig::default()
+        });
+        let owner = Uuid::new_v4();
+        let empty_side = order_book
+            .place_protected_market_order(Order::new(OrderType::Bid, 1.0), 2.5)
+            .err();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0).with_owner(owner))
+            .unwrap();
+        let own = Order::new(OrderType::Bid, 1.0).with_owner(owner);
+        let own_id = own.id();
+        let post_only =
+            Order::new(OrderType::Bid, 1.0).with_exec_instructions(ExecInstructions::POST_ONLY);
+
+        // When
+        let self_trade = order_book.place_protected_market_order(own, 2.5).err();
+        let conflicting = order_book
+            .place_protected_market_order(post_only, 2.5)
+            .err();
+
+        // Then
+        assert_eq!(
+            empty_side,
+            Some(OrderBookError::NoLiquidity(OrderType::Ask))
+        );
+        assert_eq!(self_trade, Some(OrderBookError::SelfTradeRejected(own_id)));
+        assert_eq!(
+            conflicting,
+            Some(OrderBookError::ConflictingInstructions(
+                ExecInstructions::POST_ONLY
+            ))
+        );
+        assert_eq!(order_book.depth(5).1, vec![(101.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_restores_one_side_from_its_snapshot_without_touching_the_other() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let first_bid = Order::new(OrderType::Bid, 1.0);
+        let second_bid = Order::new(OrderType::Bid, 2.0);
+        let (first_bid_id, second_bid_id) = (first_bid.id, second_bid.id);
+        order_book.add_order(99.0, first_bid).unwrap();
+        order_book.add_order(99.0, second_bid).unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 4.0))
+            .unwrap();
+        let bids_before = order_book.orders_l3(OrderType::Bid, true);
+        let asks_before = order_book.orders_l3(OrderType::Ask, true);
+
+        // When
+        let snapshot = order_book.side_snapshot(OrderType::Bid);
+        order_book.cancel_side(OrderType::Bid);
+        let cleared_bids = order_book.total_volume(OrderType::Bid);
+        order_book.restore_side(snapshot).unwrap();
+
+        // Then
+        assert_eq!(cleared_bids, 0.0);
+        assert_eq!(order_book.orders_l3(OrderType::Bid, true), bids_before);
+        assert_eq!(order_book.orders_l3(OrderType::Ask, true), asks_before);
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 99.0),
+            Some(vec![(first_bid_id, 1.0), (second_bid_id, 2.0)])
+        );
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_restores_a_side_without_counting_cancels() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let owner = Uuid::new_v4();
+        let bid = Order::new(OrderType::Bid, 1.0).with_owner(owner);
+        let bid_id = bid.id;
+        order_book.add_order(99.0, bid).unwrap();
+        let snapshot = order_book.side_snapshot(OrderType::Bid);
+        let replaced = Order::new(OrderType::Bid, 2.0).with_owner(owner);
+        let replaced_id = replaced.id;
+        order_book.add_order(98.0, replaced).unwrap();
+
+        // When
+        order_book.restore_side(snapshot).unwrap();
+
+        // Then
+        assert_eq!(order_book.depth(10).0, vec![(99.0, 1.0)]);
+        assert_eq!(order_book.account_activity(owner).cancels, 0);
+        assert_eq!(order_book.cancel_reason(bid_id), None);
+        assert_eq!(order_book.cancel_reason(replaced_id), None);
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_leaves_a_side_alone_when_its_restore_is_rejected() {
+        // Given
+        let mut source = OrderBook::new();
+        source
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        source
+            .add_order(98.0, Order::new(OrderType::Bid, 0.05))
+            .unwrap();
+        let snapshot = source.side_snapshot(OrderType::Bid);
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            min_notional: Some(10.0),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(97.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let restored = order_book.restore_side(snapshot);
+
+        // Then
+        assert!(matches!(
+            restored,
+            Err(OrderBookError::NotionalBelowMinimum { .. })
+        ));
+        assert_eq!(order_book.depth(10).0, vec![(97.0, 1.0)]);
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_merges_two_books_in_timestamp_priority() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut primary = OrderBook::new().with_clock(clock.clone());
+        let mut standby = OrderBook::new().with_clock(clock.clone());
+        let (early, middle, late) = (
+            Order::new(OrderType::Bid, 1.0),
+            Order::new(OrderType::Bid, 2.0),
+            Order::new(OrderType::Bid, 3.0),
+        );
+        let (early_id, middle_id, late_id) = (early.id, middle.id, late.id);
+        primary.add_order(99.0, early).unwrap();
+        standby
+            .add_order(101.0, Order::new(OrderType::Ask, 4.0))
+            .unwrap();
+        clock.advance(5);
+        standby.add_order(99.0, middle).unwrap();
+        clock.advance(5);
+        primary.add_order(99.0, late).unwrap();
+        let mut duplicate = OrderBook::new();
+        duplicate
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0).with_id(early_id))
+            .unwrap();
+
+        // When
+        primary.merge(standby).unwrap();
+        let rejected = primary.merge(duplicate);
+
+        // Then
+        assert_eq!(
+            primary.level_queue(OrderType::Bid, 99.0),
+            Some(vec![(early_id, 1.0), (middle_id, 2.0), (late_id, 3.0)])
+        );
+        assert_eq!(primary.depth(5), (vec![(99.0, 6.0)], vec![(101.0, 4.0)]));
+        assert_eq!(rejected, Err(OrderBookError::DuplicateOrderId(early_id)));
+        assert_eq!(primary.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_leaves_the_book_untouched_when_a_merged_order_is_rejected() {
+        // Given
+        let mut primary = OrderBook::new();
+        let resting = Order::new(OrderType::Bid, 1.0);
+        let resting_id = resting.id;
+        primary.add_order(99.0, resting).unwrap();
+        let mut standby = OrderBook::with_config(OrderBookConfig {
+            tick_size: 0.001,
+            ..OrderBookConfig::default()
+        });
+        standby
+            .add_order(98.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        standby
+            .add_order(101.005, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let sequence_before = primary.get_order(resting_id).unwrap().sequence;
+
+        // When
+        let merged = primary.merge(standby);
+
+        // Then
+        assert!(matches!(merged, Err(OrderBookError::PriceOffTick { .. })));
+        assert_eq!(primary.depth(10), (vec![(99.0, 1.0)], vec![]));
+        assert_eq!(
+            primary.get_order(resting_id).unwrap().sequence,
+            sequence_before
+        );
+        assert_eq!(primary.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_computes_the_cost_to_move_the_market_by_ticks() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for (price, size) in [(100.0, 1.0), (100.01, 2.0), (100.03, 4.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+
+        // When
+        let two_ticks = order_book.cost_to_move(OrderType::Bid, 2);
+        let four_ticks = order_book.cost_to_move(OrderType::Bid, 4);
+
+        // Then
+        let (volume, notional) = two_ticks.unwrap();
+        assert_eq!(volume, 3.0);
+        assert!((notional - (100.0 + 2.0 * 100.01)).abs() < 1e-9);
+        assert_eq!(four_ticks, None);
+        assert_eq!(order_book.cost_to_move(OrderType::Ask, 1), None);
+    }
+
+    #[test]
+    fn successfully_rejects_a_sweep_through_own_hidden_or_midpoint_orders() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            self_trade_prevention: Some(SelfTradePrevention::RejectAggressor),
+            ..OrderBookConfig::default()
+        });
+        let owner = Uuid::new_v4();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(
+                101.5,
+                Order::new(OrderType::Ask, 1.0)
+                    .with_owner(owner)
+                    .with_visibility(Visibility::Hidden),
+            )
+            .unwrap();
+        let hidden_sweep = Order::new(OrderType::Bid, 2.0).with_owner(owner);
+        let hidden_sweep_id = hidden_sweep.id();
+        let midpoint_owner = Uuid::new_v4();
+        let midpoint_sweep = Order::new(OrderType::Bid, 1.0).with_owner(midpoint_owner);
+        let midpoint_sweep_id = midpoint_sweep.id();
+
+        // When
+        let hidden = order_book.place_limit_order(102.0, hidden_sweep);
+        order_book
+            .place_midpoint_order(Order::new(OrderType::Ask, 1.0).with_owner(midpoint_owner))
+            .unwrap();
+        let midpoint = order_book.place_market_order(midpoint_sweep);
+
+        // Then
+        assert_eq!(
+            hidden.err(),
+            Some(OrderBookError::SelfTradeRejected(hidden_sweep_id))
+        );
+        assert_eq!(
+            midpoint.err(),
+            Some(OrderBookError::SelfTradeRejected(midpoint_sweep_id))
+        );
+        assert!(order_book.trades().is_empty());
+    }
+
+    #[test]
+    fn successfully_rejects_a_sweep_through_own_resting_orders_wholesale() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            self_trade_prevention: Some(SelfTradePrevention::RejectAggressor),
+            ..OrderBookConfig::default()
+        });
+        let owner = Uuid::new_v4();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(100.5, Order::new(OrderType::Ask, 1.0).with_owner(owner))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let sweep = Order::new(OrderType::Bid, 3.0).with_owner(owner);
+        let sweep_id = sweep.id();
+
+        // When
+        let rejected = order_book.place_limit_order(101.0, sweep);
+        let short_of_own =
+            order_book.place_limit_order(100.0, Order::new(OrderType::Bid, 1.0).with_owner(owner));
+
+        // Then
+        assert_eq!(
+            rejected.err(),
+            Some(OrderBookError::SelfTradeRejected(sweep_id))
+        );
+        assert_eq!(short_of_own.unwrap().len(), 1);
+        assert_eq!(order_book.depth(5).1, vec![(100.5, 1.0), (101.0, 1.0)]);
+        assert_eq!(order_book.open_orders(owner).len(), 1);
+    }
+
+    #[test]
+    fn successfully_honors_combined_exec_instructions_and_rejects_conflicts() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let owner = Uuid::new_v4();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+        let conflicting = Order::new(OrderType::Bid, 1.0).with_exec_instructions(
+            ExecInstructions::POST_ONLY | ExecInstructions::IMMEDIATE_OR_CANCEL,
+        );
+        let hidden_post_only = Order::new(OrderType::Bid, 1.0)
+            .with_exec_instructions(ExecInstructions::POST_ONLY | ExecInstructions::HIDDEN);
+        let hidden_id = hidden_post_only.id();
+
+        // When
+        let rejected = order_book.place_limit_order(99.0, conflicting);
+        let ioc_matches = order_book
+            .place_limit_order(
+                100.0,
+                Order::new(OrderType::Bid, 5.0)
+                    .with_owner(owner)
+                    .with_exec_instructions(ExecInstructions::IMMEDIATE_OR_CANCEL)
+                    .with_min_fill(2.0),
+            )
+            .unwrap();
+        order_book
+            .place_limit_order(99.0, hidden_post_only)
+            .unwrap();
+        let growing = order_book.place_limit_order(
+            101.0,
+            Order::new(OrderType::Bid, 1.0)
+                .with_owner(owner)
+                .with_exec_instructions(ExecInstructions::REDUCE_ONLY),
+        );
+
+        // Then
+        assert_eq!(
+            rejected.err(),
+            Some(OrderBookError::ConflictingInstructions(
+                ExecInstructions::POST_ONLY | ExecInstructions::IMMEDIATE_OR_CANCEL
+            ))
+        );
+        assert_eq!(ioc_matches.len(), 1);
+        assert_eq!(order_book.net_position(owner), 2.0);
+        assert!(order_book.open_orders(owner).is_empty());
+        assert_eq!(order_book.depth(5), (vec![], vec![]));
+        assert_eq!(
+            order_book.get_order(hidden_id).unwrap().visibility,
+            Visibility::Hidden
+        );
+        assert!(growing.is_err());
+    }
+
+    #[test]
+    fn successfully_executes_an_accepted_rfq_quote_at_the_locked_price() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+        let taker = Uuid::new_v4();
+        let quote = order_book
+            .request_quote(OrderType::Bid, 2.0, taker)
+            .unwrap();
+        let moved_quote = order_book
+            .request_quote(OrderType::Bid, 2.0, taker)
+            .unwrap();
+
+        // When
+        clock.advance(2);
+        let matches = order_book.accept_rfq(quote.quote_id).unwrap();
+        let moved = order_book.accept_rfq(moved_quote.quote_id);
+
+        // Then
+        assert_eq!(quote.price, 100.5);
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.price == 100.5));
+        assert!(order_book.trades().iter().all(|trade| trade.price == 100.5));
+        assert_eq!(order_book.net_position(taker), 2.0);
+        assert_eq!(
+            moved.err(),
+            Some(OrderBookError::QuoteMoved {
+                quoted: 100.5,
+                current: 101.0
+            })
+        );
+        assert_eq!(
+            order_book.accept_rfq(quote.quote_id).err(),
+            Some(OrderBookError::QuoteNotFound(quote.quote_id))
+        );
+    }
+
+    #[test]
+    fn successfully_executes_an_order_amended_through_the_touch() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let bid = Order::new(OrderType::Bid, 3.0);
+        let bid_id = bid.id();
+        order_book.add_order(99.0, bid).unwrap();
+
+        // When
+        order_book.amend_order(bid_id, 101.0, 3.0).unwrap();
+
+        // Then
+        assert_eq!(order_book.trades().len(), 1);
+        assert_eq!(order_book.trades()[0].taker_order_id, bid_id);
+        assert_eq!(order_book.depth(5), (vec![(101.0, 2.0)], vec![]));
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_amends_an_order_without_cancelling_it() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        let owner = Uuid::new_v4();
+        let bid = Order::new(OrderType::Bid, 3.0).with_owner(owner);
+        let bid_id = bid.id();
+        order_book.add_order(99.0, bid).unwrap();
+        let receiver = order_book.subscribe();
+
+        // When
+        order_book.amend_order(bid_id, 100.0, 2.0).unwrap();
+        order_book.amend_order(bid_id, 101.0, 2.0).unwrap();
+
+        // Then
+        assert_eq!(order_book.cancel_reason(bid_id), None);
+        assert_eq!(order_book.account_activity(owner).cancels, 0);
+        assert!(
+            receiver
+                .try_iter()
+                .all(|event| !matches!(event, BookEvent::OrderCancelled { .. }))
+        );
+        assert_eq!(order_book.depth(5), (vec![(101.0, 1.0)], vec![]));
+    }
+
+    #[test]
+    fn successfully_amends_an_iceberg_to_its_new_total_size() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let iceberg = Order::new(OrderType::Bid, 5.0).with_iceberg(1.0);
+        let iceberg_id = iceberg.id();
+        order_book.add_order(99.0, iceberg).unwrap();
+
+        // When
+        order_book.amend_order(iceberg_id, 98.0, 3.0).unwrap();
+        order_book.replace_order(iceberg_id, 97.0, 4.0).unwrap();
+
+        // Then
+        let iceberg = order_book.get_order(iceberg_id).unwrap();
+        assert_eq!((iceberg.size(), iceberg.reserve()), (1.0, 3.0));
+        assert_eq!(order_book.depth(5).0, vec![(97.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_rejects_or_clamps_an_amend_through_the_touch() {
+        // Given
+        let book_with = |amend_cross_policy| {
+            let mut order_book = OrderBook::with_config(OrderBookConfig {
+                amend_cross_policy,
+                ..OrderBookConfig::default()
+            });
+            order_book
+                .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+                .unwrap();
+            let bid = Order::new(OrderType::Bid, 3.0);
+            let bid_id = bid.id();
+            order_book.add_order(99.0, bid).unwrap();
+            (order_book, bid_id)
+        };
+        let (mut rejecting, rejected_id) = book_with(AmendCrossPolicy::Reject);
+        let (mut clamping, clamped_id) = book_with(AmendCrossPolicy::Clamp);
+
+        // When
+        let rejected = rejecting.amend_order(rejected_id, 101.5, 2.0);
+        clamping.amend_order(clamped_id, 101.5, 2.0).unwrap();
+
+        // Then
+        assert_eq!(rejected, Err(OrderBookError::AmendWouldCross(rejected_id)));
+        assert_eq!(rejecting.depth(5), (vec![(99.0, 3.0)], vec![(101.0, 1.0)]));
+        assert!(clamping.trades().is_empty());
+        let (bids, asks) = clamping.depth(5);
+        assert_eq!(bids.len(), 1);
+        assert!((bids[0].0 - 100.99).abs() < 1e-9);
+        assert_eq!(bids[0].1, 2.0);
+        assert_eq!(asks, vec![(101.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_reads_a_consistent_state_through_a_frozen_book() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // When
+        let (touch, depth, mid, sequence) = order_book.with_frozen(|frozen| {
+            (
+                frozen.touch(),
+                frozen.depth(1),
+                frozen.mid_price(),
+                frozen.sequence(),
+            )
+        });
+
+        // Then
+        let (best_bid, bid_size, best_ask, ask_size) = touch.unwrap();
+        assert_eq!(
+            depth,
+            (vec![(best_bid, bid_size)], vec![(best_ask, ask_size)])
+        );
+        assert_eq!(mid, Some((best_bid + best_ask) / 2.0));
+        assert_eq!(sequence, order_book.next_sequence);
+    }
+
+    #[test]
+    fn successfully_pulls_and_blocks_quotes_when_market_maker_protection_trips() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            market_maker_protection: Some(MarketMakerProtection {
+                max_fills: 10,
+                max_volume: 2.5,
+                window_secs: 1,
+            }),
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        let maker = Uuid::new_v4();
+        for price in [101.0, 102.0, 103.0, 104.0] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, 1.0).with_owner(maker))
+                .unwrap();
+        }
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 5.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_midpoint_order(Order::new(OrderType::Bid, 1.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let blocked =
+            order_book.add_order(100.0, Order::new(OrderType::Bid, 1.0).with_owner(maker));
+        let blocked_midpoint =
+            order_book.place_midpoint_order(Order::new(OrderType::Bid, 1.0).with_owner(maker));
+        order_book.reset_mmp(maker);
+        let after_reset =
+            order_book.add_order(100.0, Order::new(OrderType::Bid, 1.0).with_owner(maker));
+
+        // Then
+        assert_eq!(order_book.maker_volume(maker), 3.0);
+        assert_eq!(blocked, Err(OrderBookError::MmpTripped(maker)));
+        assert_eq!(blocked_midpoint, Err(OrderBookError::MmpTripped(maker)));
+        assert!(order_book.midpoint_orders(OrderType::Bid).is_empty());
+        assert_eq!(after_reset, Ok(()));
+        assert!(!order_book.is_mmp_tripped(maker));
+        assert_eq!(order_book.open_orders(maker).len(), 1);
+        assert_eq!(order_book.depth(5), (vec![(100.0, 1.0)], vec![]));
+    }
+
+    #[test]
+    fn successfully_reports_why_the_engine_cancelled_an_order() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let expiring = Order::new(OrderType::Bid, 1.0).with_expiry(1_010);
+        let take_profit = Order::new(OrderType::Ask, 1.0);
+        let stop_loss = Order::new(OrderType::Ask, 1.0);
+        let (expiring_id, take_profit_id, stop_loss_id) =
+            (expiring.id(), take_profit.id(), stop_loss.id());
+        order_book.add_order(99.0, expiring).unwrap();
+        order_book.add_order(101.0, take_profit).unwrap();
+        order_book.add_order(105.0, stop_loss).unwrap();
+        order_book.link_oco(take_profit_id, stop_loss_id).unwrap();
+        let receiver = order_book.subscribe();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        clock.advance(10);
+        order_book.purge_expired();
+
+        // Then
+        let cancels: Vec<(Uuid, CancelReason)> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                BookEvent::OrderCancelled { order_id, reason } => Some((order_id, reason)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            cancels,
+            vec![
+                (stop_loss_id, CancelReason::OcoSibling),
+                (expiring_id, CancelReason::Expired)
+            ]
+        );
+        assert_eq!(
+            order_book.cancel_reason(expiring_id),
+            Some(CancelReason::Expired)
+        );
+        assert_eq!(order_book.cancel_reason(take_profit_id), None);
+    }
+
+    #[test]
+    fn successfully_values_an_inverse_contract_at_size_over_price() {
+        // Given
+        let taker = Uuid::new_v4();
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_size: 0.5,
+            lot_size: 1.0,
+            inverse: true,
+            min_notional: Some(0.001),
+            fee_schedule: FeeSchedule::new(0.0, 10.0),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(50_000.0, Order::new(OrderType::Ask, 1_000.0))
+            .unwrap();
+        order_book
+            .add_order(40_000.0, Order::new(OrderType::Ask, 1_000.0))
+            .unwrap();
+
+        // When
+        let notional = order_book.notional(50_000.0, 1_000.0);
+        let too_small = order_book.add_order(50_000.0, Order::new(OrderType::Ask, 10.0));
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2_000.0).with_owner(taker))
+            .unwrap();
+
+        // Then
+        assert_eq!(notional, 0.02);
+        assert_eq!(
+            too_small,
+            Err(OrderBookError::NotionalBelowMinimum {
+                notional: 0.0002,
+                min_notional: 0.001
+            })
+        );
+        assert!((order_book.net_fees(taker) - 0.045 * 10.0 / 10_000.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn successfully_queues_a_later_displayed_order_ahead_of_an_earlier_hidden_one() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let hidden = Order::new(OrderType::Ask, 2.0).with_visibility(Visibility::Hidden);
+        let hidden_id = hidden.id();
+        order_book.add_order(100.0, hidden).unwrap();
+        clock.advance(5);
+        let displayed = Order::new(OrderType::Ask, 1.0);
+        let displayed_id = displayed.id();
+        order_book.add_order(100.0, displayed).unwrap();
+
+        // When
+        let queue = order_book.level_queue_detailed(OrderType::Ask, 100.0);
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            queue,
+            Some(vec![
+                (displayed_id, 1.0, Visibility::Displayed),
+                (hidden_id, 2.0, Visibility::Hidden)
+            ])
+        );
+        assert_eq!(matches[0].ask_id, displayed_id);
+        assert_eq!(
+            order_book.level_queue_detailed(OrderType::Ask, 100.0),
+            Some(vec![(hidden_id, 2.0, Visibility::Hidden)])
+        );
+    }
+
+    #[test]
+    fn successfully_populates_a_synthetic_ladder() {
+        // Given
+        let mut order_book = OrderBook::new();
+
+        // When
+        order_book
+            .populate_synthetic(OrderType::Bid, 99.0, 0.5, 10, 2.0, 3)
+            .unwrap();
+
+        // Then
+        let (bids, asks) = order_book.depth(20);
+        assert_eq!(bids.len(), 10);
+        assert_eq!(bids[0], (99.0, 6.0));
+        assert_eq!(bids[9], (94.5, 6.0));
+        assert!(asks.is_empty());
+        assert_eq!(order_book.all_order_ids().len(), 30);
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_sums_the_volume_within_a_band_of_the_mid() {
+        // Given
+        let mut order_book = OrderBook::new();
+        for (price, size) in [(99.8, 1.0), (99.5, 2.0), (99.0, 4.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, size))
+                .unwrap();
+        }
+        for (price, size) in [(100.2, 3.0), (100.6, 5.0)] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, size))
+                .unwrap();
+        }
+        let mut one_sided = OrderBook::new();
+        one_sided
+            .add_order(99.8, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let volume = order_book.volume_within(0.5);
+
+        // Then
+        assert_eq!(volume, (3.0, 3.0));
+        assert_eq!(one_sided.volume_within(0.5), (0.0, 0.0));
+    }
+
+    #[test]
+    fn successfully_cancels_the_orders_of_a_disconnected_session() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let owner = Uuid::new_v4();
+        let session = order_book.register_session(owner);
+        let other_session = order_book.register_session(owner);
+        let bid = Order::new(OrderType::Bid, 1.0)
+            .with_owner(owner)
+            .with_session(session);
+        let ask = Order::new(OrderType::Ask, 1.0)
+            .with_owner(owner)
+            .with_session(session);
+        let (bid_id, ask_id) = (bid.id(), ask.id());
+        order_book.add_order(99.0, bid).unwrap();
+        order_book.place_limit_order(101.0, ask).unwrap();
+        order_book
+            .add_order(
+                98.0,
+                Order::new(OrderType::Bid, 2.0)
+                    .with_owner(owner)
+                    .with_session(other_session),
+            )
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+
+        // When
+        let mut cancelled = order_book.on_disconnect(session).unwrap();
+
+        // Then
+        cancelled.sort();
+        let mut expected = vec![bid_id, ask_id];
+        expected.sort();
+        assert_eq!(cancelled, expected);
+        assert_eq!(
+            order_book.cancel_reason(bid_id),
+            Some(CancelReason::Disconnect)
+        );
+        assert_eq!(order_book.depth(5), (vec![(98.0, 2.0)], vec![(102.0, 3.0)]));
+        assert_eq!(
+            order_book.add_order(
+                99.0,
+                Order::new(OrderType::Bid, 1.0)
+                    .with_owner(owner)
+                    .with_session(session)
+            ),
+            Err(OrderBookError::SessionNotFound(session))
+        );
     }
 
-    fn add_order(&mut self, price: f64, order: Order) -> Result<(), String> {
-        let price_key = price.to_string();
+    #[test]
+    fn successfully_cancels_the_stop_and_delayed_orders_of_a_disconnected_session() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let owner = Uuid::new_v4();
+        let session = order_book.register_session(owner);
+        let stop = Order::new(OrderType::Bid, 1.0)
+            .with_owner(owner)
+            .with_session(session);
+        let delayed = Order::new(OrderType::Bid, 1.0)
+            .with_owner(owner)
+            .with_session(session)
+            .with_not_before(1_010);
+        let (stop_id, delayed_id) = (stop.id(), delayed.id());
+        order_book.place_stop_order(105.0, stop).unwrap();
+        order_book.place_limit_order(99.0, delayed).unwrap();
+        order_book
+            .add_order(106.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
 
-        let limits = self
-            .limits
-            .get_mut(&order.order_type)
-            .expect("Did not find limits for order type");
+        // When
+        let mut cancelled = order_book.on_disconnect(session).unwrap();
+        let late_stop = order_book.place_stop_order(
+            105.0,
+            Order::new(OrderType::Bid, 1.0)
+                .with_owner(owner)
+                .with_session(session),
+        );
+        let late_midpoint = order_book.place_midpoint_order(
+            Order::new(OrderType::Bid, 1.0)
+                .with_owner(owner)
+                .with_session(session),
+        );
+        clock.advance(20);
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
 
-        let price_to_limit_idx_map = self
-            .limits_by_price
-            .get_mut(&order.order_type)
-            .expect("Did not find limits by price for order type");
+        // Then
+        cancelled.sort();
+        let mut expected = vec![stop_id, delayed_id];
+        expected.sort();
+        assert_eq!(cancelled, expected);
+        for order_id in [stop_id, delayed_id] {
+            assert_eq!(
+                order_book.cancel_reason(order_id),
+                Some(CancelReason::Disconnect)
+            );
+        }
+        assert_eq!(late_stop, Err(OrderBookError::SessionNotFound(session)));
+        assert_eq!(late_midpoint, Err(OrderBookError::SessionNotFound(session)));
+        assert_eq!(order_book.trades().len(), 1);
+        assert_eq!(order_book.depth(5), (vec![], vec![(106.0, 4.0)]));
+    }
+
+    #[test]
+    fn successfully_archives_completely_filled_orders() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            filled_order_capacity: 2,
+            ..OrderBookConfig::default()
+        });
+        let first = Order::new(OrderType::Ask, 1.0);
+        let second = Order::new(OrderType::Ask, 2.0);
+        let (first_id, second_id) = (first.id(), second.id());
+        order_book.add_order(100.0, first).unwrap();
+        order_book.add_order(102.0, second).unwrap();
+        let taker = Order::new(OrderType::Bid, 3.0);
+        let taker_id = taker.id();
+
+        // When
+        order_book.place_limit_order(102.0, taker).unwrap();
+
+        // Then
+        assert!(order_book.filled_order(first_id).is_none());
+        let maker = order_book.filled_order(second_id).unwrap();
+        assert_eq!((maker.size(), maker.cumulative_filled()), (0.0, 2.0));
+        assert_eq!(maker.avg_fill_price(), 102.0);
+        let taker = order_book.filled_order(taker_id).unwrap();
+        assert_eq!(taker.cumulative_filled(), 3.0);
+        assert!((taker.avg_fill_price() - 304.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn successfully_falls_back_to_the_mark_for_the_mid_of_a_one_sided_book() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            mid_fallback: MidFallback::Mark,
+            ..OrderBookConfig::default()
+        });
+        let mut strict = OrderBook::new();
+        for book in [&mut order_book, &mut strict] {
+            book.add_order(99.0, Order::new(OrderType::Bid, 1.0))
+                .unwrap();
+            book.set_mark_price(105.0);
+        }
+
+        // When
+        let mid = order_book.mid_price();
+
+        // Then
+        assert_eq!(mid, Some(105.0));
+        assert_eq!(strict.mid_price(), None);
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        assert_eq!(order_book.mid_price(), Some(100.0));
+    }
+
+    #[test]
+    fn successfully_replays_a_primarys_matches_onto_a_replica() {
+        // Given
+        let orders = [
+            (100.0, Order::new(OrderType::Ask, 1.0)),
+            (100.0, Order::new(OrderType::Ask, 2.0)),
+            (101.0, Order::new(OrderType::Ask, 3.0)),
+            (99.0, Order::new(OrderType::Bid, 1.0)),
+        ];
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut primary = OrderBook::new().with_clock(clock.clone());
+        let mut replica = OrderBook::new().with_clock(clock);
+        for (price, order) in &orders {
+            primary.add_order(*price, order.clone()).unwrap();
+            replica.add_order(*price, order.clone()).unwrap();
+        }
+        let matches = primary
+            .place_market_order(Order::new(OrderType::Bid, 4.0))
+            .unwrap();
+
+        // When
+        let stale = replica.apply_matches(&[Match {
+            ask_id: Uuid::new_v4(),
+            bid_id: Uuid::new_v4(),
+            size_filled: 1.0,
+            price: 100.0,
+        }]);
+        replica.apply_matches(&matches).unwrap();
+
+        // Then
+        assert!(matches!(
+            stale,
+            Err(OrderBookError::MatchNotApplicable { .. })
+        ));
+        assert_eq!(replica.depth(5), primary.depth(5));
+        for side in [OrderType::Bid, OrderType::Ask] {
+            assert_eq!(replica.orders_l3(side, true), primary.orders_l3(side, true));
+        }
+        assert!(replica.apply_matches(&matches).is_err());
+        assert_eq!(replica.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_measures_the_spread_in_ticks() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_size: 0.25,
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let one_sided = order_book.spread_ticks();
+        order_book
+            .add_order(100.5, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(one_sided, None);
+        assert_eq!(order_book.spread_ticks(), Some(2));
+    }
+
+    #[test]
+    fn successfully_rejects_an_order_opening_a_level_beyond_the_cap() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            max_levels_per_side: Some(2),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .place_limit_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let new_level = order_book.place_limit_order(97.0, Order::new(OrderType::Bid, 1.0));
+        let same_level = order_book.add_order(98.0, Order::new(OrderType::Bid, 2.0));
+
+        // Then
+        assert_eq!(
+            new_level.err(),
+            Some(OrderBookError::LevelCountExceeded {
+                price: 97.0,
+                max_levels: 2
+            })
+        );
+        assert_eq!(same_level, Ok(()));
+        assert_eq!(
+            order_book.add_order(101.0, Order::new(OrderType::Ask, 1.0)),
+            Ok(())
+        );
+        assert_eq!(order_book.depth(5).0, vec![(99.0, 1.0), (98.0, 3.0)]);
+    }
+
+    #[test]
+    fn successfully_weights_the_resting_age_by_size() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+        clock.advance(20);
+
+        // When
+        let age = order_book.avg_resting_age(OrderType::Bid, order_book.now());
+
+        // Then
+        assert_eq!(age, Some((30.0 * 1.0 + 20.0 * 3.0) / 4.0));
+        assert_eq!(order_book.avg_resting_age(OrderType::Ask, 1_030), None);
+    }
+
+    #[test]
+    fn successfully_aggregates_float_noisy_prices_into_one_level() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(0.1 + 0.2, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(0.3, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+
+        // When
+        let (bids, _) = order_book.depth(10);
+
+        // Then
+        assert_eq!(bids, vec![(0.3, 3.0)]);
+        assert_eq!(order_book.depth_detailed(10).0[0].order_count, 2);
+    }
+
+    #[test]
+    fn successfully_keeps_the_queue_position_of_a_same_price_smaller_replace() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let first = Order::new(OrderType::Bid, 2.0);
+        let first_id = first.id;
+        let second = Order::new(OrderType::Bid, 1.0);
+        let second_id = second.id;
+        order_book.add_order(99.0, first).unwrap();
+        order_book.add_order(99.0, second).unwrap();
+
+        // When
+        order_book.replace_order(first_id, 99.0, 1.5).unwrap();
+        let queue_after_shrink = order_book.level_queue_detailed(OrderType::Bid, 99.0);
+        order_book.replace_order(first_id, 99.0, 3.0).unwrap();
+        let queue_after_growth = order_book.level_queue_detailed(OrderType::Bid, 99.0);
+
+        // Then
+        assert_eq!(
+            queue_after_shrink,
+            Some(vec![
+                (first_id, 1.5, Visibility::Displayed),
+                (second_id, 1.0, Visibility::Displayed)
+            ])
+        );
+        assert_eq!(
+            queue_after_growth,
+            Some(vec![
+                (second_id, 1.0, Visibility::Displayed),
+                (first_id, 3.0, Visibility::Displayed)
+            ])
+        );
+    }
+
+    #[test]
+    fn successfully_filters_the_tape_by_account_and_time_window() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let (maker, first_taker, second_taker) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 10.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0).with_owner(first_taker))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 2.0).with_owner(second_taker))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 3.0).with_owner(first_taker))
+            .unwrap();
+
+        // When
+        let maker_trades = order_book.account_trades(maker, 1_005, 1_020);
+        let first_taker_trades = order_book.account_trades(first_taker, 1_000, 1_010);
+        let second_taker_trades = order_book.account_trades(second_taker, 1_000, 1_020);
+
+        // Then
+        let sizes = |trades: &[Trade]| trades.iter().map(|trade| trade.size).collect::<Vec<_>>();
+        assert_eq!(sizes(&maker_trades), vec![2.0, 3.0]);
+        assert_eq!(sizes(&first_taker_trades), vec![1.0]);
+        assert_eq!(sizes(&second_taker_trades), vec![2.0]);
+        assert!(order_book.account_trades(maker, 1_030, 1_000).is_empty());
+    }
+
+    #[test]
+    fn successfully_measures_how_concentrated_a_side_is() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 97.0))
+            .unwrap();
+        for price in [99.0, 98.0, 97.0, 96.0] {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, 5.0))
+                .unwrap();
+        }
+        for price in [102.0, 103.0, 104.0] {
+            order_book
+                .add_order(price, Order::new(OrderType::Ask, 1.0))
+                .unwrap();
+        }
+
+        // When
+        let concentrated = order_book.concentration(OrderType::Ask).unwrap();
+        let spread_out = order_book.concentration(OrderType::Bid).unwrap();
+
+        // Then
+        assert!((concentrated - (0.97f64.powi(2) + 3.0 * 0.01f64.powi(2))).abs() < 1e-9);
+        assert!((spread_out - 0.25).abs() < 1e-9);
+        assert_eq!(OrderBook::new().concentration(OrderType::Bid), None);
+    }
+
+    #[test]
+    fn successfully_replaces_an_owner_ladder_in_one_go() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let other = Order::new(OrderType::Ask, 1.0);
+        let other_id = other.id;
+        let maker = Uuid::new_v4();
+        order_book.add_order(101.0, other).unwrap();
+        let old_ids = order_book
+            .replace_ladder(
+                maker,
+                OrderType::Ask,
+                &[
+                    (101.0, 1.0),
+                    (102.0, 1.0),
+                    (103.0, 1.0),
+                    (104.0, 1.0),
+                    (105.0, 1.0),
+                ],
+            )
+            .unwrap();
+        let receiver = order_book.subscribe();
+
+        // When
+        let rejected =
+            order_book.replace_ladder(maker, OrderType::Ask, &[(101.5, 2.0), (102.5, 0.0)]);
+        let cancelled_by_rejection: Vec<Uuid> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                BookEvent::OrderCancelled { order_id, .. } => Some(order_id),
+                _ => None,
+            })
+            .collect();
+        let depth_after_rejection = order_book.depth(10).1;
+        let queue_after_rejection = order_book.level_queue(OrderType::Ask, 101.0);
+        let new_ids = order_book
+            .replace_ladder(
+                maker,
+                OrderType::Ask,
+                &[(101.5, 2.0), (102.5, 2.0), (103.5, 2.0)],
+            )
+            .unwrap();
+
+        // Then
+        assert_eq!(rejected, Err(OrderBookError::InvalidSize(0.0)));
+        // Only the new quote that did rest gets cancelled, the old ones never left
+        assert_eq!(cancelled_by_rejection.len(), 1);
+        assert!(!old_ids.iter().any(|id| cancelled_by_rejection.contains(id)));
+        assert_eq!(
+            depth_after_rejection,
+            vec![
+                (101.0, 2.0),
+                (102.0, 1.0),
+                (103.0, 1.0),
+                (104.0, 1.0),
+                (105.0, 1.0)
+            ]
+        );
+        assert_eq!(
+            queue_after_rejection,
+            Some(vec![(other_id, 1.0), (old_ids[0], 1.0)])
+        );
+        assert!(old_ids.iter().all(|id| order_book.get_order(*id).is_none()));
+        assert_eq!(
+            order_book
+                .orders_by_owner(OrderType::Ask, maker)
+                .iter()
+                .map(|view| view.id)
+                .collect::<Vec<_>>(),
+            new_ids
+        );
+        assert_eq!(
+            order_book.depth(10).1,
+            vec![(101.0, 1.0), (101.5, 2.0), (102.5, 2.0), (103.5, 2.0)]
+        );
+    }
+
+    #[test]
+    fn successfully_rejects_a_ladder_quoted_through_the_opposite_touch() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let maker = Uuid::new_v4();
+        order_book
+            .replace_ladder(maker, OrderType::Ask, &[(101.0, 1.0), (102.0, 1.0)])
+            .unwrap();
+
+        // When
+        let rejected =
+            order_book.replace_ladder(maker, OrderType::Ask, &[(100.5, 1.0), (99.5, 1.0)]);
+
+        // Then
+        assert!(matches!(
+            rejected,
+            Err(OrderBookError::PostOnlyWouldCross(_))
+        ));
+        assert!(order_book.trades().is_empty());
+        assert_eq!(
+            order_book.depth(10),
+            (vec![(100.0, 1.0)], vec![(101.0, 1.0), (102.0, 1.0)])
+        );
+        assert_eq!(order_book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn successfully_finds_orders_resting_longer_than_the_threshold() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let oldest = Order::new(OrderType::Ask, 1.0);
+        let oldest_id = oldest.id;
+        order_book.add_order(101.0, oldest).unwrap();
+        clock.advance(30);
+        let old = Order::new(OrderType::Bid, 1.0);
+        let old_id = old.id;
+        order_book.add_order(99.0, old).unwrap();
+        clock.advance(61);
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let stale = order_book.stale_orders(60, order_book.now());
+
+        // Then
+        assert_eq!(stale, vec![oldest_id, old_id]);
+    }
 
-        match price_to_limit_idx_map.get(&price_key) {
-            Some(&limit_idx) => {
-                // We already have a limit for this price so we add the order to it
-                if let Some(limit) = limits.get_mut(limit_idx) {
-                    limit.add_order(order);
-                    Ok(())
+    #[test]
+    fn successfully_rejects_cancels_before_the_minimum_resting_time() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            min_resting_time_ms: Some(250),
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        let order = Order::new(OrderType::Bid, 1.0);
+        let order_id = order.id;
+        order_book.add_order(99.0, order).unwrap();
+
+        // When
+        let immediate = order_book.cancel_order(order_id).map(|o| o.id);
+        clock.advance_millis(250);
+        let later = order_book.cancel_order(order_id).map(|o| o.id);
+
+        // Then
+        assert_eq!(
+            immediate,
+            Err(OrderBookError::CancelTooSoon {
+                order_id,
+                min_resting_time_ms: 250
+            })
+        );
+        assert_eq!(later, Ok(order_id));
+    }
+
+    #[test]
+    fn successfully_counts_displayed_and_hidden_orders_apart() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 5.0).with_iceberg(1.0))
+            .unwrap();
+        for price in [99.0, 97.0] {
+            order_book
+                .add_order(
+                    price,
+                    Order::new(OrderType::Bid, 2.0).with_visibility(Visibility::Hidden),
+                )
+                .unwrap();
+        }
+
+        // When
+        let bid_counts = order_book.order_count_detailed(OrderType::Bid);
+
+        // Then
+        assert_eq!(bid_counts, (2, 4));
+        assert_eq!(order_book.order_count_detailed(OrderType::Ask), (0, 0));
+    }
+
+    #[test]
+    fn successfully_estimates_the_fair_price_where_depth_balances() {
+        // Given
+        let populate = |levels: &[(f64, f64)]| {
+            let mut order_book = OrderBook::new();
+            for &(price, size) in levels {
+                let side = if price < 100.0 {
+                    OrderType::Bid
                 } else {
-                    Err(format!(
-                        "Limit index {} is invalid for price {}",
-                        limit_idx, price
-                    ))
-                }
-            }
-            None => {
-                // These is no limit for this price yet so we need to create one
-                let mut limit = Limit::new(price);
-                limit.add_order(order);
-                let new_limit_idx = limits.len();
-                limits.push(limit);
-                price_to_limit_idx_map.insert(price_key, new_limit_idx);
-                Ok(())
+                    OrderType::Ask
+                };
+                order_book.add_order(price, Order::new(side, size)).unwrap();
             }
-        }
+            order_book
+        };
+        let symmetric = populate(&[(99.0, 1.0), (98.0, 2.0), (101.0, 1.0), (102.0, 2.0)]);
+        let thin_asks = populate(&[
+            (99.0, 5.0),
+            (98.0, 5.0),
+            (101.0, 1.0),
+            (102.0, 1.0),
+            (103.0, 1.0),
+        ]);
+
+        // When
+        let symmetric_fair = symmetric.implied_fair(10);
+        let skewed_fair = thin_asks.implied_fair(10);
+
+        // Then
+        assert_eq!(symmetric_fair, symmetric.mid_price());
+        assert_eq!(skewed_fair, Some(101.0));
+        assert!(skewed_fair > thin_asks.mid_price());
+        assert_eq!(OrderBook::new().implied_fair(10), None);
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use crate::order_book::{Limit, Order, OrderBook, OrderType};
+    #[test]
+    fn successfully_nets_an_accounts_fills_into_a_settlement_record() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            fee_schedule: FeeSchedule::new(-1.0, 5.0),
+            ..OrderBookConfig::default()
+        })
+        .with_clock(clock.clone());
+        let (trader, maker) = (Uuid::new_v4(), Uuid::new_v4());
+        order_book
+            .add_order(100.0, Order::new(OrderType::Ask, 4.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 4.0).with_owner(trader))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .add_order(110.0, Order::new(OrderType::Ask, 1.0).with_owner(trader))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0).with_owner(maker))
+            .unwrap();
+        clock.advance(10);
+        order_book
+            .add_order(120.0, Order::new(OrderType::Ask, 1.0).with_owner(maker))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0).with_owner(trader))
+            .unwrap();
+
+        // When
+        let records = order_book.settlement_records(1_000, 1_010);
+
+        // Then
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].owner, maker);
+        let trader_record = &records[1];
+        assert_eq!(trader_record.owner, trader);
+        assert_eq!(trader_record.trade_count, 2);
+        assert!((trader_record.base_delta - 3.0).abs() < 1e-9);
+        assert!((trader_record.quote_delta - (-400.0 + 110.0)).abs() < 1e-9);
+        assert!((trader_record.fees - (0.2 - 0.011)).abs() < 1e-9);
+        assert!((records[0].base_delta + 3.0).abs() < 1e-9);
+    }
 
     #[test]
-    fn successfully_adds_a_buy_order_to_a_limit() {
+    fn successfully_rejects_or_rests_a_limit_order_at_the_opposite_touch() {
         // Given
-        let mut limit = Limit::new(10_000.0);
-        let buy_order = Order::new(OrderType::Bid, 5.0);
+        let with_policy = |touch_policy| {
+            let mut order_book = OrderBook::with_config(OrderBookConfig {
+                touch_policy,
+                ..OrderBookConfig::default()
+            });
+            order_book
+                .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+                .unwrap();
+            order_book
+        };
+        let mut rejecting = with_policy(TouchPolicy::Reject);
+        let mut resting = with_policy(TouchPolicy::Rest);
+        let bid = Order::new(OrderType::Bid, 1.0);
+        let bid_id = bid.id;
 
         // When
-        limit.add_order(buy_order);
+        let rejected = rejecting.place_limit_order(101.0, bid);
+        let rested = resting.place_limit_order(101.0, Order::new(OrderType::Bid, 1.0));
 
         // Then
-        assert_eq!(limit.orders.len(), 1);
+        assert_eq!(rejected, Err(OrderBookError::WouldLockBook(bid_id)));
+        assert_eq!(rejecting.depth(10).0, vec![]);
+        assert_eq!(rested, Ok(vec![]));
+        assert!(resting.is_locked());
+        assert_eq!(resting.check_invariants(), Ok(()));
+        assert!(
+            rejecting
+                .place_limit_order(101.5, Order::new(OrderType::Bid, 1.0))
+                .is_ok_and(|matches| matches.len() == 1)
+        );
     }
 
     #[test]
-    fn successfully_removes_a_buy_order_from_a_limit() {
+    fn successfully_counts_only_the_displayed_slice_of_an_iceberg_ahead() {
         // Given
-        let mut limit = Limit::new(10_000.0);
-        let buy_order_a = Order::new(OrderType::Bid, 5.0);
-        let buy_order_b = Order::new(OrderType::Bid, 8.0);
-        let buy_order_c = Order::new(OrderType::Bid, 10.0);
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 10.0).with_iceberg(2.0))
+            .unwrap();
+        order_book
+            .add_order(
+                99.0,
+                Order::new(OrderType::Bid, 5.0).with_visibility(Visibility::Hidden),
+            )
+            .unwrap();
+        let order = Order::new(OrderType::Bid, 1.0);
+        let order_id = order.id;
+        order_book.add_order(99.0, order).unwrap();
 
-        // Store the ID before moving the order
-        let buy_order_b_id = buy_order_b.id;
+        // When
+        let ahead = order_book.displayed_queue_ahead(order_id);
 
-        limit.add_order(buy_order_a);
-        limit.add_order(buy_order_b);
-        limit.add_order(buy_order_c);
+        // Then
+        assert_eq!(ahead, Some(2.0));
+        assert_eq!(order_book.displayed_queue_ahead(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn successfully_stamps_a_level_whenever_it_changes() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let first_update = order_book.level_last_updated(OrderType::Bid, 99.0);
 
         // When
-        let _result = limit.remove_order(buy_order_b_id);
+        clock.advance(5);
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 2.0))
+            .unwrap();
+        clock.advance(5);
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 0.5))
+            .unwrap();
 
         // Then
-        assert_eq!(limit.total_volume, 15.0);
+        assert_eq!(first_update, Some(1_000));
+        assert_eq!(
+            order_book.level_last_updated(OrderType::Bid, 99.0),
+            Some(1_010)
+        );
+        assert_eq!(order_book.level_last_updated(OrderType::Bid, 98.0), None);
     }
 
     #[test]
-    fn successfully_adds_a_buy_order_to_an_order_book() {
-        //Given
+    fn successfully_enters_orders_by_yield_at_their_canonical_prices() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_size: 0.005,
+            yield_convention: Some(YieldConvention::Index { par: 100.0 }),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .place_limit_order_by_yield(4.25, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .place_limit_order_by_yield(4.2, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // When
+        let (bids, asks) = order_book.depth(10);
+        let matches = order_book
+            .place_limit_order_by_yield(4.2, Order::new(OrderType::Bid, 0.5))
+            .unwrap();
+
+        // Then
+        assert_eq!(bids, vec![(95.75, 1.0)]);
+        assert_eq!(asks, vec![(95.8, 2.0)]);
+        assert!((order_book.price_to_yield(asks[0].0).unwrap() - 4.2).abs() < 1e-9);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            OrderBook::new().yield_to_price(4.2),
+            Err(OrderBookError::NoYieldConvention)
+        );
+        let bill = YieldConvention::Discount {
+            face: 100.0,
+            days_to_maturity: 90,
+        };
+        assert!((bill.yield_to_price(4.0) - 99.0).abs() < 1e-9);
+        assert!((bill.price_to_yield(99.0) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn successfully_merges_duplicate_limits_of_one_tick() {
+        // Given
         let mut order_book = OrderBook::new();
-        let buy_order = Order::new(OrderType::Bid, 10.0);
+        let first = Order::new(OrderType::Bid, 1.0);
+        let first_id = first.id;
+        order_book.add_order(99.0, first).unwrap();
+        order_book
+            .add_order(98.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let mut duplicate = Limit::new(99.0 + 1e-10);
+        let mut second = Order::new(OrderType::Bid, 2.0);
+        second.sequence = order_book.next_sequence;
+        let second_id = second.id;
+        duplicate.add_order(second);
+        let bids = order_book.limits.get_mut(&OrderType::Bid).unwrap();
+        bids.push(duplicate);
+        let duplicate_idx = bids.len() - 1;
+        order_book
+            .order_index
+            .insert(second_id, (OrderType::Bid, duplicate_idx));
 
-        //When
-        let _result = order_book.add_order(15_000.0, buy_order);
+        // When
+        let merged = order_book.dedup_levels(OrderType::Bid);
 
-        //Then
-        assert_eq!(order_book.limits.get(&OrderType::Bid).unwrap().len(), 1);
+        // Then
+        assert_eq!(merged, 1);
+        assert_eq!(order_book.depth(10).0, vec![(99.0, 3.0), (98.0, 1.0)]);
+        assert_eq!(
+            order_book.level_queue(OrderType::Bid, 99.0),
+            Some(vec![(first_id, 1.0), (second_id, 2.0)])
+        );
+        assert!(order_book.cancel_order(second_id).is_ok());
+        assert_eq!(order_book.depth(10).0, vec![(99.0, 1.0), (98.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_measures_the_volatility_of_the_spread() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .add_order(100.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        let tight_ask = Order::new(OrderType::Ask, 1.0);
+        let tight_ask_id = tight_ask.id;
+        order_book.add_order(100.5, tight_ask).unwrap();
+        order_book.cancel_order(tight_ask_id).unwrap();
+
+        // When
+        let volatility = order_book.spread_volatility(60, order_book.now());
+        clock.advance(120);
+        let stale_volatility = order_book.spread_volatility(60, order_book.now());
+
+        // Then
+        // Spreads of 2, 1, 0.5 and 1 around a mean of 1.125
+        assert!((volatility.unwrap() - 0.296875f64.sqrt()).abs() < 1e-9);
+        assert_eq!(stale_volatility, None);
+    }
+
+    #[test]
+    fn successfully_ignores_a_retried_submission_with_the_same_idempotency_key() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            idempotency_keys_per_owner: 1,
+            ..OrderBookConfig::default()
+        });
+        let owner = Uuid::new_v4();
+
+        // When
+        let first = order_book
+            .submit_limit_order(
+                99.0,
+                Order::new(OrderType::Bid, 1.0).with_owner(owner),
+                Some("k1"),
+            )
+            .unwrap();
+        let retried = order_book
+            .submit_limit_order(
+                99.0,
+                Order::new(OrderType::Bid, 1.0).with_owner(owner),
+                Some("k1"),
+            )
+            .unwrap();
+        let depth_after_retry = order_book.depth(10).0;
+        order_book
+            .submit_limit_order(
+                98.0,
+                Order::new(OrderType::Bid, 1.0).with_owner(owner),
+                Some("k2"),
+            )
+            .unwrap();
+        let after_eviction = order_book
+            .submit_limit_order(
+                99.0,
+                Order::new(OrderType::Bid, 1.0).with_owner(owner),
+                Some("k1"),
+            )
+            .unwrap();
+
+        // Then
+        assert_eq!(first, retried);
+        assert_eq!(depth_after_retry, vec![(99.0, 1.0)]);
+        assert_ne!(after_eviction.0, first.0);
+        assert_eq!(order_book.depth(10).0, vec![(99.0, 2.0), (98.0, 1.0)]);
+    }
+
+    #[test]
+    fn successfully_averages_the_fill_prices_of_an_aggressive_order() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+
+        // When
+        let matches = order_book
+            .place_market_order(Order::new(OrderType::Bid, 3.0))
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            Match::weighted_price(&matches),
+            Some((101.0 + 2.0 * 102.0) / 3.0)
+        );
+        assert_eq!(Match::weighted_price(&[]), None);
+    }
+
+    #[test]
+    fn successfully_rejects_fat_finger_prices_and_sizes() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            max_price: Some(1_000.0),
+            max_size: Some(100.0),
+            price_band: Some(0.05),
+            ..OrderBookConfig::default()
+        });
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 1.0))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let absurd_price = order_book.place_limit_order(100_000.0, Order::new(OrderType::Bid, 1.0));
+        let absurd_size = order_book.place_market_order(Order::new(OrderType::Bid, 1_000_000.0));
+
+        // Then
+        assert_eq!(
+            absurd_price,
+            Err(OrderBookError::PriceAboveMaximum {
+                price: 100_000.0,
+                max_price: 1_000.0
+            })
+        );
+        assert_eq!(
+            absurd_size,
+            Err(OrderBookError::SizeAboveMaximum {
+                size: 1_000_000.0,
+                max_size: 100.0
+            })
+        );
+        assert!(
+            order_book
+                .place_limit_order(1_000.0, Order::new(OrderType::Bid, 100.0))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn successfully_profiles_the_liquidity_skew_by_tick_distance() {
+        // Given
+        let mut order_book = OrderBook::with_config(OrderBookConfig {
+            tick_size: 1.0,
+            ..OrderBookConfig::default()
+        });
+        for (side, price, size) in [
+            (OrderType::Bid, 99.0, 3.0),
+            (OrderType::Bid, 97.0, 2.0),
+            (OrderType::Bid, 90.0, 5.0),
+            (OrderType::Ask, 101.0, 1.0),
+            (OrderType::Ask, 102.0, 4.0),
+        ] {
+            order_book.add_order(price, Order::new(side, size)).unwrap();
+        }
+
+        // When
+        let profile = order_book.skew_profile(3);
+
+        // Then
+        assert_eq!(profile, vec![(1, 2.0), (2, -4.0), (3, 2.0)]);
+        assert!(OrderBook::new().skew_profile(3).is_empty());
     }
 }
@@ -0,0 +1,159 @@
+use uuid::Uuid;
+
+use crate::order_book::{Order, OrderBook};
+
+// Arrow data type of a column. Ids and owners are hyphenated UUID strings and the side is
+// "Bid" or "Ask", so every column maps onto a plain Arrow array without extension types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Utf8,
+    Float64,
+    Int64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Utf8(Vec<String>),
+    Float64(Vec<f64>),
+    Int64(Vec<i64>),
+}
+
+impl Column {
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Utf8(values) => values.len(),
+            Column::Float64(values) => values.len(),
+            Column::Int64(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// The resting orders as equally long typed columns, laid out the way an Arrow record batch
+// is so it can be handed to Polars or DataFusion column by column without reshaping.
+// The schema never changes order or type without a new column name:
+//   id Utf8, side Utf8, price Float64, remaining_size Float64, timestamp Int64, owner Utf8
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordBatch {
+    schema: Vec<Field>,
+    columns: Vec<Column>,
+}
+
+impl RecordBatch {
+    pub fn schema(&self) -> &[Field] {
+        &self.schema
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, Column::len)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        let position = self.schema.iter().position(|field| field.name == name)?;
+        self.columns.get(position)
+    }
+}
+
+fn uuid_column(orders: &[(f64, &Order)], uuid: impl Fn(&Order) -> Uuid) -> Column {
+    Column::Utf8(orders.iter().map(|(_, o)| uuid(o).to_string()).collect())
+}
+
+impl OrderBook {
+    // One row per resting order in arrival order, hidden orders included. The remaining size
+    // is what is left of the whole order, an iceberg's reserve included.
+    pub fn to_record_batch(&self) -> RecordBatch {
+        let mut orders: Vec<(f64, &Order)> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter().map(|o| (limit.price, o)))
+            .collect();
+        orders.sort_by_key(|(_, o)| o.sequence);
+
+        let field = |name, data_type| Field { name, data_type };
+        RecordBatch {
+            schema: vec![
+                field("id", DataType::Utf8),
+                field("side", DataType::Utf8),
+                field("price", DataType::Float64),
+                field("remaining_size", DataType::Float64),
+                field("timestamp", DataType::Int64),
+                field("owner", DataType::Utf8),
+            ],
+            columns: vec![
+                uuid_column(&orders, |o| o.id),
+                Column::Utf8(
+                    orders
+                        .iter()
+                        .map(|(_, o)| o.order_type.to_string())
+                        .collect(),
+                ),
+                Column::Float64(orders.iter().map(|&(price, _)| price).collect()),
+                Column::Float64(orders.iter().map(|(_, o)| o.size + o.reserve).collect()),
+                Column::Int64(orders.iter().map(|(_, o)| o.timestamp).collect()),
+                uuid_column(&orders, |o| o.owner),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::order_book::{Order, OrderBook, OrderType};
+    use crate::record_batch::{Column, DataType};
+
+    #[test]
+    fn successfully_exports_resting_orders_as_a_record_batch() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let bid = Order::new(OrderType::Bid, 1.5);
+        let bid_id = bid.id;
+        order_book.add_order(99.0, bid).unwrap();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 5.0).with_iceberg(1.0))
+            .unwrap();
+        order_book
+            .add_order(102.0, Order::new(OrderType::Ask, 2.0))
+            .unwrap();
+
+        // When
+        let batch = order_book.to_record_batch();
+
+        // Then
+        let schema: Vec<(&str, DataType)> = batch
+            .schema()
+            .iter()
+            .map(|field| (field.name, field.data_type))
+            .collect();
+        assert_eq!(
+            schema,
+            vec![
+                ("id", DataType::Utf8),
+                ("side", DataType::Utf8),
+                ("price", DataType::Float64),
+                ("remaining_size", DataType::Float64),
+                ("timestamp", DataType::Int64),
+                ("owner", DataType::Utf8),
+            ]
+        );
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.column("remaining_size"),
+            Some(&Column::Float64(vec![1.5, 5.0, 2.0]))
+        );
+        let Some(Column::Utf8(ids)) = batch.column("id") else {
+            panic!("id column is not Utf8");
+        };
+        assert_eq!(ids[0], bid_id.to_string());
+        assert_eq!(OrderBook::new().to_record_batch().num_rows(), 0);
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::order_book::{Order, OrderBook};
+
+// Why a risk check turned an order away, passed on to the client as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectReason(pub String);
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Pre-trade check the book runs before it accepts any order, injected so that margin or
+// credit checks can be plugged in without touching the engine
+pub trait RiskCheck: Send + Sync {
+    fn check(&self, order: &Order, book: &OrderBook) -> Result<(), RejectReason>;
+}
+
+// Accepts every order, what a book uses unless told otherwise
+#[derive(Debug, Default)]
+pub struct NoRiskCheck;
+
+impl RiskCheck for NoRiskCheck {
+    fn check(&self, _order: &Order, _book: &OrderBook) -> Result<(), RejectReason> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::order_book::{Order, OrderBook, OrderBookError, OrderType};
+    use crate::risk::{RejectReason, RiskCheck};
+
+    struct MaxOrderSize(f64);
+
+    impl RiskCheck for MaxOrderSize {
+        fn check(&self, order: &Order, _book: &OrderBook) -> Result<(), RejectReason> {
+            if order.size > self.0 {
+                return Err(RejectReason(format!("Size above {}", self.0)));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn successfully_rejects_orders_failing_the_risk_check() {
+        // Given
+        let mut order_book = OrderBook::new().with_risk_check(Arc::new(MaxOrderSize(5.0)));
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 5.0))
+            .unwrap();
+
+        // When
+        let resting = order_book.add_order(101.0, Order::new(OrderType::Ask, 6.0));
+        let limit = order_book.place_limit_order(101.0, Order::new(OrderType::Bid, 6.0));
+        let market = order_book.place_market_order(Order::new(OrderType::Bid, 2.0));
+
+        // Then
+        let reason = RejectReason("Size above 5".to_string());
+        assert_eq!(resting, Err(OrderBookError::RiskRejected(reason.clone())));
+        assert_eq!(limit, Err(OrderBookError::RiskRejected(reason)));
+        assert_eq!(market.unwrap().len(), 1);
+        assert_eq!(order_book.depth(10).1, vec![(101.0, 3.0)]);
+    }
+
+    #[test]
+    fn successfully_runs_the_risk_check_on_every_entry_point() {
+        // Given
+        let mut order_book = OrderBook::new().with_risk_check(Arc::new(MaxOrderSize(5.0)));
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 5.0))
+            .unwrap();
+        for _ in 0..2 {
+            order_book
+                .add_order(101.0, Order::new(OrderType::Ask, 5.0))
+                .unwrap();
+        }
+        let bid = Order::new(OrderType::Bid, 3.0);
+        let bid_id = bid.id();
+        order_book.add_order(98.0, bid).unwrap();
+        let quote = order_book
+            .request_quote(OrderType::Bid, 6.0, Uuid::nil())
+            .unwrap();
+
+        // When
+        let rfq = order_book.accept_rfq(quote.quote_id);
+        let midpoint = order_book.place_midpoint_order(Order::new(OrderType::Bid, 6.0));
+        let protected =
+            order_book.place_protected_market_order(Order::new(OrderType::Bid, 6.0), 1.0);
+        let grown = order_book.amend_order(bid_id, 98.0, 6.0);
+        let shrunk = order_book.amend_order(bid_id, 97.0, 2.0);
+
+        // Then
+        let reason = RejectReason("Size above 5".to_string());
+        let rejected = || Some(OrderBookError::RiskRejected(reason.clone()));
+        assert_eq!(rfq.err(), rejected());
+        assert_eq!(midpoint.err(), rejected());
+        assert_eq!(protected.err(), rejected());
+        assert_eq!(grown.err(), rejected());
+        assert_eq!(shrunk, Ok(()));
+        assert_eq!(
+            order_book.depth(10),
+            (vec![(99.0, 5.0), (97.0, 2.0)], vec![(101.0, 10.0)])
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::error::Error;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::order_book::{
+    ExecInstructions, ExecutionPrice, Order, OrderBook, OrderBookConfig, OrderBookError,
+    OrderClass, OrderType, TimeInForce, Visibility,
+};
+
+// Bumped whenever the layout changes so old snapshots are rejected instead of misread
+const SNAPSHOT_VERSION: u8 = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    UnexpectedEnd,
+    UnsupportedVersion(u8),
+    InvalidTag { field: &'static str, tag: u8 },
+    InvalidClientOrderId,
+    TrailingBytes(usize),
+    OrderBook(OrderBookError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnexpectedEnd => write!(f, "Snapshot ended unexpectedly"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "Snapshot version {version} is not supported")
+            }
+            SnapshotError::InvalidTag { field, tag } => {
+                write!(f, "Invalid tag {tag} for {field}")
+            }
+            SnapshotError::InvalidClientOrderId => {
+                write!(f, "Client order id is not valid UTF-8")
+            }
+            SnapshotError::TrailingBytes(count) => {
+                write!(f, "Snapshot has {count} unexpected trailing bytes")
+            }
+            SnapshotError::OrderBook(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<OrderBookError> for SnapshotError {
+    fn from(err: OrderBookError) -> Self {
+        SnapshotError::OrderBook(err)
+    }
+}
+
+// Layout, all numbers little endian: version u8, next sequence u64, order count u32 and
+// every resting order in arrival order as its price f64 followed by its fields, midpoint
+// order count u32 and every midpoint order, bids first and each side in queue order,
+// then OCO link count u32 and both order ids of every link. Optional values are a 0/1 byte
+// followed by the value when present, strings are a u32 length followed by UTF-8 bytes.
+// Only orders and their links are part of it, the config, trades and pending stop or
+// delayed orders are not.
+impl OrderBook {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut orders: Vec<(f64, &Order)> = self
+            .limits
+            .values()
+            .flatten()
+            .flat_map(|limit| limit.orders.iter().map(|o| (limit.price, o)))
+            .collect();
+        orders.sort_by_key(|(_, o)| o.sequence);
+        let midpoint_orders: Vec<&Order> = [OrderType::Bid, OrderType::Ask]
+            .into_iter()
+            .flat_map(|side| self.midpoint_orders(side))
+            .collect();
+        // Links are stored both ways, each one is written once and in a stable order
+        let mut oco_links: Vec<(Uuid, Uuid)> = self
+            .oco_links
+            .iter()
+            .filter(|(order_id, sibling_id)| order_id < sibling_id)
+            .map(|(&order_id, &sibling_id)| (order_id, sibling_id))
+            .collect();
+        oco_links.sort();
+
+        let mut bytes = vec![SNAPSHOT_VERSION];
+        bytes.extend(self.next_sequence.to_le_bytes());
+        bytes.extend((orders.len() as u32).to_le_bytes());
+        for (price, order) in orders {
+            bytes.extend(price.to_le_bytes());
+            encode_order(&mut bytes, order);
+        }
+        bytes.extend((midpoint_orders.len() as u32).to_le_bytes());
+        for order in midpoint_orders {
+            encode_order(&mut bytes, order);
+        }
+        bytes.extend((oco_links.len() as u32).to_le_bytes());
+        for (order_id, sibling_id) in oco_links {
+            bytes.extend(order_id.as_bytes());
+            bytes.extend(sibling_id.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<OrderBook, SnapshotError> {
+        OrderBook::from_bytes_with_config(OrderBookConfig::default(), bytes)
+    }
+
+    // Orders are re-added oldest first with their original sequence, timestamp and resting
+    // clock, so the restored book has the same priorities as the one the snapshot was
+    // taken of
+    pub fn from_bytes_with_config(
+        config: OrderBookConfig,
+        bytes: &[u8],
+    ) -> Result<OrderBook, SnapshotError> {
+        let mut reader = Reader { bytes, position: 0 };
+        let version = reader.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let next_sequence = reader.u64()?;
+
+        let mut order_book = OrderBook::with_config(config);
+        for _ in 0..reader.u32()? {
+            let price = reader.f64()?;
+            let order = decode_order(&mut reader)?;
+            order_book.restore_order(price, order)?;
+        }
+        for _ in 0..reader.u32()? {
+            let order = decode_order(&mut reader)?;
+            order_book.restore_midpoint_order(order)?;
+        }
+        for _ in 0..reader.u32()? {
+            let (order_id, sibling_id) = (reader.uuid()?, reader.uuid()?);
+            order_book.link_oco(order_id, sibling_id)?;
+        }
+        order_book.next_sequence = next_sequence;
+
+        let trailing = reader.bytes.len() - reader.position;
+        if trailing > 0 {
+            return Err(SnapshotError::TrailingBytes(trailing));
+        }
+        Ok(order_book)
+    }
+}
+
+fn encode_order(bytes: &mut Vec<u8>, order: &Order) {
+    bytes.extend(order.id.as_bytes());
+    bytes.push(match order.order_type {
+        OrderType::Bid => 0,
+        OrderType::Ask => 1,
+    });
+    bytes.extend(order.size.to_le_bytes());
+    bytes.extend(order.timestamp.to_le_bytes());
+    bytes.extend(order.rested_at_millis.to_le_bytes());
+    bytes.extend(order.sequence.to_le_bytes());
+    bytes.extend(order.owner.as_bytes());
+    bytes.push(match order.time_in_force {
+        TimeInForce::GoodTillCancel => 0,
+        TimeInForce::Session => 1,
+    });
+    bytes.push(match order.visibility {
+        Visibility::Displayed => 0,
+        Visibility::Hidden => 1,
+    });
+    bytes.push(match order.execution_price {
+        ExecutionPrice::Maker => 0,
+        ExecutionPrice::Taker => 1,
+    });
+    bytes.push(match order.class {
+        OrderClass::Normal => 0,
+        OrderClass::Liquidation => 1,
+    });
+    match order.expires_at {
+        Some(expires_at) => {
+            bytes.push(1);
+            bytes.extend(expires_at.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+    match &order.client_order_id {
+        Some(client_order_id) => {
+            bytes.push(1);
+            bytes.extend((client_order_id.len() as u32).to_le_bytes());
+            bytes.extend(client_order_id.as_bytes());
+        }
+        None => bytes.push(0),
+    }
+    match order.peak {
+        Some(peak) => {
+            bytes.push(1);
+            bytes.extend(peak.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes.extend(order.reserve.to_le_bytes());
+    match order.refreshes_remaining {
+        Some(refreshes_remaining) => {
+            bytes.push(1);
+            bytes.extend(refreshes_remaining.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes.extend(order.cumulative_filled.to_le_bytes());
+    bytes.extend(order.avg_fill_price.to_le_bytes());
+    bytes.push(order.exec_instructions.bits());
+    match order.min_fill_size {
+        Some(min_fill_size) => {
+            bytes.push(1);
+            bytes.extend(min_fill_size.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn decode_order(reader: &mut Reader) -> Result<Order, SnapshotError> {
+    let id = reader.uuid()?;
+    let order_type = match reader.u8()? {
+        0 => OrderType::Bid,
+        1 => OrderType::Ask,
+        tag => return Err(SnapshotError::InvalidTag { field: "side", tag }),
+    };
+    let mut order = Order::new(order_type, reader.f64()?).with_id(id);
+    order.timestamp = reader.i64()?;
+    order.rested_at_millis = reader.i64()?;
+    order.sequence = reader.u64()?;
+    order.owner = reader.uuid()?;
+    order.time_in_force = match reader.u8()? {
+        0 => TimeInForce::GoodTillCancel,
+        1 => TimeInForce::Session,
+        tag => {
+            return Err(SnapshotError::InvalidTag {
+                field: "time in force",
+                tag,
+            });
+        }
+    };
+    order.visibility = match reader.u8()? {
+        0 => Visibility::Displayed,
+        1 => Visibility::Hidden,
+        tag => {
+            return Err(SnapshotError::InvalidTag {
+                field: "visibility",
+                tag,
+            });
+        }
+    };
+    order.execution_price = match reader.u8()? {
+        0 => ExecutionPrice::Maker,
+        1 => ExecutionPrice::Taker,
+        tag => {
+            return Err(SnapshotError::InvalidTag {
+                field: "execution price",
+                tag,
+            });
+        }
+    };
+    order.class = match reader.u8()? {
+        0 => OrderClass::Normal,
+        1 => OrderClass::Liquidation,
+        tag => {
+            return Err(SnapshotError::InvalidTag {
+                field: "class",
+                tag,
+            });
+        }
+    };
+    if reader.flag("expiry")? {
+        order.expires_at = Some(reader.i64()?);
+    }
+    if reader.flag("client order id")? {
+        let len = reader.u32()? as usize;
+        let client_order_id = String::from_utf8(reader.take(len)?.to_vec())
+            .map_err(|_| SnapshotError::InvalidClientOrderId)?;
+        order.client_order_id = Some(client_order_id);
+    }
+    if reader.flag("peak")? {
+        order.peak = Some(reader.f64()?);
+    }
+    order.reserve = reader.f64()?;
+    if reader.flag("refreshes remaining")? {
+        order.refreshes_remaining = Some(reader.u32()?);
+    }
+    order.cumulative_filled = reader.f64()?;
+    order.avg_fill_price = reader.f64()?;
+    let tag = reader.u8()?;
+    order.exec_instructions =
+        ExecInstructions::from_bits(tag).ok_or(SnapshotError::InvalidTag {
+            field: "execution instructions",
+            tag,
+        })?;
+    if reader.flag("minimum fill size")? {
+        order.min_fill_size = Some(reader.f64()?);
+    }
+    Ok(order)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], SnapshotError> {
+        let end = self.position + len;
+        let taken = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(SnapshotError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().expect("Took exactly N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn flag(&mut self, field: &'static str) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(SnapshotError::InvalidTag { field, tag }),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, SnapshotError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    fn uuid(&mut self) -> Result<Uuid, SnapshotError> {
+        Ok(Uuid::from_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::clock::ManualClock;
+    use crate::order_book::{ExecInstructions, Order, OrderBook, OrderType, Visibility};
+    use crate::snapshot::SnapshotError;
+
+    #[test]
+    fn successfully_round_trips_a_book_through_its_binary_snapshot() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock.clone());
+        let owner = Uuid::new_v4();
+        let bid = Order::new(OrderType::Bid, 2.0).with_owner(owner);
+        let bid_id = bid.id();
+        order_book.add_order(99.0, bid).unwrap();
+        clock.advance(5);
+        order_book
+            .add_order(
+                99.0,
+                Order::new(OrderType::Bid, 1.0)
+                    .with_visibility(Visibility::Hidden)
+                    .with_exec_instructions(ExecInstructions::POST_ONLY),
+            )
+            .unwrap();
+        let ask = Order::new(OrderType::Ask, 3.0)
+            .with_owner(owner)
+            .with_client_order_id("ask-1")
+            .with_expiry(2_000);
+        order_book.add_order(101.0, ask).unwrap();
+        clock.advance_millis(250);
+        order_book
+            .place_midpoint_order(Order::new(OrderType::Bid, 1.5).with_owner(owner))
+            .unwrap();
+        let iceberg = Order::new(OrderType::Ask, 5.0)
+            .with_iceberg(1.0)
+            .with_max_refreshes(3);
+        let iceberg_id = iceberg.id();
+        order_book.add_order(102.0, iceberg).unwrap();
+        order_book.link_oco(bid_id, iceberg_id).unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+
+        // When
+        let bytes = order_book.to_bytes();
+        let restored = OrderBook::from_bytes(&bytes).unwrap();
+
+        // Then
+        assert_eq!(restored.to_bytes(), bytes);
+        assert_eq!(restored.depth(10), order_book.depth(10));
+        assert_eq!(restored.touch(), order_book.touch());
+        for side in [OrderType::Bid, OrderType::Ask] {
+            assert_eq!(
+                restored.orders_l3(side, true),
+                order_book.orders_l3(side, true)
+            );
+        }
+        let midpoint_bids = |order_book: &OrderBook| -> Vec<(Uuid, f64, u64)> {
+            order_book
+                .midpoint_orders(OrderType::Bid)
+                .iter()
+                .map(|o| (o.id, o.size, o.sequence))
+                .collect()
+        };
+        assert_eq!(midpoint_bids(&restored), midpoint_bids(&order_book));
+        assert_eq!(restored.oco_links, order_book.oco_links);
+        assert_eq!(
+            restored.order_id_by_client_id(owner, "ask-1"),
+            order_book.order_id_by_client_id(owner, "ask-1")
+        );
+        assert_eq!(
+            OrderBook::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(SnapshotError::UnexpectedEnd)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::order_book::{Order, OrderBook, OrderType};
+
+// A single randomly generated operation. Orders are referred to by index of the add that
+// created them so a stream can be generated without knowing what is still resting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    AddLimit {
+        side: OrderType,
+        price: f64,
+        size: f64,
+    },
+    Cancel {
+        add_idx: usize,
+    },
+    Amend {
+        add_idx: usize,
+        price: f64,
+        size: f64,
+    },
+    Market {
+        side: OrderType,
+        size: f64,
+    },
+}
+
+// Deterministic op stream driven by a 64-bit linear congruential generator so a failing
+// run can be reproduced from its seed alone
+pub struct OpGenerator {
+    state: u64,
+    adds: usize,
+}
+
+impl OpGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            adds: 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.state >> 33
+    }
+
+    fn side(&mut self) -> OrderType {
+        if self.next_u64().is_multiple_of(2) {
+            OrderType::Bid
+        } else {
+            OrderType::Ask
+        }
+    }
+
+    // Prices between 95.00 and 105.00 in half steps so orders regularly cross and share levels
+    fn price(&mut self) -> f64 {
+        95.0 + (self.next_u64() % 21) as f64 * 0.5
+    }
+
+    fn size(&mut self) -> f64 {
+        (self.next_u64() % 10 + 1) as f64 * 0.25
+    }
+
+    pub fn next_op(&mut self) -> Op {
+        let roll = self.next_u64() % 10;
+        if self.adds > 0 && roll < 3 {
+            Op::Cancel {
+                add_idx: (self.next_u64() as usize) % self.adds,
+            }
+        } else if self.adds > 0 && roll < 5 {
+            Op::Amend {
+                add_idx: (self.next_u64() as usize) % self.adds,
+                price: self.price(),
+                size: self.size(),
+            }
+        } else if roll < 6 {
+            Op::Market {
+                side: self.side(),
+                size: self.size(),
+            }
+        } else {
+            self.adds += 1;
+            Op::AddLimit {
+                side: self.side(),
+                price: self.price(),
+                size: self.size(),
+            }
+        }
+    }
+
+    pub fn ops(&mut self, count: usize) -> Vec<Op> {
+        (0..count).map(|_| self.next_op()).collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvariantViolation {
+    pub op_idx: usize,
+    pub op: Op,
+    pub reason: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invariant violated after op {} ({:?}): {}",
+            self.op_idx, self.op, self.reason
+        )
+    }
+}
+
+impl OrderBook {
+    // Applies the ops one by one, checking the book invariants after each of them. Errors
+    // returned by the individual operations (cancelling an order that already filled, an
+    // amend of an order that already filled) are expected and ignored, only broken
+    // invariants stop the run.
+    pub fn apply_ops(&mut self, ops: &[Op]) -> Result<(), InvariantViolation> {
+        let mut added_ids: Vec<Uuid> = vec![];
+        for (op_idx, op) in ops.iter().enumerate() {
+            match *op {
+                Op::AddLimit { side, price, size } => {
+                    let order = Order::new(side, size);
+                    added_ids.push(order.id());
+                    let _ = self.place_limit_order(price, order);
+                }
+                Op::Cancel { add_idx } => {
+                    if let Some(&order_id) = added_ids.get(add_idx) {
+                        let _ = self.cancel_order(order_id);
+                    }
+                }
+                Op::Amend {
+                    add_idx,
+                    price,
+                    size,
+                } => {
+                    // An amend through the touch trades like a fresh limit order would,
+                    // under the default amend cross policy
+                    if let Some(&order_id) = added_ids.get(add_idx) {
+                        let _ = self.amend_order(order_id, price, size);
+                    }
+                }
+                Op::Market { side, size } => {
+                    let _ = self.place_market_order(Order::new(side, size));
+                }
+            }
+
+            self.check_invariants()
+                .map_err(|reason| InvariantViolation {
+                    op_idx,
+                    op: op.clone(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::order_book::OrderBook;
+    use crate::stress::OpGenerator;
+
+    #[test]
+    fn successfully_generates_the_same_ops_for_the_same_seed() {
+        // Given
+        let (mut generator_a, mut generator_b) = (OpGenerator::new(7), OpGenerator::new(7));
+
+        // When
+        let (ops_a, ops_b) = (generator_a.ops(100), generator_b.ops(100));
+
+        // Then
+        assert_eq!(ops_a, ops_b);
+    }
+
+    #[test]
+    fn successfully_holds_all_invariants_over_a_fixed_seed() {
+        // Given
+        let mut order_book = OrderBook::new();
+        let ops = OpGenerator::new(20_240_601).ops(5_000);
+
+        // When
+        let result = order_book.apply_ops(&ops);
+
+        // Then
+        assert_eq!(result, Ok(()));
+        assert!(!order_book.trades().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::order_book::{OrderBook, OrderClass, OrderType, Trade};
+
+// One CSV line per trade with the fields in declaration order. A one-sided book has no mid
+// so that field is left empty.
+fn to_csv_line(trade: &Trade) -> String {
+    let mid_at_trade = trade.mid_at_trade.map(|mid| mid.to_string());
+    let taker_class = match trade.taker_class {
+        OrderClass::Normal => "Normal",
+        OrderClass::Liquidation => "Liquidation",
+    };
+    format!(
+        "{},{},{},{},{},{},{},{},{},{}\n",
+        trade.maker_order_id,
+        trade.taker_order_id,
+        trade.maker_owner,
+        trade.taker_owner,
+        trade.aggressor,
+        trade.price,
+        trade.size,
+        trade.timestamp,
+        mid_at_trade.unwrap_or_default(),
+        taker_class
+    )
+}
+
+fn from_csv_line(line: &str) -> Option<Trade> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let [
+        maker_order_id,
+        taker_order_id,
+        maker_owner,
+        taker_owner,
+        aggressor,
+        price,
+        size,
+        timestamp,
+        mid_at_trade,
+        taker_class,
+    ] = fields[..]
+    else {
+        return None;
+    };
+    Some(Trade {
+        maker_order_id: Uuid::from_str(maker_order_id).ok()?,
+        taker_order_id: Uuid::from_str(taker_order_id).ok()?,
+        maker_owner: Uuid::from_str(maker_owner).ok()?,
+        taker_owner: Uuid::from_str(taker_owner).ok()?,
+        aggressor: match aggressor {
+            "Bid" => OrderType::Bid,
+            "Ask" => OrderType::Ask,
+            _ => return None,
+        },
+        price: price.parse().ok()?,
+        size: size.parse().ok()?,
+        timestamp: timestamp.parse().ok()?,
+        mid_at_trade: match mid_at_trade {
+            "" => None,
+            mid => Some(mid.parse().ok()?),
+        },
+        taker_class: match taker_class {
+            "Normal" => OrderClass::Normal,
+            "Liquidation" => OrderClass::Liquidation,
+            _ => return None,
+        },
+    })
+}
+
+// Reads a tape written by a trade log back, e.g. to recover after a restart
+pub fn read_trade_log(path: impl AsRef<Path>) -> io::Result<Vec<Trade>> {
+    let reader = BufReader::new(File::open(path)?);
+    reader
+        .lines()
+        .map(|line| {
+            let line = line?;
+            from_csv_line(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid trade log line {line}"),
+                )
+            })
+        })
+        .collect()
+}
+
+impl OrderBook {
+    // Appends every trade from now on to the file, creating it if needed. Each line is
+    // synced to disk before the trade is considered logged.
+    pub fn attach_trade_log(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.trade_log = Some(file);
+        self.trade_log_error = None;
+        Ok(())
+    }
+
+    // The error that made the trade log stop, if any. A failed write detaches the log while
+    // the book itself carries on, so the tape in memory stays complete.
+    pub fn take_trade_log_error(&mut self) -> Option<io::Error> {
+        self.trade_log_error.take()
+    }
+
+    pub(crate) fn append_to_trade_log(&mut self, trade: &Trade) {
+        let Some(file) = self.trade_log.as_mut() else {
+            return;
+        };
+        let written = file
+            .write_all(to_csv_line(trade).as_bytes())
+            .and_then(|_| file.sync_data());
+        if let Err(err) = written {
+            self.trade_log = None;
+            self.trade_log_error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+
+    use uuid::Uuid;
+
+    use crate::order_book::{Order, OrderBook, OrderType};
+    use crate::trade_log::read_trade_log;
+
+    #[test]
+    fn successfully_reads_back_the_trades_appended_to_the_log() {
+        // Given
+        let path = std::env::temp_dir().join(format!("trade-log-{}.csv", Uuid::new_v4()));
+        let mut order_book = OrderBook::new();
+        order_book.attach_trade_log(&path).unwrap();
+        order_book
+            .add_order(101.5, Order::new(OrderType::Ask, 3.0))
+            .unwrap();
+
+        // When
+        order_book
+            .place_market_order(Order::new(OrderType::Bid, 1.25))
+            .unwrap();
+        order_book
+            .add_order(99.0, Order::new(OrderType::Bid, 1.0))
+            .unwrap();
+        order_book
+            .place_market_order(Order::new(OrderType::Ask, 0.5))
+            .unwrap();
+        let logged = read_trade_log(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Then
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged, order_book.trades());
+        assert!(order_book.take_trade_log_error().is_none());
+    }
+}
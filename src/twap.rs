@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+
+use uuid::Uuid;
+
+use crate::order_book::{Match, Order, OrderBook, OrderBookError, OrderType};
+
+// Splits the total into `slices` sizes that are whole multiples of the lot size and differ
+// by at most one lot, the first slices taking the remainder. Slices can be zero when the
+// total has fewer lots than there are slices.
+pub fn twap_schedule(total_size: f64, slices: usize, lot_size: f64) -> Vec<f64> {
+    if slices == 0 {
+        return vec![];
+    }
+    let lots = (total_size / lot_size).round() as u64;
+    let (per_slice, remainder) = (lots / slices as u64, lots % slices as u64);
+    (0..slices as u64)
+        .map(|i| {
+            let slice_lots = per_slice + u64::from(i < remainder);
+            slice_lots as f64 * lot_size
+        })
+        .collect()
+}
+
+// Works a large order off as a series of market orders, one slice per tick
+pub struct TwapExecutor {
+    side: OrderType,
+    owner: Uuid,
+    slices: VecDeque<f64>,
+}
+
+impl TwapExecutor {
+    pub fn new(
+        order_book: &OrderBook,
+        side: OrderType,
+        owner: Uuid,
+        total_size: f64,
+        slices: usize,
+    ) -> Self {
+        let lot_size = order_book.config().lot_size;
+        Self {
+            side,
+            owner,
+            slices: twap_schedule(total_size, slices, lot_size).into(),
+        }
+    }
+
+    pub fn remaining_slices(&self) -> usize {
+        self.slices.len()
+    }
+
+    // Sends the next slice to the book, None once the schedule is done. Empty slices are
+    // ticks the executor sits out.
+    pub fn tick(
+        &mut self,
+        order_book: &mut OrderBook,
+    ) -> Option<Result<Vec<Match>, OrderBookError>> {
+        let size = self.slices.pop_front()?;
+        if size <= 0.0 {
+            return Some(Ok(vec![]));
+        }
+        Some(order_book.place_market_order(Order::new(self.side, size).with_owner(self.owner)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use uuid::Uuid;
+
+    use crate::order_book::{Order, OrderBook, OrderType};
+    use crate::twap::{TwapExecutor, twap_schedule};
+
+    #[test]
+    fn successfully_splits_a_total_into_slices_at_most_one_lot_apart() {
+        // Given
+        let lot_size = 0.1;
+
+        // When
+        let schedule = twap_schedule(10.0, 3, lot_size);
+
+        // Then
+        assert_eq!(schedule.len(), 3);
+        assert!((schedule.iter().sum::<f64>() - 10.0).abs() < 1e-9);
+        let largest = schedule.iter().cloned().fold(f64::MIN, f64::max);
+        let smallest = schedule.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(largest - smallest <= lot_size + 1e-9);
+    }
+
+    #[test]
+    fn successfully_executes_one_slice_per_tick() {
+        // Given
+        let mut order_book = OrderBook::new();
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 10.0))
+            .unwrap();
+        let owner = Uuid::new_v4();
+        let mut executor = TwapExecutor::new(&order_book, OrderType::Bid, owner, 4.0, 4);
+
+        // When
+        let first = executor.tick(&mut order_book).unwrap().unwrap();
+        while let Some(result) = executor.tick(&mut order_book) {
+            result.unwrap();
+        }
+
+        // Then
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].size_filled, 1.0);
+        assert_eq!(executor.remaining_slices(), 0);
+        assert_eq!(order_book.taker_volume(owner), 4.0);
+        assert_eq!(order_book.total_volume(OrderType::Ask), 6.0);
+    }
+}
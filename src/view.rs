@@ -0,0 +1,77 @@
+use crate::order_book::{OrderBook, PriceLevel};
+
+// Bumped whenever a field is renamed or removed so frontends can tell what they got
+const VIEW_SCHEMA_VERSION: u32 = 1;
+
+fn levels_json(levels: &[PriceLevel]) -> String {
+    let levels: Vec<String> = levels
+        .iter()
+        .map(|(price, size)| format!("{{\"price\":{price},\"size\":{size}}}"))
+        .collect();
+    format!("[{}]", levels.join(","))
+}
+
+fn optional_json(value: Option<f64>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+impl OrderBook {
+    // The top `levels` of each side as a compact JSON document for a web frontend, best
+    // price first. Kept separate from any full representation of the book so the frontend
+    // only breaks when the schema version changes. Spread and mid are null for a one-sided
+    // book.
+    pub fn to_view_json(&self, levels: usize) -> String {
+        let (bids, asks) = self.depth(levels);
+        let spread = self
+            .touch()
+            .map(|(best_bid, _, best_ask, _)| best_ask - best_bid);
+        format!(
+            "{{\"schema_version\":{},\"timestamp\":{},\"bids\":{},\"asks\":{},\"spread\":{},\"mid\":{}}}",
+            VIEW_SCHEMA_VERSION,
+            self.now(),
+            levels_json(&bids),
+            levels_json(&asks),
+            optional_json(spread),
+            optional_json(self.mid_price())
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use crate::clock::ManualClock;
+    use crate::order_book::{Order, OrderBook, OrderType};
+
+    #[test]
+    fn successfully_exports_the_top_of_the_book_as_view_json() {
+        // Given
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut order_book = OrderBook::new().with_clock(clock);
+        for price in [99.0, 98.5, 98.0] {
+            order_book
+                .add_order(price, Order::new(OrderType::Bid, 1.0))
+                .unwrap();
+        }
+        order_book
+            .add_order(101.0, Order::new(OrderType::Ask, 2.5))
+            .unwrap();
+
+        // When
+        let json = order_book.to_view_json(2);
+        let one_sided = OrderBook::new().to_view_json(2);
+
+        // Then
+        assert_eq!(
+            json,
+            concat!(
+                "{\"schema_version\":1,\"timestamp\":1000,",
+                "\"bids\":[{\"price\":99,\"size\":1},{\"price\":98.5,\"size\":1}],",
+                "\"asks\":[{\"price\":101,\"size\":2.5}],",
+                "\"spread\":2,\"mid\":100}"
+            )
+        );
+        assert!(one_sided.contains("\"bids\":[],\"asks\":[],\"spread\":null,\"mid\":null"));
+    }
+}